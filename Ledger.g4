@@ -5,15 +5,29 @@ CRDT-friendly PTA (hledger/ledger-like) with rich crypto lot annotations.
 
 Key ideas:
 - Transaction header includes meta comment; semantic pass enforces txn:<ID>.
-- Each posting has explicit amount.
+- Each posting has an explicit amount, except at most one per transaction whose amount is
+  elided and inferred from the others (split per commodity when the residual spans several).
 - Optional cost annotations:
     { ... }   per-unit cost + optional metadata
     {{ ... }} total cost + optional metadata
 - Optional price annotations:
     @  <amount>   per-unit price
     @@ <amount>   total price
+- Optional balance assertion:
+    = <amount>    account's running balance (date order, all files) after the posting
+- Commodities may be declared with a `commodity` block (precision, symbol, name, kind).
+- Market prices are recorded with `price` directives; `@`/`@@` annotations also feed the
+  price database.
+- Accounts are typed by root (assets, liabilities, equity, income, expenses); a `type:<TYPE>`
+  tag on an account declaration overrides the root for it and its sub-accounts.
 - Lot annotation content is structured: comma-separated fields.
   Fields can be positional or key:value.
+- Positive postings with a cost annotation acquire lots; negative ones reduce the lots they
+  select (lot:<ID>, date, label, cost) using the annotation's method: (strict, fifo, lifo,
+  hifo, average), else the account declaration's method: tag, else FIFO.
+- A fee:<amount> lot field implies a posting of the fee to fee_to:<account> (default
+  expenses:fees) unless the transaction writes one; under the capitalize policy a fee in the
+  cost or price commodity is added to the lot's cost basis or deducted from sale proceeds.
 
 Examples:
 2026-01-15 * "Binance" "Buy SOL" ; txn:01J2N9R9..., src:binance:order:999
@@ -27,13 +41,41 @@ Examples:
 */
 
 file
-  : (blankLine | directive | accountDeclaration | transaction)* EOF
+  : (blankLine | directive | optionDirective | commodityDeclaration | priceDirective
+    | accountDeclaration | transaction)* EOF
   ;
 
 transaction
   : headerLine postingLine+ blankLine*
   ;
 
+// `option strict`: postings may only use declared accounts and commodities.
+optionDirective
+  : 'option' WS+ 'strict' WS* NEWLINE
+  ;
+
+// Market price of one unit of the first commodity, e.g. `price 2026-01-15 BTC 98000.00 AUD`.
+priceDirective
+  : 'price' WS+ DATETIME WS+ commodity WS+ amountSpec WS* metaComment? NEWLINE
+  ;
+
+commodityDeclaration
+  : 'commodity' WS+ commodity WS* metaComment? NEWLINE
+    commodityDeclLine* blankLine*
+  ;
+
+commodityDeclLine
+  : INDENT commodityDeclBody WS* metaComment? NEWLINE
+  ;
+
+// precision: display decimal places (and the most allowed on postings in strict mode).
+commodityDeclBody
+  : 'precision' WS+ NUMBER
+  | 'symbol' WS+ (QUOTED | IDENT)
+  | 'name' WS+ (QUOTED | IDENT)
+  | 'kind' WS+ ('fiat' | 'crypto' | 'stock')
+  ;
+
 accountDeclaration
   : 'account' WS+ account (WS+ commodity)? WS* metaComment? NEWLINE
     accountDeclLine* blankLine*
@@ -43,8 +85,12 @@ accountDeclLine
   : INDENT accountDeclBody WS* metaComment? NEWLINE
   ;
 
+// open/close take a plain date; postings dated before `open` or after `close` are rejected.
+// At most one opening per commodity; a dated opening applies from that day.
 accountDeclBody
-  : 'opening' WS+ signedNumber (WS+ commodity)?
+  : 'opening' WS+ (DATETIME WS+)? signedNumber (WS+ commodity)?
+  | 'open' WS+ DATETIME
+  | 'close' WS+ DATETIME
   ;
 
 headerLine
@@ -55,7 +101,9 @@ postingLine
   : INDENT account WS+ amountSpec WS*
     lotCostSpec? WS*
     priceSpec? WS*
+    balanceAssertion? WS*
     metaComment? NEWLINE
+  | INDENT account WS* metaComment? NEWLINE
   ;
 
 directive
@@ -220,6 +268,15 @@ priceTotal
   : '@@' WS* amountSpec
   ;
 
+/* ----- Balance assertions ----- */
+
+/*
+  assets:CBA:smartaccess  -50.00 AUD = 1234.56 AUD
+*/
+balanceAssertion
+  : '=' WS* amountSpec
+  ;
+
 /* ----- Lexer ----- */
 
 /*
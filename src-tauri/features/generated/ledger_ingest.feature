@@ -13,3 +13,16 @@ Feature: Generated ledger ingestion
     Then the active ledger should include payee "Manual"
     And the active ledger should include meta tag "txn:"
 
+  Scenario: Importing keeps posting meta comments
+    Given a clean generated ledger directory
+    And a copy of fixture "posting_meta.transactions" as a source file
+    When I import that source file into the generated ledger for month "202601"
+    Then the active ledger should include meta tag "@@ 160.00 USD ; fill:partial"
+    And the active ledger should include meta tag '; note:"settled T+1"'
+
+  Scenario: Importing carries commodity declarations into the generated ledger
+    Given a clean generated ledger directory
+    And a copy of fixture "commodities.transactions" as a source file
+    When I import that source file into the generated ledger for month "202601"
+    Then the active ledger should include meta tag 'commodity SOL ; note:"Solana"'
+    And the active ledger should include meta tag "    precision 4"
@@ -26,4 +26,51 @@ Feature: Parse .transactions files
     Given a transactions file named "account_opening.transactions"
     When I run the ledger parser on that file
     Then the parse should succeed
-    And the balance for account "assets:CBA:smartaccess" should be "100.0" "AUD"
+    And the balance for account "assets:CBA:smartaccess" should be "100.00" "AUD"
+
+  Scenario: Unbalanced transaction reports its residual
+    Given a transactions file named "unbalanced.transactions"
+    When I run the ledger parser on that file
+    Then the parse should fail
+    And diagnostics should include "transaction does not balance (residual: -0.10 USD)"
+
+  Scenario: Failed balance assertion reports expected and actual balances
+    Given a transactions file named "balance_assertion.transactions"
+    When I run the ledger parser on that file
+    Then the parse should fail
+    And diagnostics should include "balance assertion failed for assets:CBA:smartaccess: expected 40.00 AUD, actual 45.00 AUD"
+
+  Scenario: Elided posting amount is inferred from the other postings
+    Given a transactions file named "elided_amount.transactions"
+    When I run the ledger parser on that file
+    Then the parse should succeed
+    And the balance for account "assets:exchange:binance:usd" should be "-230.00" "USD"
+    And the balance for account "assets:CBA:smartaccess" should be "-42.50" "AUD"
+
+  Scenario: Declared commodity precision rounds account balances
+    Given a transactions file named "commodities.transactions"
+    When I run the ledger parser on that file
+    Then the parse should succeed
+    And the balance for account "assets:exchange:binance:sol" should be "10.1235" "SOL"
+    And the balance for account "assets:cash:usd" should be "-230.00" "USD"
+
+  Scenario: Strict mode suggests declared accounts for typos
+    Given a transactions file named "strict_typo.transactions"
+    When I run the ledger parser on that file
+    Then the parse should fail
+    And diagnostics should include "undeclared account: assets:CBA:smartacess (did you mean assets:CBA:smartaccess?)"
+
+  Scenario: Accounts can open with balances in several commodities
+    Given a transactions file named "multi_opening.transactions"
+    When I run the ledger parser on that file
+    Then the parse should succeed
+    And the balance for account "assets:exchange:binance" should be "250.00" "USD"
+    And the balance for account "assets:exchange:binance" should be "1.500000" "SOL"
+    And the balance for account "assets:exchange:binance" should be "0.01000000" "BTC"
+
+  Scenario: Every account must map to an account type
+    Given a transactions file named "account_types.transactions"
+    When I run the ledger parser on that file
+    Then the parse should fail
+    And diagnostics should include "account revenue:bonus has no account type: unknown root revenue"
+    And diagnostics should include "liability account liabilities:amex goes negative on 2026-01-20: -17.50 AUD"
@@ -0,0 +1,323 @@
+use crate::amount::Amount;
+use crate::ledger_parser::{CommodityAmount, ParseResult};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Classification of an account, which fixes its normal balance sign.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountType {
+  Asset,
+  Liability,
+  Equity,
+  Income,
+  Expense,
+}
+
+impl AccountType {
+  pub const ALL: [AccountType; 5] = [
+    AccountType::Asset,
+    AccountType::Liability,
+    AccountType::Equity,
+    AccountType::Income,
+    AccountType::Expense,
+  ];
+
+  /// Parses a `type:` tag value. Plural forms (`assets`, `liabilities`, ...) are accepted too.
+  pub fn parse(name: &str) -> Option<Self> {
+    match name.trim().to_ascii_lowercase().as_str() {
+      "asset" | "assets" => Some(Self::Asset),
+      "liability" | "liabilities" => Some(Self::Liability),
+      "equity" => Some(Self::Equity),
+      "income" | "revenue" => Some(Self::Income),
+      "expense" | "expenses" => Some(Self::Expense),
+      _ => None,
+    }
+  }
+
+  pub fn as_str(self) -> &'static str {
+    match self {
+      Self::Asset => "asset",
+      Self::Liability => "liability",
+      Self::Equity => "equity",
+      Self::Income => "income",
+      Self::Expense => "expense",
+    }
+  }
+
+  /// Assets and expenses normally carry debit (positive) balances; liabilities, equity and
+  /// income normally carry credit (negative) balances.
+  pub fn is_debit_normal(self) -> bool {
+    matches!(self, Self::Asset | Self::Expense)
+  }
+
+  /// `amount` with the conventional sign for this type: credit-normal amounts are negated, so
+  /// money owed, earned or contributed reads as positive.
+  pub fn present(self, amount: Amount) -> Amount {
+    if self.is_debit_normal() {
+      amount
+    } else {
+      -amount
+    }
+  }
+
+  /// Whether a negative presented balance is suspicious: an asset below zero or a liability
+  /// that has been overpaid usually means a missing or misclassified entry.
+  pub fn expects_non_negative(self) -> bool {
+    matches!(self, Self::Asset | Self::Liability)
+  }
+}
+
+impl fmt::Display for AccountType {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+/// Maps account roots (the first path segment) to account types. The default maps `assets`,
+/// `liabilities`, `equity`, `income` and `expenses`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountTypes {
+  roots: BTreeMap<String, AccountType>,
+}
+
+impl Default for AccountTypes {
+  fn default() -> Self {
+    Self::empty()
+      .with_root("assets", AccountType::Asset)
+      .with_root("liabilities", AccountType::Liability)
+      .with_root("equity", AccountType::Equity)
+      .with_root("income", AccountType::Income)
+      .with_root("expenses", AccountType::Expense)
+  }
+}
+
+impl AccountTypes {
+  /// No roots: every account needs a `type:` tag on its own declaration or an ancestor's.
+  pub fn empty() -> Self {
+    Self {
+      roots: BTreeMap::new(),
+    }
+  }
+
+  pub fn with_root(mut self, root: impl Into<String>, account_type: AccountType) -> Self {
+    self.insert(root, account_type);
+    self
+  }
+
+  /// Maps `root` to `account_type`, replacing any previous mapping.
+  pub fn insert(&mut self, root: impl Into<String>, account_type: AccountType) {
+    self.roots.insert(root.into(), account_type);
+  }
+
+  pub fn roots(&self) -> impl Iterator<Item = (&str, AccountType)> {
+    self.roots.iter().map(|(root, account_type)| (root.as_str(), *account_type))
+  }
+
+  /// Type of `account`: the `type:` tag of its own declaration or of its nearest declared
+  /// ancestor (`tagged`), falling back to the type of its root.
+  pub fn classify(
+    &self,
+    account: &str,
+    tagged: &BTreeMap<&str, AccountType>,
+  ) -> Option<AccountType> {
+    let mut prefix = account;
+    loop {
+      if let Some(account_type) = tagged.get(prefix) {
+        return Some(*account_type);
+      }
+      match prefix.rfind(':') {
+        Some(pos) => prefix = &prefix[..pos],
+        None => return self.roots.get(prefix).copied(),
+      }
+    }
+  }
+}
+
+/// One account in the tree built by [`account_tree`].
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct AccountNode {
+  /// Full path, e.g. `assets:exchange`.
+  pub account: String,
+  /// Last path segment, e.g. `exchange`.
+  pub name: String,
+  /// Number of path segments; top-level accounts are at depth 1.
+  pub depth: usize,
+  /// For an implicit node, the type shared by all its children (if they agree).
+  pub account_type: Option<AccountType>,
+  /// The node only exists as the parent of other accounts: it has no postings, openings or
+  /// declaration of its own.
+  pub implicit: bool,
+  /// Balance of the account itself, per commodity.
+  pub own: Vec<CommodityAmount>,
+  /// Balance of the account and every sub-account, including those cut off by the depth limit.
+  pub total: Vec<CommodityAmount>,
+  /// Display text of `total` per commodity, using the declared symbol and precision.
+  pub display: BTreeMap<String, String>,
+  pub children: Vec<AccountNode>,
+}
+
+fn sorted_amounts(by_commodity: BTreeMap<String, Amount>) -> Vec<CommodityAmount> {
+  by_commodity
+    .into_iter()
+    .map(|(commodity, amount)| CommodityAmount { commodity, amount })
+    .collect()
+}
+
+/// Builds the account hierarchy of the open accounts in `result`, adding implicit parents for
+/// every path prefix (`assets`, `assets:exchange`, ...) and rolling balances up. With
+/// `max_depth`, nodes deeper than that are folded into their ancestor's `total`. Fails when a
+/// rolled-up total is out of range.
+pub fn account_tree(
+  result: &ParseResult,
+  max_depth: Option<usize>,
+) -> Result<Vec<AccountNode>, String> {
+  let mut own: BTreeMap<&str, &[CommodityAmount]> = BTreeMap::new();
+  let mut children: BTreeMap<Option<&str>, Vec<&str>> = BTreeMap::new();
+  for balance in &result.balances {
+    own.insert(balance.account.as_str(), &balance.totals);
+  }
+  let mut paths: Vec<&str> = Vec::new();
+  for account in own.keys() {
+    let ends = account.match_indices(':').map(|(pos, _)| pos).chain([account.len()]);
+    paths.extend(ends.map(|end| &account[..end]));
+  }
+  paths.sort_unstable();
+  paths.dedup();
+  for path in paths {
+    let parent = path.rfind(':').map(|pos| &path[..pos]);
+    children.entry(parent).or_default().push(path);
+  }
+
+  fn build(
+    path: &str,
+    depth: usize,
+    own: &BTreeMap<&str, &[CommodityAmount]>,
+    children: &BTreeMap<Option<&str>, Vec<&str>>,
+    result: &ParseResult,
+    max_depth: Option<usize>,
+  ) -> Result<AccountNode, String> {
+    let nodes: Vec<AccountNode> = children
+      .get(&Some(path))
+      .into_iter()
+      .flatten()
+      .map(|child| build(child, depth + 1, own, children, result, max_depth))
+      .collect::<Result<_, _>>()?;
+
+    let implicit = !own.contains_key(path);
+    let own_totals = own.get(path).copied().unwrap_or_default();
+    let mut total: BTreeMap<String, Amount> = BTreeMap::new();
+    for amount in own_totals.iter().chain(nodes.iter().flat_map(|n| &n.total)) {
+      let entry = total.entry(amount.commodity.clone()).or_default();
+      *entry = entry
+        .checked_add(&amount.amount)
+        .ok_or_else(|| format!("total of {path} out of range in {}", amount.commodity))?;
+    }
+    let display = total
+      .iter()
+      .map(|(commodity, amount)| (commodity.clone(), result.format_amount(commodity, *amount)))
+      .collect();
+
+    let account_type = match result.account_types.get(path) {
+      Some(account_type) => Some(*account_type),
+      None if implicit => {
+        let mut types = nodes.iter().map(|n| n.account_type);
+        let first = types.next().flatten();
+        first.filter(|t| types.all(|other| other == Some(*t)))
+      }
+      None => None,
+    };
+
+    Ok(AccountNode {
+      account: path.to_string(),
+      name: path.rsplit(':').next().unwrap_or(path).to_string(),
+      depth,
+      account_type,
+      implicit,
+      own: own_totals.to_vec(),
+      total: sorted_amounts(total),
+      display,
+      children: if max_depth.is_some_and(|max| depth >= max) { Vec::new() } else { nodes },
+    })
+  }
+
+  children
+    .get(&None)
+    .into_iter()
+    .flatten()
+    .map(|root| build(root, 1, &own, &children, result, max_depth))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ledger_parser::parse_transactions;
+
+  #[test]
+  fn classifies_by_declaration_tag_then_root() {
+    let types = AccountTypes::default().with_root("revenue", AccountType::Income);
+    let tagged = BTreeMap::from([("assets:loans", AccountType::Liability)]);
+
+    assert_eq!(types.classify("assets:cash", &tagged), Some(AccountType::Asset));
+    assert_eq!(types.classify("assets:loans:car", &tagged), Some(AccountType::Liability));
+    assert_eq!(types.classify("revenue:salary", &tagged), Some(AccountType::Income));
+    assert_eq!(types.classify("asset:cash", &tagged), None);
+    assert_eq!(AccountTypes::empty().classify("assets:cash", &BTreeMap::new()), None);
+  }
+
+  #[test]
+  fn presents_credit_normal_amounts_as_positive() {
+    let amount: Amount = "-120.50".parse().unwrap();
+    assert_eq!(AccountType::Asset.present(amount).to_string(), "-120.50");
+    assert_eq!(AccountType::Liability.present(amount).to_string(), "120.50");
+    assert_eq!(AccountType::Income.present(amount).to_string(), "120.50");
+    assert_eq!(AccountType::parse("Liabilities"), Some(AccountType::Liability));
+    assert_eq!(AccountType::parse("cash"), None);
+  }
+
+  #[test]
+  fn rolls_balances_up_the_account_tree() {
+    let result = parse_transactions(
+      r#"commodity USD
+    symbol "$"
+    precision 2
+
+account assets:exchange:binance
+    opening 500.00 USD
+
+2026-01-15 * "Binance" "Buy SOL" ; txn:01J2N9R9
+    assets:exchange:binance:sol    2 SOL { 200.00 USD }
+    assets:exchange:binance       -400.00 USD
+
+2026-01-16 * "Kraken" "Deposit" ; txn:01J2N9RA
+    assets:exchange:kraken:usd     100.00 USD
+    income:interest               -100.00 USD
+"#,
+    );
+    assert!(result.ok, "{:?}", result.diagnostics);
+
+    let tree = account_tree(&result, None).unwrap();
+    let roots: Vec<&str> = tree.iter().map(|n| n.account.as_str()).collect();
+    assert_eq!(roots, ["assets", "income"]);
+
+    let assets = &tree[0];
+    assert!(assets.implicit);
+    assert_eq!(assets.account_type, Some(AccountType::Asset));
+    assert!(assets.own.is_empty());
+    assert_eq!(assets.display["USD"], "$200.00");
+    assert_eq!(assets.display["SOL"], "2 SOL");
+
+    let exchange = &assets.children[0];
+    assert_eq!((exchange.name.as_str(), exchange.depth), ("exchange", 2));
+    let binance = &exchange.children[0];
+    assert!(!binance.implicit);
+    assert_eq!(binance.own[0].amount.to_string(), "100.00");
+    assert_eq!(binance.children[0].account, "assets:exchange:binance:sol");
+
+    let shallow = account_tree(&result, Some(2)).unwrap();
+    assert!(shallow[0].children[0].children.is_empty());
+    assert_eq!(shallow[0].children[0].display, assets.display);
+  }
+}
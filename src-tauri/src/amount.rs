@@ -0,0 +1,420 @@
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+/// Largest number of fractional digits an [`Amount`] will carry.
+pub const MAX_SCALE: u32 = 18;
+
+/// Exact fixed-point decimal: `units / 10^scale`.
+///
+/// The scale is preserved from the source text (`100.00` keeps two digits) and grows to the
+/// larger of both operands on addition, so sums never lose the precision the ledger was written
+/// with. Equality and ordering compare numeric value, so `100.0 == 100.00`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Amount {
+  units: i128,
+  scale: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AmountError {
+  #[error("invalid amount: {0}")]
+  Invalid(String),
+  #[error("amount has too many decimal places: {0}")]
+  TooPrecise(String),
+  #[error("amount out of range: {0}")]
+  Overflow(String),
+}
+
+fn pow10(exp: u32) -> i128 {
+  10i128.pow(exp)
+}
+
+/// Integer division rounding half away from zero.
+fn div_round(numerator: i128, denominator: i128) -> i128 {
+  let quotient = numerator / denominator;
+  let remainder = numerator % denominator;
+  if remainder.abs() * 2 >= denominator.abs() {
+    if (numerator < 0) != (denominator < 0) {
+      quotient - 1
+    } else {
+      quotient + 1
+    }
+  } else {
+    quotient
+  }
+}
+
+impl Amount {
+  pub const ZERO: Amount = Amount { units: 0, scale: 0 };
+
+  pub fn new(units: i128, scale: u32) -> Self {
+    Self { units, scale }
+  }
+
+  pub fn from_int(value: i64) -> Self {
+    Self {
+      units: value as i128,
+      scale: 0,
+    }
+  }
+
+  /// Number of fractional digits carried by this amount.
+  pub fn scale(&self) -> u32 {
+    self.scale
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.units == 0
+  }
+
+  pub fn is_negative(&self) -> bool {
+    self.units < 0
+  }
+
+  pub fn is_positive(&self) -> bool {
+    self.units > 0
+  }
+
+  pub fn abs(&self) -> Self {
+    Self {
+      units: self.units.abs(),
+      scale: self.scale,
+    }
+  }
+
+  /// Returns the same value expressed with at least `scale` fractional digits. Never drops
+  /// digits; use [`Amount::round_dp`] to reduce precision.
+  ///
+  /// Panics if the padded value is out of range (see [`Amount::checked_rescale`]).
+  pub fn with_min_scale(&self, scale: u32) -> Self {
+    self.checked_rescale(scale.max(self.scale)).expect("amount out of range")
+  }
+
+  /// Rounds (half away from zero) or pads to exactly `scale` fractional digits.
+  ///
+  /// Panics if the padded value is out of range (see [`Amount::checked_rescale`]).
+  pub fn round_dp(&self, scale: u32) -> Self {
+    self.checked_rescale(scale).expect("amount out of range")
+  }
+
+  /// [`Amount::round_dp`], returning `None` when padding to `scale` digits overflows.
+  pub fn checked_rescale(&self, scale: u32) -> Option<Self> {
+    let units = if scale >= self.scale {
+      self.units.checked_mul(10i128.checked_pow(scale - self.scale)?)?
+    } else {
+      div_round(self.units, pow10(self.scale - scale))
+    };
+    Some(Self { units, scale })
+  }
+
+  /// Exact sum at the larger of both scales; `None` when out of range.
+  pub fn checked_add(&self, rhs: &Amount) -> Option<Self> {
+    let scale = self.scale.max(rhs.scale);
+    let (a, b) = (self.checked_rescale(scale)?, rhs.checked_rescale(scale)?);
+    Some(Self {
+      units: a.units.checked_add(b.units)?,
+      scale,
+    })
+  }
+
+  pub fn checked_sub(&self, rhs: &Amount) -> Option<Self> {
+    let negated = Self {
+      units: rhs.units.checked_neg()?,
+      scale: rhs.scale,
+    };
+    self.checked_add(&negated)
+  }
+
+  /// Exact product carrying the sum of both scales (capped at [`MAX_SCALE`]); `None` when out
+  /// of range.
+  ///
+  /// Trailing zeros carry no value, so an 18-digit amount times an 18-digit amount still fits as
+  /// long as the digits that matter do: `1.000000000000000000 * 3000.000000000000000000` is
+  /// computed as `1 * 3000` and padded back.
+  pub fn checked_mul(&self, rhs: &Amount) -> Option<Self> {
+    let scale = self.scale + rhs.scale;
+    let product = match self.units.checked_mul(rhs.units) {
+      Some(units) => Self { units, scale },
+      None => {
+        let (a, b) = (self.normalize(), rhs.normalize());
+        let exact = Self {
+          units: a.units.checked_mul(b.units)?,
+          scale: a.scale + b.scale,
+        };
+        return Some(exact.checked_rescale(scale.min(MAX_SCALE)).unwrap_or(exact));
+      }
+    };
+    if product.scale > MAX_SCALE {
+      product.checked_rescale(MAX_SCALE)
+    } else {
+      Some(product)
+    }
+  }
+
+  /// Drops trailing fractional zeros (`1.5000` becomes `1.5`).
+  pub fn normalize(&self) -> Self {
+    let mut out = *self;
+    while out.scale > 0 && out.units % 10 == 0 {
+      out.units /= 10;
+      out.scale -= 1;
+    }
+    out
+  }
+
+  /// Divides by `rhs`, rounding the quotient to `scale` fractional digits.
+  /// Returns `None` when dividing by zero.
+  pub fn checked_div(&self, rhs: &Amount, scale: u32) -> Option<Self> {
+    if rhs.units == 0 {
+      return None;
+    }
+    // self / rhs * 10^scale == self.units * 10^(scale + rhs.scale - self.scale) / rhs.units
+    let exp = scale as i64 + rhs.scale as i64 - self.scale as i64;
+    let (numerator, denominator) = if exp >= 0 {
+      (self.units.checked_mul(pow10(exp as u32))?, rhs.units)
+    } else {
+      (self.units, rhs.units.checked_mul(pow10((-exp) as u32))?)
+    };
+    Some(Self {
+      units: div_round(numerator, denominator),
+      scale,
+    })
+  }
+}
+
+impl FromStr for Amount {
+  type Err = AmountError;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    let trimmed = text.trim();
+    let (negative, digits) = match trimmed.as_bytes().first() {
+      Some(b'-') => (true, &trimmed[1..]),
+      Some(b'+') => (false, &trimmed[1..]),
+      _ => (false, trimmed),
+    };
+
+    let (int_part, frac_part) = match digits.split_once('.') {
+      Some((i, f)) => (i, f),
+      None => (digits, ""),
+    };
+    let valid = !int_part.is_empty()
+      && int_part.bytes().all(|b| b.is_ascii_digit())
+      && (!digits.contains('.') || !frac_part.is_empty())
+      && frac_part.bytes().all(|b| b.is_ascii_digit());
+    if !valid {
+      return Err(AmountError::Invalid(text.to_string()));
+    }
+
+    let scale = frac_part.len() as u32;
+    if scale > MAX_SCALE {
+      return Err(AmountError::TooPrecise(text.to_string()));
+    }
+
+    let mut units: i128 = 0;
+    for b in int_part.bytes().chain(frac_part.bytes()) {
+      units = units
+        .checked_mul(10)
+        .and_then(|u| u.checked_add((b - b'0') as i128))
+        .ok_or_else(|| AmountError::Overflow(text.to_string()))?;
+    }
+
+    Ok(Self {
+      units: if negative { -units } else { units },
+      scale,
+    })
+  }
+}
+
+impl fmt::Display for Amount {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let sign = if self.units < 0 { "-" } else { "" };
+    let magnitude = self.units.unsigned_abs();
+    if self.scale == 0 {
+      return write!(f, "{sign}{magnitude}");
+    }
+    let divisor = 10u128.pow(self.scale);
+    write!(
+      f,
+      "{sign}{}.{:0width$}",
+      magnitude / divisor,
+      magnitude % divisor,
+      width = self.scale as usize
+    )
+  }
+}
+
+impl PartialEq for Amount {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl Eq for Amount {}
+
+impl PartialOrd for Amount {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Amount {
+  fn cmp(&self, other: &Self) -> Ordering {
+    let scale = self.scale.max(other.scale);
+    match (self.checked_rescale(scale), other.checked_rescale(scale)) {
+      (Some(a), Some(b)) => a.units.cmp(&b.units),
+      // A side that can't be padded to the shared scale is larger in magnitude than any value
+      // the other side can hold at it.
+      (None, _) => self.units.cmp(&0),
+      (_, None) => 0.cmp(&other.units),
+    }
+  }
+}
+
+/// Panics when out of range; ledger input goes through [`Amount::checked_add`].
+impl Add for Amount {
+  type Output = Amount;
+
+  fn add(self, rhs: Amount) -> Amount {
+    self.checked_add(&rhs).expect("amount out of range")
+  }
+}
+
+impl AddAssign for Amount {
+  fn add_assign(&mut self, rhs: Amount) {
+    *self = *self + rhs;
+  }
+}
+
+impl Sub for Amount {
+  type Output = Amount;
+
+  fn sub(self, rhs: Amount) -> Amount {
+    self + (-rhs)
+  }
+}
+
+impl SubAssign for Amount {
+  fn sub_assign(&mut self, rhs: Amount) {
+    *self = *self - rhs;
+  }
+}
+
+impl Neg for Amount {
+  type Output = Amount;
+
+  fn neg(self) -> Amount {
+    Amount {
+      units: -self.units,
+      scale: self.scale,
+    }
+  }
+}
+
+/// Exact product (see [`Amount::checked_mul`]); panics when out of range.
+impl Mul for Amount {
+  type Output = Amount;
+
+  fn mul(self, rhs: Amount) -> Amount {
+    self.checked_mul(&rhs).expect("amount out of range")
+  }
+}
+
+impl Sum for Amount {
+  fn sum<I: Iterator<Item = Amount>>(iter: I) -> Amount {
+    iter.fold(Amount::ZERO, |acc, a| acc + a)
+  }
+}
+
+impl Serialize for Amount {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(self)
+  }
+}
+
+struct AmountVisitor;
+
+impl Visitor<'_> for AmountVisitor {
+  type Value = Amount;
+
+  fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("a decimal amount as a string or number")
+  }
+
+  fn visit_str<E: de::Error>(self, v: &str) -> Result<Amount, E> {
+    v.parse().map_err(E::custom)
+  }
+
+  fn visit_i64<E: de::Error>(self, v: i64) -> Result<Amount, E> {
+    Ok(Amount::from_int(v))
+  }
+
+  fn visit_u64<E: de::Error>(self, v: u64) -> Result<Amount, E> {
+    Ok(Amount::new(v as i128, 0))
+  }
+
+  fn visit_f64<E: de::Error>(self, v: f64) -> Result<Amount, E> {
+    v.to_string().parse().map_err(E::custom)
+  }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Amount, D::Error> {
+    deserializer.deserialize_any(AmountVisitor)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn amt(s: &str) -> Amount {
+    s.parse().expect("valid amount")
+  }
+
+  #[test]
+  fn preserves_scale_and_sums_exactly() {
+    let total: Amount = ["0.1", "0.2", "-0.005000"].iter().map(|s| amt(s)).sum();
+    assert_eq!(total.to_string(), "0.295000");
+    assert_eq!(amt("100.00").to_string(), "100.00");
+    assert_eq!(amt("100.0"), amt("100.00"));
+  }
+
+  #[test]
+  fn rounds_half_away_from_zero() {
+    assert_eq!(amt("1.005").round_dp(2).to_string(), "1.01");
+    assert_eq!(amt("-1.005").round_dp(2).to_string(), "-1.01");
+    assert_eq!(amt("160.00").checked_div(&amt("0.005000"), 2).unwrap().to_string(), "32000.00");
+  }
+
+  #[test]
+  fn checks_arithmetic_at_max_scale() {
+    let cost = amt("3000.000000000000000000");
+    let units = amt("1.000000000000000000");
+    assert_eq!(cost.checked_mul(&units).unwrap().to_string(), "3000.000000000000000000");
+    assert_eq!((cost * units).to_string(), "3000.000000000000000000");
+
+    let many = amt("12345678901234567890.123456789012345678");
+    assert_eq!(many.checked_mul(&many), None);
+    let twice = many.checked_add(&many).unwrap();
+    assert_eq!(twice.to_string(), "24691357802469135780.246913578024691356");
+    let most = Amount::new(i128::MAX, MAX_SCALE);
+    assert_eq!(most.checked_add(&amt("0.000000000000000001")), None);
+    assert_eq!(most.checked_sub(&most), Some(Amount::ZERO));
+    assert_eq!(amt("1").checked_rescale(MAX_SCALE + 30), None);
+
+    // Comparing across scales never overflows.
+    let whole = amt("170141183460469231731687303715884105727");
+    assert!(whole > most && -whole < most && whole != amt("0.5"));
+  }
+
+  #[test]
+  fn rejects_malformed_text() {
+    for bad in ["", "-", "1.", ".5", "1e5", "1,000"] {
+      assert!(bad.parse::<Amount>().is_err(), "expected {bad:?} to be rejected");
+    }
+  }
+}
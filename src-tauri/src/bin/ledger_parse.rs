@@ -1,36 +1,187 @@
-use std::path::PathBuf;
+use squirrel_covid::accounts::AccountType;
+use squirrel_covid::ledger_parser::{
+  parse_sources, parse_transactions_with_options, Diagnostic, FeePolicy, LedgerSource,
+  ParseOptions, TxnIdSyntax,
+};
+use squirrel_covid::gains::realized_gains;
+use squirrel_covid::lots::{lot_inventory, BookingMethod};
+use squirrel_covid::query::{parse_query, run_query};
+use squirrel_covid::register::{register, RegisterQuery};
 
-use squirrel_covid::ledger_parser::parse_transactions;
+const USAGE: &str = "Usage: ledger-parse [--strict] [--strict-txn-ids[=ulid]] \
+[--account-root=<root>:<type>]... [--capitalize-fees] [--register=<account> [--subaccounts] \
+[--from=<date>] [--to=<date>]] [--query=<query>] [--lots[=<method>]] \
+[--gains[=<method>] [--from=<date>] [--to=<date>]] <file.transactions>...";
+
+fn print_diagnostic(diagnostic: &Diagnostic) {
+  let prefix = diagnostic
+    .file
+    .as_deref()
+    .map(|f| format!("{f}: "))
+    .unwrap_or_default();
+  let severity = if diagnostic.is_error() { "" } else { "warning: " };
+  eprintln!(
+    "{prefix}line {}, column {}: {severity}{}",
+    diagnostic.line, diagnostic.column, diagnostic.message
+  );
+}
+
+/// Prints a report error and exits with status 1.
+fn report_failed(error: String) -> ! {
+  eprintln!("Report failed: {error}");
+  std::process::exit(1);
+}
 
 fn main() {
-  let mut args = std::env::args().skip(1);
-  let Some(file_path) = args.next() else {
-    eprintln!("Usage: ledger-parse <file.transactions>");
+  let mut options = ParseOptions::default();
+  let mut query = RegisterQuery::default();
+  let mut filter = None;
+  let mut lots = None;
+  let mut gains = None;
+  let mut files: Vec<String> = Vec::new();
+  for arg in std::env::args().skip(1) {
+    match arg.as_str() {
+      "--strict" => options.strict = true,
+      "--strict-txn-ids" => options.require_txn_ids = true,
+      "--capitalize-fees" => options.fee_policy = FeePolicy::Capitalize,
+      "--strict-txn-ids=ulid" => {
+        options.require_txn_ids = true;
+        options.txn_id_syntax = TxnIdSyntax::Ulid;
+      }
+      "--subaccounts" => query.include_subaccounts = true,
+      flag if flag.starts_with("--register=") => {
+        query.account = flag["--register=".len()..].to_string();
+      }
+      flag if flag.starts_with("--from=") => query.from = Some(flag["--from=".len()..].to_string()),
+      flag if flag.starts_with("--to=") => query.to = Some(flag["--to=".len()..].to_string()),
+      flag if flag.starts_with("--query=") => match parse_query(&flag["--query=".len()..]) {
+        Ok(parsed) => filter = Some(parsed),
+        Err(e) => {
+          eprintln!("Invalid query: {e}");
+          std::process::exit(2);
+        }
+      },
+      "--lots" => lots = Some(BookingMethod::default()),
+      "--gains" => gains = Some(BookingMethod::default()),
+      flag if flag.starts_with("--lots=") || flag.starts_with("--gains=") => {
+        let (name, method) = flag.split_once('=').unwrap_or_default();
+        let Some(method) = BookingMethod::parse(method) else {
+          eprintln!(
+            "Unknown booking method: {method} (expected strict, fifo, lifo, hifo or average)"
+          );
+          std::process::exit(2);
+        };
+        if name == "--lots" {
+          lots = Some(method);
+        } else {
+          gains = Some(method);
+        }
+      }
+      flag if flag.starts_with("--account-root=") => {
+        let mapping = &flag["--account-root=".len()..];
+        let parsed = mapping
+          .split_once(':')
+          .and_then(|(root, name)| Some((root, AccountType::parse(name)?)));
+        let Some((root, account_type)) = parsed else {
+          eprintln!("Invalid account root mapping: {mapping} (expected <root>:<type>)");
+          std::process::exit(2);
+        };
+        options.account_types.insert(root, account_type);
+      }
+      flag if flag.starts_with("--") => {
+        eprintln!("Unknown option: {flag}");
+        eprintln!("{USAGE}");
+        std::process::exit(2);
+      }
+      _ => files.push(arg),
+    }
+  }
+
+  if files.is_empty() {
+    eprintln!("{USAGE}");
     std::process::exit(2);
-  };
+  }
 
-  let path = PathBuf::from(file_path);
-  let contents = match std::fs::read_to_string(&path) {
-    Ok(c) => c,
-    Err(e) => {
-      eprintln!("Failed to read file: {e}");
-      std::process::exit(2);
-    }
-  };
+  let mut sources = Vec::with_capacity(files.len());
+  for path in files {
+    let contents = match std::fs::read_to_string(&path) {
+      Ok(c) => c,
+      Err(e) => {
+        eprintln!("Failed to read file: {e}");
+        std::process::exit(2);
+      }
+    };
+    sources.push(LedgerSource { path, contents });
+  }
 
-  let result = parse_transactions(&contents);
+  // A single file keeps the historical `line N, column M` output without a path prefix.
+  let result = if sources.len() == 1 {
+    parse_transactions_with_options(&sources[0].contents, &options)
+  } else {
+    parse_sources(&sources, &options)
+  };
   if result.ok {
-    println!("OK");
+    if query.account.is_empty() && filter.is_none() && lots.is_none() && gains.is_none() {
+      println!("OK");
+    }
+    if let Some(filter) = &filter {
+      let found = run_query(&result, filter).unwrap_or_else(|e| report_failed(e));
+      for m in &found.matches {
+        let txn = &m.transaction;
+        println!("{} {}", txn.date, txn.payee.as_deref().unwrap_or(""));
+        for posting in m.postings.iter().map(|&i| &txn.postings[i]) {
+          println!("    {}  {} {}", posting.account, posting.amount, posting.commodity);
+        }
+      }
+      for total in &found.totals {
+        println!("total  {} {}", total.amount, total.commodity);
+      }
+    }
+    for row in register(&result, &query).unwrap_or_else(|e| report_failed(e)) {
+      let balance: Vec<String> =
+        row.balance.iter().map(|b| format!("{} {}", b.amount, b.commodity)).collect();
+      println!(
+        "{}  {}  {}  {} {}  {}",
+        row.date,
+        row.payee.as_deref().unwrap_or(""),
+        row.counter_accounts.join(", "),
+        row.amount,
+        row.commodity,
+        balance.join(", "),
+      );
+    }
+    result.diagnostics.iter().for_each(print_diagnostic);
+    if let Some(method) = lots {
+      let inventory = lot_inventory(&result, method);
+      for lot in &inventory.lots {
+        let cost = lot
+          .cost_basis
+          .as_ref()
+          .map(|c| format!("{} {}", c.amount, c.commodity))
+          .unwrap_or_else(|| "unknown cost".to_string());
+        println!(
+          "{}  {}  {}  {} {}  {}",
+          lot.account, lot.id, lot.acquired, lot.remaining, lot.commodity, cost
+        );
+      }
+      inventory.diagnostics.iter().for_each(print_diagnostic);
+      if inventory.diagnostics.iter().any(Diagnostic::is_error) {
+        std::process::exit(1);
+      }
+    }
+    if let Some(method) = gains {
+      let report = realized_gains(&result, method, query.from.as_deref(), query.to.as_deref());
+      print!("{}", report.to_csv());
+      report.diagnostics.iter().for_each(print_diagnostic);
+      if report.diagnostics.iter().any(Diagnostic::is_error) {
+        std::process::exit(1);
+      }
+    }
     std::process::exit(0);
   }
 
   eprintln!("Parse failed with diagnostics:");
-  for diagnostic in result.diagnostics {
-    eprintln!(
-      "line {}, column {}: {}",
-      diagnostic.line, diagnostic.column, diagnostic.message
-    );
-  }
+  result.diagnostics.iter().for_each(print_diagnostic);
 
   std::process::exit(1);
 }
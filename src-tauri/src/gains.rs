@@ -0,0 +1,550 @@
+use crate::amount::Amount;
+use crate::ledger_parser::{
+  net_of_fee, CommodityAmount, Diagnostic, ParseResult, Severity, Transaction,
+};
+use crate::lots::{lot_inventory, BookingMethod, LotMatch, LotReduction};
+use crate::periodic::{csv_field, parse_date};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Account (or parent of the accounts) whose postings record a trade's realized gain, as a
+/// credit, e.g. `income:trading:pnl  -10.00 USD` for a 10 USD gain.
+pub const PNL_ACCOUNT: &str = "income:trading:pnl";
+
+/// Units held for more than a year are long term.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Term {
+  Short,
+  Long,
+}
+
+/// Units of one lot sold by one posting.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Disposal {
+  /// Date sold.
+  pub date: String,
+  pub datetime: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub file: Option<String>,
+  /// 1-based line number of the selling posting.
+  pub line: usize,
+  pub txn_id: Option<String>,
+  pub account: String,
+  pub commodity: String,
+  pub quantity: Amount,
+  pub lot_id: Option<String>,
+  /// Acquisition date (or datetime) of the lot.
+  pub acquired: Option<String>,
+  /// Days from acquisition to sale.
+  pub holding_days: Option<i64>,
+  pub term: Option<Term>,
+  /// This lot's share of the posting's `@`/`@@` price. Proceeds and cost basis are rounded to
+  /// the declared precision of their commodity.
+  pub proceeds: CommodityAmount,
+  pub cost_basis: Option<CommodityAmount>,
+  /// Proceeds minus cost basis; `None` when the cost is unknown or in another commodity.
+  pub gain: Option<Amount>,
+}
+
+/// Sums of the disposals whose proceeds are in `currency`.
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct GainTotal {
+  pub currency: String,
+  pub proceeds: Amount,
+  pub cost_basis: Amount,
+  pub gain: Amount,
+  pub short_term: Amount,
+  pub long_term: Amount,
+}
+
+impl GainTotal {
+  /// Adds `disposal` in; `None` when a sum is out of range.
+  fn add(&mut self, disposal: &Disposal) -> Option<()> {
+    self.proceeds = self.proceeds.checked_add(&disposal.proceeds.amount)?;
+    if let (Some(cost), Some(gain)) = (&disposal.cost_basis, disposal.gain) {
+      self.cost_basis = self.cost_basis.checked_add(&cost.amount)?;
+      self.gain = self.gain.checked_add(&gain)?;
+      let term = match disposal.term {
+        Some(Term::Long) => &mut self.long_term,
+        _ => &mut self.short_term,
+      };
+      *term = term.checked_add(&gain)?;
+    }
+    Some(())
+  }
+}
+
+/// A transaction's computed gain compared with its [`PNL_ACCOUNT`] postings.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PnlCheck {
+  pub date: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub file: Option<String>,
+  /// 1-based line number of the transaction header.
+  pub line: usize,
+  pub txn_id: Option<String>,
+  pub currency: String,
+  pub computed: Amount,
+  /// Gain recorded by the postings (their negated sum).
+  pub recorded: Amount,
+  /// `computed`, rounded to the decimal places of `recorded`, equals `recorded`.
+  pub agrees: bool,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct RealizedGains {
+  pub from: Option<String>,
+  pub to: Option<String>,
+  /// Disposals in date order.
+  pub disposals: Vec<Disposal>,
+  pub totals: Vec<GainTotal>,
+  pub pnl_checks: Vec<PnlCheck>,
+  /// Lot matching problems, sales without proceeds or cost, and disagreeing pnl postings.
+  pub diagnostics: Vec<Diagnostic>,
+}
+
+impl RealizedGains {
+  /// One line per disposal, in the column order of a capital gains schedule (description,
+  /// acquired, sold, proceeds, cost basis, gain), amounts at their commodity's declared
+  /// precision.
+  pub fn to_csv(&self) -> String {
+    let mut out = String::from(
+      "description,date_acquired,date_sold,proceeds,cost_basis,gain,currency,term,holding_days,\
+       account,lot\n",
+    );
+    for d in &self.disposals {
+      let optional = |value: Option<String>| value.unwrap_or_default();
+      let fields = [
+        format!("{} {}", d.quantity, d.commodity),
+        optional(d.acquired.as_ref().map(|a| a.get(..10).unwrap_or(a).to_string())),
+        d.date.clone(),
+        d.proceeds.amount.to_string(),
+        optional(d.cost_basis.as_ref().map(|c| c.amount.to_string())),
+        optional(d.gain.map(|g| g.to_string())),
+        d.proceeds.commodity.clone(),
+        optional(d.term.map(|t| if t == Term::Long { "long" } else { "short" }.to_string())),
+        optional(d.holding_days.map(|days| days.to_string())),
+        d.account.clone(),
+        optional(d.lot_id.clone()),
+      ];
+      let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+      out.push_str(&fields.join(","));
+      out.push('\n');
+    }
+    out
+  }
+}
+
+fn diagnostic(
+  file: Option<&String>,
+  line: usize,
+  severity: Severity,
+  message: String,
+) -> Diagnostic {
+  Diagnostic {
+    line,
+    column: 0,
+    message,
+    file: file.cloned(),
+    severity,
+  }
+}
+
+/// Holding period of units acquired on `acquired` and sold on `sold`; more than a year (sold
+/// after the first anniversary) is long term.
+fn holding(acquired: &str, sold: &str) -> Option<(i64, Term)> {
+  let days = parse_date(sold)? - parse_date(acquired)?;
+  let year: i64 = acquired.get(..4)?.parse().ok()?;
+  let anniversary = format!("{:04}{}", year + 1, acquired.get(4..10)?);
+  let term = if sold.get(..10)? > anniversary.as_str() { Term::Long } else { Term::Short };
+  Some((days, term))
+}
+
+fn disposal(
+  reduction: &LotReduction,
+  taken: &LotMatch,
+  proceeds: CommodityAmount,
+  cost_basis: Option<CommodityAmount>,
+) -> Disposal {
+  let held = taken.acquired.as_deref().and_then(|a| holding(a, &reduction.date));
+  let gain = match &cost_basis {
+    Some(cost) if cost.commodity == proceeds.commodity => {
+      proceeds.amount.checked_sub(&cost.amount)
+    }
+    _ => None,
+  };
+  Disposal {
+    date: reduction.date.clone(),
+    datetime: reduction.datetime.clone(),
+    file: reduction.file.clone(),
+    line: reduction.line,
+    txn_id: reduction.txn_id.clone(),
+    account: reduction.account.clone(),
+    commodity: reduction.commodity.clone(),
+    quantity: taken.quantity,
+    lot_id: taken.lot_id.clone(),
+    acquired: taken.acquired.clone(),
+    holding_days: held.map(|(days, _)| days),
+    term: held.map(|(_, term)| term),
+    proceeds,
+    cost_basis,
+    gain,
+  }
+}
+
+/// Realized gains of every sale dated between `from` and `to` (inclusive dates, either
+/// open-ended), matching lots over the whole history with `method` (see [`lot_inventory`]).
+///
+/// Each sale's proceeds are the total of its `@`/`@@` price, less a capitalized `fee:`, split
+/// across the lots it consumed by quantity. Transactions with [`PNL_ACCOUNT`] postings are
+/// checked against the computed gain.
+pub fn realized_gains(
+  result: &ParseResult,
+  method: BookingMethod,
+  from: Option<&str>,
+  to: Option<&str>,
+) -> RealizedGains {
+  let inventory = lot_inventory(result, method);
+  let mut diagnostics = inventory.diagnostics;
+  let round = |amount: CommodityAmount| match result.commodity(&amount.commodity) {
+    Some(decl) => CommodityAmount {
+      amount: decl.round(amount.amount),
+      ..amount
+    },
+    None => amount,
+  };
+  let in_range = |date: &str| from.is_none_or(|f| date >= f) && to.is_none_or(|t| date <= t);
+
+  let mut disposals: Vec<Disposal> = Vec::new();
+  let sales = inventory
+    .reductions
+    .iter()
+    .filter(|r| r.transferred_to.is_none() && in_range(&r.date));
+  for reduction in sales {
+    let (quantity, commodity) = (reduction.quantity, &reduction.commodity);
+    let account = &reduction.account;
+    let report = |severity: Severity, message: String| {
+      diagnostic(reduction.file.as_ref(), reduction.line, severity, message)
+    };
+    let warn = |message: String| report(Severity::Warning, message);
+    let out_of_range = || {
+      let message = format!("gain on {quantity} {commodity} sold from {account} out of range");
+      report(Severity::Error, message)
+    };
+    let Some(price) = &reduction.price else {
+      let message = format!(
+        "sale of {quantity} {commodity} from {account} has no @/@@ price: proceeds unknown"
+      );
+      diagnostics.push(warn(message));
+      continue;
+    };
+
+    let net = match &reduction.fee {
+      Some(fee) => net_of_fee(price, fee, false).map_or(price.amount, |net| net.amount),
+      None => price.amount,
+    };
+    let mut left = net;
+    for (n, taken) in reduction.matches.iter().enumerate() {
+      let share = if n + 1 == reduction.matches.len() {
+        Some(left)
+      } else {
+        let share = net.checked_mul(&taken.quantity);
+        share.map(|s| s.checked_div(&quantity, net.scale()).unwrap_or_default())
+      };
+      let Some((share, rest)) = share.and_then(|s| Some((s, left.checked_sub(&s)?))) else {
+        diagnostics.push(out_of_range());
+        break;
+      };
+      left = rest;
+      let proceeds = CommodityAmount {
+        commodity: price.commodity.clone(),
+        amount: share,
+      };
+      let sold = disposal(reduction, taken, round(proceeds), taken.cost_basis.clone().map(round));
+      match &taken.cost_basis {
+        _ if sold.gain.is_some() => {}
+        Some(cost) if cost.commodity == price.commodity => diagnostics.push(out_of_range()),
+        cost_basis => {
+          let reason = match cost_basis {
+            Some(cost) => format!("cost in {}, proceeds in {}", cost.commodity, price.commodity),
+            None => "unknown cost basis".to_string(),
+          };
+          let quantity = taken.quantity;
+          let message =
+            format!("no gain for {quantity} {commodity} sold from {account}: {reason}");
+          diagnostics.push(warn(message));
+        }
+      }
+      disposals.push(sold);
+    }
+  }
+
+  let mut totals: BTreeMap<&str, GainTotal> = BTreeMap::new();
+  for d in &disposals {
+    let total = totals.entry(&d.proceeds.commodity).or_insert_with(|| GainTotal {
+      currency: d.proceeds.commodity.clone(),
+      ..GainTotal::default()
+    });
+    if total.add(d).is_none() {
+      let message = format!("realized gain totals out of range in {}", d.proceeds.commodity);
+      diagnostics.push(diagnostic(d.file.as_ref(), d.line, Severity::Error, message));
+    }
+  }
+  let totals = totals.into_values().collect();
+
+  // Computed gain per transaction (by file and header line) and currency.
+  let mut by_txn: BTreeMap<(Option<&String>, usize), BTreeMap<&str, Amount>> = BTreeMap::new();
+  let header_line: BTreeMap<(Option<&String>, usize), &Transaction> = result
+    .transactions
+    .iter()
+    .flat_map(|t| t.postings.iter().map(move |p| ((t.file.as_ref(), p.line), t)))
+    .collect();
+  for d in &disposals {
+    let Some(txn) = header_line.get(&(d.file.as_ref(), d.line)) else {
+      continue;
+    };
+    let gains = by_txn.entry((txn.file.as_ref(), txn.line)).or_default();
+    let gain = gains.entry(&d.proceeds.commodity).or_default();
+    match gain.checked_add(&d.gain.unwrap_or_default()) {
+      Some(sum) => *gain = sum,
+      None => {
+        let message = format!("realized gain out of range in {}", d.proceeds.commodity);
+        diagnostics.push(diagnostic(txn.file.as_ref(), txn.line, Severity::Error, message));
+      }
+    }
+  }
+
+  let mut pnl_checks = Vec::new();
+  for txn in &result.transactions {
+    let Some(computed) = by_txn.get(&(txn.file.as_ref(), txn.line)) else {
+      continue;
+    };
+    let mut recorded: BTreeMap<&str, (Amount, usize)> = BTreeMap::new();
+    let pnl = txn.postings.iter().filter(|p| {
+      p.account == PNL_ACCOUNT
+        || p.account.strip_prefix(PNL_ACCOUNT).is_some_and(|rest| rest.starts_with(':'))
+    });
+    for posting in pnl {
+      let entry = recorded.entry(&posting.commodity).or_insert((Amount::ZERO, posting.line));
+      match entry.0.checked_sub(&posting.amount) {
+        Some(sum) => entry.0 = sum,
+        None => {
+          let message = format!("{PNL_ACCOUNT} postings out of range in {}", posting.commodity);
+          diagnostics.push(diagnostic(txn.file.as_ref(), posting.line, Severity::Error, message));
+        }
+      }
+    }
+    for (currency, (recorded, line)) in recorded {
+      let computed = computed.get(currency).copied().unwrap_or_default();
+      let agrees = computed.checked_rescale(recorded.scale()) == Some(recorded);
+      if !agrees {
+        let message = format!(
+          "realized gain of {computed} {currency} disagrees with {PNL_ACCOUNT} posting of \
+           {recorded} {currency}"
+        );
+        diagnostics.push(diagnostic(txn.file.as_ref(), line, Severity::Error, message));
+      }
+      pnl_checks.push(PnlCheck {
+        date: txn.date.clone(),
+        file: txn.file.clone(),
+        line: txn.line,
+        txn_id: txn.txn_id().map(str::to_string),
+        currency: currency.to_string(),
+        computed,
+        recorded,
+        agrees,
+      });
+    }
+  }
+
+  RealizedGains {
+    from: from.map(str::to_string),
+    to: to.map(str::to_string),
+    disposals,
+    totals,
+    pnl_checks,
+    diagnostics,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ledger_parser::{
+    parse_transactions, parse_transactions_with_options, FeePolicy, ParseOptions,
+  };
+
+  #[test]
+  fn computes_gains_per_lot_and_checks_pnl_postings() {
+    let result = parse_transactions(
+      r#"2025-01-10 * "Kraken" "Buy BTC" ; txn:01J2N9R1
+    assets:exchange:kraken:btc    0.010 BTC { 20000 USD }
+    assets:exchange:kraken:usd   -200.00 USD
+
+2025-12-01 * "Kraken" "Buy BTC" ; txn:01J2N9R2
+    assets:exchange:kraken:btc    0.010 BTC { 40000 USD }
+    assets:exchange:kraken:usd   -400.00 USD
+
+2026-01-16T10:05:03Z * "Kraken" "Sell BTC" ; txn:01J2N9R3
+    assets:exchange:kraken:btc   -0.015 BTC {} @@ 750.00 USD
+    assets:exchange:kraken:usd    750.00 USD
+    income:trading:pnl           -350.00 USD
+    equity:trading                350.00 USD
+
+2026-01-17 * "Kraken" "Sell BTC" ; txn:01J2N9R4
+    assets:exchange:kraken:btc   -0.005000 BTC { 30000 USD, 2026-01-15T09:31:22+11:00, lot:kraken:btc:abc123 } @@ 160.00 USD
+    assets:cash:usd               160.00 USD
+    income:trading:pnl            -10.00 USD
+"#,
+    );
+    assert!(result.ok, "{:?}", result.diagnostics);
+
+    let gains = realized_gains(&result, BookingMethod::Fifo, Some("2026-01-01"), None);
+    let rows: Vec<String> = gains
+      .disposals
+      .iter()
+      .map(|d| {
+        let lot = d.lot_id.as_deref().unwrap_or("-");
+        let gain = d.gain.unwrap_or_default();
+        format!("{lot} {} {} {gain} {:?}", d.quantity, d.proceeds.amount, d.term.unwrap())
+      })
+      .collect();
+    assert_eq!(
+      rows,
+      [
+        "01J2N9R1/1 0.010 500.00 300.000 Long",
+        "01J2N9R2/1 0.005 250.00 50.000 Short",
+        "kraken:btc:abc123 0.005000 160.00 10.000000 Short",
+      ]
+    );
+    assert_eq!(gains.disposals[0].holding_days, Some(371));
+    assert_eq!(gains.disposals[2].acquired.as_deref(), Some("2026-01-15T09:31:22+11:00"));
+
+    let total = &gains.totals[0];
+    assert_eq!((total.currency.as_str(), total.gain.to_string()), ("USD", "360.000000".into()));
+    assert_eq!(total.long_term.to_string(), "300.000");
+
+    let checks: Vec<(usize, String, bool)> =
+      gains.pnl_checks.iter().map(|c| (c.line, c.computed.to_string(), c.agrees)).collect();
+    assert_eq!(checks, [(9, "350.000".into(), true), (15, "10.000000".into(), true)]);
+    let messages: Vec<&str> = gains.diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+      messages,
+      [
+        "no lot kraken:btc:abc123 of BTC held in assets:exchange:kraken:btc; using the annotated \
+         cost"
+      ]
+    );
+
+    let csv = gains.to_csv();
+    let mut lines = csv.lines().skip(1);
+    assert_eq!(
+      lines.next(),
+      Some(
+        "0.010 BTC,2025-01-10,2026-01-16,500.00,200.000,300.000,USD,long,371,\
+         assets:exchange:kraken:btc,01J2N9R1/1"
+      )
+    );
+  }
+
+  #[test]
+  fn flags_pnl_postings_that_disagree() {
+    let result = parse_transactions(
+      r#"2026-01-10 * "Binance" "Buy SOL" ; txn:01J2N9R1
+    assets:exchange:binance:sol    10 SOL {{ 230.00 USD }}
+    assets:exchange:binance:usd   -230.00 USD
+
+2026-02-10 * "Binance" "Sell SOL" ; txn:01J2N9R2
+    assets:exchange:binance:sol    -4 SOL {} @ 30.00 USD
+    assets:exchange:binance:usd    120.00 USD
+    income:trading:pnl             -20.00 USD
+    equity:trading                  20.00 USD
+"#,
+    );
+    assert!(result.ok, "{:?}", result.diagnostics);
+
+    let gains = realized_gains(&result, BookingMethod::Fifo, None, None);
+    assert_eq!(gains.disposals[0].cost_basis.as_ref().unwrap().amount.to_string(), "92");
+    assert_eq!(gains.disposals[0].gain.unwrap().to_string(), "28.00");
+    assert!(!gains.pnl_checks[0].agrees);
+    assert_eq!(
+      gains.diagnostics[0].message,
+      "realized gain of 28.00 USD disagrees with income:trading:pnl posting of 20.00 USD"
+    );
+    assert_eq!(gains.diagnostics[0].line, 8);
+  }
+
+  #[test]
+  fn capitalizes_fees_into_cost_and_proceeds() {
+    let input = r#"2026-01-10 * "Binance" "Buy SOL" ; txn:01J2N9R1
+    assets:exchange:binance:sol    10 SOL {{ 230.00 USD, fee:1.00 USD }}
+    assets:exchange:binance:usd   -231.00 USD
+
+2026-02-10 * "Binance" "Sell SOL" ; txn:01J2N9R2
+    assets:exchange:binance:sol    -4 SOL { fee:0.50 USD } @ 30.00 USD
+    assets:exchange:binance:usd    119.50 USD
+    income:trading:pnl             -27.10 USD
+    equity:trading                  27.10 USD
+
+2026-02-11 * "Kraken" "Buy BTC" ; txn:01J2N9R3
+    assets:exchange:kraken:btc     0.5 BTC { 30000 USD }
+    assets:exchange:kraken:usd   -15000.00 USD
+
+2026-02-12 * "Kraken" "Sell BTC" ; txn:01J2N9R4
+    assets:exchange:kraken:btc    -0.25 BTC { 30000 USD, fee:0.10 USD } @ 40000 USD
+    assets:exchange:kraken:usd     9999.90 USD
+    income:trading:pnl            -2499.90 USD
+"#;
+    let options = ParseOptions {
+      fee_policy: FeePolicy::Capitalize,
+      ..ParseOptions::default()
+    };
+    let result = parse_transactions_with_options(input, &options);
+    assert!(result.ok, "{:?}", result.diagnostics);
+
+    let gains = realized_gains(&result, BookingMethod::Fifo, None, None);
+    let sold = &gains.disposals[0];
+    let basis = sold.cost_basis.as_ref().unwrap().amount;
+    assert_eq!(sold.proceeds.amount.to_string(), "119.50");
+    assert_eq!(basis.to_string(), "92.40");
+    assert_eq!(sold.gain.unwrap().to_string(), "27.10");
+    // A sale weighed at its lot cost balances with the fee netted out of the gain alone.
+    let sold = &gains.disposals[1];
+    assert_eq!(sold.proceeds.amount.to_string(), "9999.90");
+    assert_eq!(sold.gain.unwrap().to_string(), "2499.90");
+    assert!(gains.pnl_checks.iter().all(|c| c.agrees), "{:?}", gains.pnl_checks);
+    assert!(gains.diagnostics.is_empty(), "{:?}", gains.diagnostics);
+  }
+
+  #[test]
+  fn rounds_amounts_to_the_declared_precision() {
+    let result = parse_transactions(
+      r#"commodity USD
+    precision 2
+
+2026-01-05 * "Kraken" "Buy BTC" ; txn:01J2N9R1
+    assets:exchange:kraken:btc    0.5 BTC { 30000.123 USD }
+    assets:exchange:kraken:usd   -15000.06 USD
+
+2026-02-01 * "Kraken" "Sell BTC" ; txn:01J2N9R2
+    assets:exchange:kraken:btc   -0.5 BTC {} @@ 16000.00 USD
+    assets:exchange:kraken:usd    16000.00 USD
+    income:trading:pnl            -999.94 USD
+    equity:trading                 999.94 USD
+"#,
+    );
+    assert!(result.ok, "{:?}", result.diagnostics);
+
+    let gains = realized_gains(&result, BookingMethod::Fifo, None, None);
+    assert!(gains.pnl_checks[0].agrees, "{:?}", gains.pnl_checks);
+    let csv = gains.to_csv();
+    assert_eq!(
+      csv.lines().nth(1),
+      Some(
+        "0.5 BTC,2026-01-05,2026-02-01,16000.00,15000.06,999.94,USD,short,27,\
+         assets:exchange:kraken:btc,01J2N9R1/1"
+      )
+    );
+  }
+}
@@ -14,10 +14,12 @@ fn archive_path(base_dir: &Path, yyyymm: &str) -> PathBuf {
   archive_dir(base_dir).join(format!("ledger-{yyyymm}.transactions"))
 }
 
+/// The month of the first transaction header, skipping directives such as `account` and
+/// `commodity` that may precede it.
 fn parse_yyyymm_from_contents(contents: &str) -> Option<String> {
   for raw_line in contents.lines() {
     let line = raw_line.trim();
-    if line.is_empty() || line.starts_with(';') {
+    if line.is_empty() || line.starts_with(';') || raw_line.starts_with([' ', '\t']) {
       continue;
     }
     // Expected header starts with YYYY-MM-DD...
@@ -37,7 +39,6 @@ fn parse_yyyymm_from_contents(contents: &str) -> Option<String> {
         return Some(format!("{year}{month}"));
       }
     }
-    break;
   }
   None
 }
@@ -68,7 +69,7 @@ fn unique_archive_path(desired: PathBuf) -> PathBuf {
 }
 
 /// Rotates `ledger.transactions` into `archive/ledger-YYYYMM.transactions` when the ledger's
-/// transaction month differs from `now_yyyymm`.
+/// transaction month differs from `now_yyyymm`. A ledger without transactions is left in place.
 ///
 /// - `base_dir` is the root folder containing `ledger.transactions`.
 /// - `now_yyyymm` is the current month string, e.g. `"202601"`.
@@ -85,7 +86,10 @@ pub fn rotate_ledger_if_needed(base_dir: &Path, now_yyyymm: &str) -> io::Result<
     return Ok(());
   }
 
-  let ledger_yyyymm = parse_yyyymm_from_contents(&contents).unwrap_or_else(|| "unknown".to_string());
+  // A ledger holding only directives has no month yet and stays active.
+  let Some(ledger_yyyymm) = parse_yyyymm_from_contents(&contents) else {
+    return Ok(());
+  };
   if ledger_yyyymm == now_yyyymm {
     return Ok(());
   }
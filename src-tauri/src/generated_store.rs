@@ -1,7 +1,10 @@
 use crate::generated_ledger::rotate_ledger_if_needed;
-use crate::ledger_parser::{parse_transactions, ParseResult, Posting, Transaction};
+use crate::ledger_parser::{
+  parse_sources, parse_transactions, parse_transactions_with_options, LedgerSource, ParseOptions,
+  ParseResult, Posting, Transaction,
+};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
@@ -21,6 +24,30 @@ pub struct ImportStats {
   pub imported: usize,
   pub skipped_duplicates: usize,
   pub archived: usize,
+  /// `account`, `commodity` and `price` directives copied into the active ledger.
+  pub directives: usize,
+}
+
+/// Controls how imported transactions are written to the generated ledger.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ImportOptions {
+  /// Write amounts the parser inferred for elided postings instead of leaving them elided.
+  #[serde(default)]
+  pub write_inferred_amounts: bool,
+  /// Reject source files with undeclared accounts or commodities (see
+  /// [`ParseOptions::strict`]). A source file can also opt in with `option strict`.
+  #[serde(default)]
+  pub strict: bool,
+}
+
+/// One `opening` line of a new account declaration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpeningBalanceInput {
+  pub amount: String,
+  /// Defaults to the account's currency.
+  pub commodity: Option<String>,
+  /// `YYYY-MM-DD`; undated openings apply before every transaction.
+  pub date: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -79,26 +106,10 @@ fn quote(s: &str) -> String {
   format!("\"{escaped}\"")
 }
 
-fn extract_txn_id(meta: &str) -> Option<String> {
-  // Token ends at comma or whitespace.
-  let pos = meta.find("txn:")?;
-  let after = &meta[pos + 4..];
-  let end = after
-    .find(|c: char| c == ',' || c.is_whitespace())
-    .unwrap_or(after.len());
-  let id = after[..end].trim();
-  if id.is_empty() {
-    None
-  } else {
-    Some(id.to_string())
-  }
-}
-
-fn ensure_txn_id(meta: Option<&str>) -> (String, String) {
-  if let Some(m) = meta {
-    if let Some(id) = extract_txn_id(m) {
-      return (id, m.to_string());
-    }
+fn ensure_txn_id(txn: &Transaction) -> (String, String) {
+  let meta = txn.meta.as_deref();
+  if let (Some(id), Some(m)) = (txn.txn_id(), meta) {
+    return (id.to_string(), m.to_string());
   }
 
   let id = generate_txn_id();
@@ -109,13 +120,33 @@ fn ensure_txn_id(meta: Option<&str>) -> (String, String) {
   (id, updated)
 }
 
+const CROCKFORD_BASE32: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Generates a ULID: a 48-bit millisecond timestamp followed by 80 random bits, so generated
+/// ids sort by creation time and pass `TxnIdSyntax::Ulid`.
 fn generate_txn_id() -> String {
-  let nanos = std::time::SystemTime::now()
+  use std::collections::hash_map::RandomState;
+  use std::hash::{BuildHasher, Hasher};
+
+  let now = std::time::SystemTime::now()
     .duration_since(std::time::UNIX_EPOCH)
-    .unwrap_or_default()
-    .as_nanos();
-  let pid = std::process::id();
-  format!("gen-{pid:x}-{nanos:x}")
+    .unwrap_or_default();
+  let millis = now.as_millis() & ((1u128 << 48) - 1);
+
+  // `RandomState` is seeded from OS randomness per instance.
+  let mut hasher = RandomState::new().build_hasher();
+  hasher.write_u128(now.as_nanos());
+  hasher.write_u32(std::process::id());
+  let high = hasher.finish();
+  hasher.write_u64(high);
+  let low = hasher.finish();
+  let random = ((high as u128) << 16) | (low as u128 & 0xFFFF);
+
+  let value = (millis << 80) | random;
+  (0..26)
+    .rev()
+    .map(|i| CROCKFORD_BASE32[((value >> (i * 5)) & 0x1F) as usize] as char)
+    .collect()
 }
 
 fn yyyymm_from_datetime(datetime: &str) -> Option<String> {
@@ -131,7 +162,21 @@ fn yyyymm_from_datetime(datetime: &str) -> Option<String> {
   }
 }
 
-fn posting_to_text(posting: &Posting) -> String {
+fn posting_to_text(posting: &Posting, write_inferred: bool) -> String {
+  if posting.inferred && !write_inferred {
+    let mut out = format!("    {}", posting.account);
+    if let Some(assertion) = posting.remainder.as_deref().map(str::trim).filter(|a| !a.is_empty())
+    {
+      out.push(' ');
+      out.push_str(assertion);
+    }
+    if let Some(meta) = posting.meta.as_deref().map(str::trim).filter(|m| !m.is_empty()) {
+      out.push_str(" ; ");
+      out.push_str(meta);
+    }
+    return out;
+  }
+
   let mut out = format!(
     "    {} {} {}",
     posting.account, posting.amount_text, posting.commodity
@@ -143,10 +188,14 @@ fn posting_to_text(posting: &Posting) -> String {
     out.push(' ');
     out.push_str(rem);
   }
+  if let Some(meta) = posting.meta.as_deref().map(str::trim).filter(|m| !m.is_empty()) {
+    out.push_str(" ; ");
+    out.push_str(meta);
+  }
   out
 }
 
-fn transaction_to_text(txn: &Transaction, forced_meta: &str) -> String {
+fn transaction_to_text(txn: &Transaction, forced_meta: &str, write_inferred: bool) -> String {
   let mut header = txn.datetime.clone();
   header.push(' ');
 
@@ -169,8 +218,17 @@ fn transaction_to_text(txn: &Transaction, forced_meta: &str) -> String {
 
   let mut lines = Vec::with_capacity(1 + txn.postings.len() + 1);
   lines.push(header);
-  for posting in &txn.postings {
-    lines.push(posting_to_text(posting));
+  // Fee postings implied by a lot's `fee:` field are rebuilt from the field on parse.
+  let postings: Vec<&Posting> = txn.postings.iter().filter(|p| p.fee_for.is_none()).collect();
+  for (i, posting) in postings.iter().enumerate() {
+    // An amount inferred in several commodities came from a single elided source line, written
+    // from its last part (which keeps any balance assertion).
+    let continued =
+      postings.get(i + 1).is_some_and(|next| next.inferred && next.line == posting.line);
+    if posting.inferred && !write_inferred && continued {
+      continue;
+    }
+    lines.push(posting_to_text(posting, write_inferred));
   }
   lines.push(String::new());
   lines.join("\n")
@@ -183,18 +241,19 @@ fn manual_to_transaction(input: &ManualTransactionInput) -> Transaction {
     status: input.status,
     payee: Some(input.payee.clone()),
     narration: Some(input.narration.clone()),
-    meta: None,
     postings: input
       .postings
       .iter()
       .map(|p| Posting {
         account: p.account.clone(),
-        amount: p.amount.parse().unwrap_or(0.0),
+        amount: p.amount.parse().unwrap_or_default(),
         amount_text: p.amount.clone(),
         commodity: p.commodity.clone(),
         remainder: p.remainder.clone(),
+        ..Posting::default()
       })
       .collect(),
+    ..Transaction::default()
   }
 }
 
@@ -277,6 +336,15 @@ pub fn import_source_files(
   base_dir: &Path,
   now_yyyymm: &str,
   paths: &[String],
+) -> Result<ImportStats, String> {
+  import_source_files_with_options(base_dir, now_yyyymm, paths, &ImportOptions::default())
+}
+
+pub fn import_source_files_with_options(
+  base_dir: &Path,
+  now_yyyymm: &str,
+  paths: &[String],
+  options: &ImportOptions,
 ) -> Result<ImportStats, String> {
   fs::create_dir_all(base_dir).map_err(|e| e.to_string())?;
   rotate_ledger_if_needed(base_dir, now_yyyymm).map_err(|e| e.to_string())?;
@@ -284,6 +352,13 @@ pub fn import_source_files(
   let mut index: GeneratedIndex = read_json(&index_path(base_dir)).map_err(|e| e.to_string())?;
   let mut sources: SourceRegistry = read_json(&sources_path(base_dir)).map_err(|e| e.to_string())?;
 
+  check_strict_sources(base_dir, paths, options)?;
+  let directives = new_directives(base_dir, paths)?;
+  if !directives.is_empty() {
+    append_text(&generated_ledger_path(base_dir), &(directives.join("\n\n") + "\n"))
+      .map_err(|e| e.to_string())?;
+  }
+
   let mut imported = 0usize;
   let mut skipped_duplicates = 0usize;
   let mut archived = 0usize;
@@ -305,7 +380,7 @@ pub fn import_source_files(
         continue;
       }
 
-      let (id, meta) = ensure_txn_id(txn.meta.as_deref());
+      let (id, meta) = ensure_txn_id(&txn);
       if index.txn_ids.contains(&id) {
         skipped_duplicates += 1;
         continue;
@@ -319,7 +394,7 @@ pub fn import_source_files(
         generated_archive_path(base_dir, &yyyymm)
       };
 
-      let text = transaction_to_text(&txn, &meta);
+      let text = transaction_to_text(&txn, &meta, options.write_inferred_amounts);
       append_text(&dest, &text).map_err(|e| e.to_string())?;
       index.txn_ids.insert(id);
       imported += 1;
@@ -334,9 +409,131 @@ pub fn import_source_files(
     imported,
     skipped_duplicates,
     archived,
+    directives: directives.len(),
   })
 }
 
+/// The `account`, `commodity` and `price` directives of the source files that the generated
+/// ledger doesn't already hold word for word. Redeclaring an account or commodity differently is
+/// an error, checked before anything is imported.
+fn new_directives(base_dir: &Path, paths: &[String]) -> Result<Vec<String>, String> {
+  let history = ledger_history_sources(base_dir)?;
+  let existing = parse_sources(&history, &ParseOptions::default());
+  let text_in = |file: &Option<String>, line: usize| {
+    let source = history.iter().find(|s| Some(&s.path) == file.as_ref());
+    directive_text(source.map_or("", |s| s.contents.as_str()), line)
+  };
+
+  let mut declared: BTreeMap<String, String> = BTreeMap::new();
+  for decl in &existing.accounts {
+    declared.insert(format!("account {}", decl.account), text_in(&decl.file, decl.line));
+  }
+  for decl in &existing.commodities {
+    declared.insert(format!("commodity {}", decl.commodity), text_in(&decl.file, decl.line));
+  }
+  let mut prices: BTreeSet<String> =
+    existing.prices.iter().map(|p| text_in(&p.file, p.line)).collect();
+
+  let mut directives: Vec<String> = Vec::new();
+  let mut errors: Vec<String> = Vec::new();
+  for p in paths {
+    let contents =
+      fs::read_to_string(p).map_err(|e| format!("failed to read source file {p}: {e}"))?;
+    let result = parse_transactions(&contents);
+
+    // (line, key) in source order; prices have no key and are only deduplicated.
+    let mut found: Vec<(usize, Option<String>)> = Vec::new();
+    found.extend(result.accounts.iter().map(|d| (d.line, Some(format!("account {}", d.account)))));
+    found.extend(
+      result.commodities.iter().map(|d| (d.line, Some(format!("commodity {}", d.commodity)))),
+    );
+    found.extend(result.prices.iter().map(|d| (d.line, None)));
+    found.sort();
+
+    for (line, key) in found {
+      let text = directive_text(&contents, line);
+      match key {
+        Some(key) => match declared.get(&key) {
+          Some(existing) if *existing == text => {}
+          Some(_) => errors.push(format!(
+            "{p}: line {line}: {key} is already declared differently in the generated ledger"
+          )),
+          None => {
+            declared.insert(key, text.clone());
+            directives.push(text);
+          }
+        },
+        None => {
+          if prices.insert(text.clone()) {
+            directives.push(text);
+          }
+        }
+      }
+    }
+  }
+
+  if errors.is_empty() {
+    Ok(directives)
+  } else {
+    Err(format!("conflicting declarations rejected the import:\n{}", errors.join("\n")))
+  }
+}
+
+/// The directive on 1-based `line` of `contents` with its indented entries, trailing whitespace
+/// trimmed.
+fn directive_text(contents: &str, line: usize) -> String {
+  let mut lines = contents.lines().skip(line.saturating_sub(1));
+  let mut text = lines.next().unwrap_or_default().trim_end().to_string();
+  for entry in lines.take_while(|l| l.starts_with([' ', '\t']) && !l.trim().is_empty()) {
+    text.push('\n');
+    text.push_str(entry.trim_end());
+  }
+  text
+}
+
+/// In strict mode, checks every source file against the declarations in the generated ledger and
+/// its own, before anything is imported.
+fn check_strict_sources(
+  base_dir: &Path,
+  paths: &[String],
+  options: &ImportOptions,
+) -> Result<(), String> {
+  let history = ledger_history_sources(base_dir)?;
+  let parse_options = ParseOptions {
+    strict: options.strict,
+    ..ParseOptions::default()
+  };
+
+  let mut errors: Vec<String> = Vec::new();
+  for p in paths {
+    let contents =
+      fs::read_to_string(p).map_err(|e| format!("failed to read source file {p}: {e}"))?;
+    let mut sources = history.clone();
+    sources.push(LedgerSource {
+      path: p.clone(),
+      contents,
+    });
+
+    let result = parse_sources(&sources, &parse_options);
+    if !result.strict {
+      continue;
+    }
+    errors.extend(
+      result
+        .diagnostics
+        .iter()
+        .filter(|d| d.is_error() && d.file.as_deref() == Some(p.as_str()))
+        .map(|d| format!("{p}: line {}, column {}: {}", d.line, d.column, d.message)),
+    );
+  }
+
+  if errors.is_empty() {
+    Ok(())
+  } else {
+    Err(format!("strict mode rejected the import:\n{}", errors.join("\n")))
+  }
+}
+
 pub fn add_manual_transaction(
   base_dir: &Path,
   now_yyyymm: &str,
@@ -347,7 +544,7 @@ pub fn add_manual_transaction(
   let mut index: GeneratedIndex = read_json(&index_path(base_dir)).map_err(|e| e.to_string())?;
   let txn = manual_to_transaction(input);
 
-  let (id, meta) = ensure_txn_id(None);
+  let (id, meta) = ensure_txn_id(&txn);
   if index.txn_ids.contains(&id) {
     return Err("generated txn id collided; retry".to_string());
   }
@@ -359,7 +556,22 @@ pub fn add_manual_transaction(
     generated_archive_path(base_dir, &yyyymm)
   };
 
-  let text = transaction_to_text(&txn, &meta);
+  let text = transaction_to_text(&txn, &meta, true);
+  // Balance assertions depend on the rest of the ledger, so they can't be judged in isolation.
+  let options = ParseOptions {
+    skip_balance_assertions: true,
+    ..ParseOptions::default()
+  };
+  let messages: Vec<String> = parse_transactions_with_options(&text, &options)
+    .diagnostics
+    .into_iter()
+    .filter(|d| d.is_error())
+    .map(|d| d.message)
+    .collect();
+  if !messages.is_empty() {
+    return Err(format!("invalid transaction: {}", messages.join("; ")));
+  }
+
   append_text(&dest, &text).map_err(|e| e.to_string())?;
   index.txn_ids.insert(id.clone());
   write_json(&index_path(base_dir), &index).map_err(|e| e.to_string())?;
@@ -371,7 +583,7 @@ pub fn add_account_declaration(
   base_dir: &Path,
   account_name: &str,
   currency: Option<&str>,
-  opening_balance: Option<&str>,
+  openings: &[OpeningBalanceInput],
 ) -> Result<(), String> {
   fs::create_dir_all(base_dir).map_err(|e| e.to_string())?;
 
@@ -385,10 +597,25 @@ pub fn add_account_declaration(
   }
   text.push('\n');
 
-  // Add opening balance if provided
-  if let Some(balance) = opening_balance {
-    let curr = currency.unwrap_or("USD");
-    text.push_str(&format!("    opening {} {}\n", balance, curr));
+  // One opening line per commodity
+  for opening in openings {
+    let curr = opening.commodity.as_deref().or(currency).unwrap_or("USD");
+    text.push_str("    opening ");
+    if let Some(date) = opening.date.as_deref() {
+      text.push_str(date);
+      text.push(' ');
+    }
+    text.push_str(&format!("{} {}\n", opening.amount.trim(), curr));
+  }
+
+  let messages: Vec<String> = parse_transactions(&text)
+    .diagnostics
+    .into_iter()
+    .filter(|d| d.is_error())
+    .map(|d| d.message)
+    .collect();
+  if !messages.is_empty() {
+    return Err(format!("invalid account declaration: {}", messages.join("; ")));
   }
 
   append_text(&dest, &text).map_err(|e| e.to_string())?;
@@ -396,15 +623,11 @@ pub fn add_account_declaration(
   Ok(())
 }
 
-pub fn load_active_ledger(base_dir: &Path) -> Result<ParseResult, String> {
+/// Reads `ledger.transactions`, normalizing blank lines in place. `None` when it doesn't exist.
+fn read_active_ledger(base_dir: &Path) -> Result<Option<String>, String> {
   let ledger = generated_ledger_path(base_dir);
   if !ledger.exists() {
-    return Ok(ParseResult {
-      ok: true,
-      diagnostics: Vec::new(),
-      transactions: Vec::new(),
-      balances: Vec::new(),
-    });
+    return Ok(None);
   }
 
   let contents = fs::read_to_string(&ledger).map_err(|e| e.to_string())?;
@@ -412,5 +635,59 @@ pub fn load_active_ledger(base_dir: &Path) -> Result<ParseResult, String> {
   if normalized != contents {
     fs::write(&ledger, &normalized).map_err(|e| e.to_string())?;
   }
-  Ok(parse_transactions(&normalized))
+  Ok(Some(normalized))
+}
+
+pub fn load_active_ledger(base_dir: &Path) -> Result<ParseResult, String> {
+  match read_active_ledger(base_dir)? {
+    Some(contents) => Ok(parse_transactions(&contents)),
+    None => Ok(ParseResult {
+      ok: true,
+      ..ParseResult::default()
+    }),
+  }
+}
+
+/// Parses every `archive/*.transactions` month (oldest first) plus the active ledger as one set,
+/// so balances and balance assertions see the full history. Source paths in diagnostics are
+/// relative to `base_dir`.
+pub fn load_ledger_history(base_dir: &Path) -> Result<ParseResult, String> {
+  let sources = ledger_history_sources(base_dir)?;
+  Ok(parse_sources(&sources, &ParseOptions::default()))
+}
+
+/// The archived ledgers (oldest first) followed by the active ledger.
+fn ledger_history_sources(base_dir: &Path) -> Result<Vec<LedgerSource>, String> {
+  let mut sources: Vec<LedgerSource> = Vec::new();
+
+  let archive_dir = generated_archive_dir(base_dir);
+  if archive_dir.exists() {
+    let mut paths: Vec<PathBuf> = fs::read_dir(&archive_dir)
+      .map_err(|e| e.to_string())?
+      .filter_map(|entry| entry.ok().map(|e| e.path()))
+      .filter(|p| p.extension().is_some_and(|ext| ext == "transactions"))
+      .collect();
+    paths.sort();
+
+    for path in paths {
+      let contents = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+      let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+      sources.push(LedgerSource {
+        path: format!("archive/{name}"),
+        contents,
+      });
+    }
+  }
+
+  if let Some(contents) = read_active_ledger(base_dir)? {
+    sources.push(LedgerSource {
+      path: "ledger.transactions".to_string(),
+      contents,
+    });
+  }
+
+  Ok(sources)
 }
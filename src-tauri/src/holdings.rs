@@ -0,0 +1,282 @@
+use crate::amount::Amount;
+use crate::ledger_parser::{Diagnostic, ParseResult};
+use crate::lots::{lot_inventory_at, BookingMethod, Lot};
+use crate::prices::{trim_rate, PriceDb};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A lot still held, valued in the report currency.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ValuedLot {
+  #[serde(flatten)]
+  pub lot: Lot,
+  /// Cost basis of the remaining quantity, converted at the acquisition date when the cost is
+  /// in another commodity; `None` when unknown.
+  pub cost: Option<Amount>,
+  /// Market value of the remaining quantity; `None` without a price.
+  pub value: Option<Amount>,
+  /// `value` minus `cost`.
+  pub gain: Option<Amount>,
+}
+
+/// One commodity held in one account.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Holding {
+  pub account: String,
+  pub commodity: String,
+  pub quantity: Amount,
+  /// Market price of one unit in the report currency.
+  pub rate: Option<Amount>,
+  /// Sum of the lots' known costs.
+  pub cost: Amount,
+  pub value: Option<Amount>,
+  /// Sum of the lots' gains; lots of unknown cost are left out.
+  pub gain: Option<Amount>,
+  /// Every lot had a known cost.
+  pub complete: bool,
+  pub lots: Vec<ValuedLot>,
+}
+
+/// One commodity across every account.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct CommodityHolding {
+  pub commodity: String,
+  pub quantity: Amount,
+  pub rate: Option<Amount>,
+  pub cost: Amount,
+  pub value: Option<Amount>,
+  pub gain: Option<Amount>,
+  pub complete: bool,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct HoldingsReport {
+  pub currency: String,
+  pub date: String,
+  /// Holdings by account and commodity.
+  pub holdings: Vec<Holding>,
+  pub commodities: Vec<CommodityHolding>,
+  /// Sums over every holding, in `currency`.
+  pub cost: Amount,
+  pub value: Amount,
+  pub gain: Amount,
+  /// Commodities with no price into `currency` at `date`, or lot costs that couldn't be
+  /// converted at their acquisition date.
+  pub missing_prices: Vec<String>,
+  /// Problems found while matching lots (see [`crate::lots::LotInventory`]).
+  pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Checked sum of `amounts`.
+fn sum(amounts: impl IntoIterator<Item = Amount>, what: &str) -> Result<Amount, String> {
+  amounts.into_iter().try_fold(Amount::ZERO, |total, amount| {
+    total.checked_add(&amount).ok_or_else(|| format!("{what} out of range"))
+  })
+}
+
+fn add(total: &mut Option<Amount>, amount: Option<Amount>, what: &str) -> Result<(), String> {
+  if let Some(amount) = amount {
+    *total = Some(sum([total.unwrap_or_default(), amount], what)?);
+  }
+  Ok(())
+}
+
+/// Lots held at `date` (booked with `method`, see [`lot_inventory_at`]) with their cost basis
+/// and market value in `currency`, per lot, per account and commodity, and per commodity.
+/// Fails when a value or total is out of range.
+pub fn holdings(
+  result: &ParseResult,
+  method: BookingMethod,
+  currency: &str,
+  date: &str,
+) -> Result<HoldingsReport, String> {
+  let inventory = lot_inventory_at(result, method, date);
+  let prices = PriceDb::from_parse(result);
+  let decl = result.commodity(currency);
+  let round = |value: Amount| decl.map_or_else(|| trim_rate(value), |d| d.round(value));
+  let rate_of = |commodity: &str| -> Result<Option<Amount>, String> {
+    if commodity == currency {
+      Ok(Some(Amount::from_int(1)))
+    } else {
+      Ok(prices.rate(commodity, currency, date)?.map(|r| r.rate))
+    }
+  };
+  let mut missing: BTreeSet<String> = BTreeSet::new();
+
+  let mut grouped: BTreeMap<(String, String), Vec<ValuedLot>> = BTreeMap::new();
+  for lot in inventory.lots {
+    let rate = rate_of(&lot.commodity)?;
+    if rate.is_none() {
+      missing.insert(lot.commodity.clone());
+    }
+    let cost = match &lot.cost_basis {
+      Some(basis) => {
+        let converted = if basis.commodity == currency {
+          Some(basis.amount)
+        } else {
+          prices.convert(basis.amount, &basis.commodity, currency, &lot.acquired)?
+        };
+        if converted.is_none() {
+          missing.insert(basis.commodity.clone());
+        }
+        converted.map(round)
+      }
+      None => None,
+    };
+    let out_of_range = || format!("value of lot {} out of range in {currency}", lot.id);
+    let value = match rate {
+      Some(rate) => Some(round(lot.remaining.checked_mul(&rate).ok_or_else(out_of_range)?)),
+      None => None,
+    };
+    let gain = match cost.zip(value) {
+      Some((cost, value)) => Some(value.checked_sub(&cost).ok_or_else(out_of_range)?),
+      None => None,
+    };
+    grouped
+      .entry((lot.account.clone(), lot.commodity.clone()))
+      .or_default()
+      .push(ValuedLot {
+        lot,
+        cost,
+        value,
+        gain,
+      });
+  }
+
+  let mut holdings = Vec::new();
+  let mut commodities: BTreeMap<String, CommodityHolding> = BTreeMap::new();
+  for ((account, commodity), lots) in grouped {
+    let what = format!("holding of {commodity} in {account}");
+    let mut holding = Holding {
+      account,
+      commodity: commodity.clone(),
+      quantity: sum(lots.iter().map(|l| l.lot.remaining), &what)?,
+      rate: rate_of(&commodity)?,
+      cost: sum(lots.iter().filter_map(|l| l.cost), &what)?,
+      value: None,
+      gain: None,
+      complete: lots.iter().all(|l| l.cost.is_some()),
+      lots: Vec::new(),
+    };
+    for lot in &lots {
+      add(&mut holding.value, lot.value, &what)?;
+      add(&mut holding.gain, lot.gain, &what)?;
+    }
+    holding.lots = lots;
+
+    let total = commodities.entry(commodity.clone()).or_insert_with(|| CommodityHolding {
+      commodity,
+      quantity: Amount::ZERO,
+      rate: holding.rate,
+      cost: Amount::ZERO,
+      value: None,
+      gain: None,
+      complete: true,
+    });
+    let what = format!("holding of {}", total.commodity);
+    total.quantity = sum([total.quantity, holding.quantity], &what)?;
+    total.cost = sum([total.cost, holding.cost], &what)?;
+    add(&mut total.value, holding.value, &what)?;
+    add(&mut total.gain, holding.gain, &what)?;
+    total.complete &= holding.complete;
+    holdings.push(holding);
+  }
+
+  let commodities: Vec<CommodityHolding> = commodities.into_values().collect();
+  let what = format!("holdings total in {currency}");
+  Ok(HoldingsReport {
+    currency: currency.to_string(),
+    date: date.to_string(),
+    cost: sum(commodities.iter().map(|c| c.cost), &what)?,
+    value: sum(commodities.iter().filter_map(|c| c.value), &what)?,
+    gain: sum(commodities.iter().filter_map(|c| c.gain), &what)?,
+    holdings,
+    commodities,
+    missing_prices: missing.into_iter().collect(),
+    diagnostics: inventory.diagnostics,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ledger_parser::parse_transactions;
+
+  const LEDGER: &str = r#"commodity USD
+    precision 2
+
+2026-01-05 * "Kraken" "Buy BTC" ; txn:01J2N9R1
+    assets:exchange:kraken:btc    0.5 BTC { 30000 USD }
+    assets:exchange:kraken:usd   -15000.00 USD
+
+2026-01-10 * "Binance" "Buy BTC" ; txn:01J2N9R2
+    assets:exchange:binance:btc   0.25 BTC { 40000 USD }
+    assets:exchange:binance:usd  -10000.00 USD
+
+2026-01-12 * "Binance" "Buy SOL" ; txn:01J2N9R3
+    assets:exchange:binance:sol   10 SOL {{ 230.00 USD }}
+    assets:exchange:binance:usd  -230.00 USD
+
+2026-02-01 * "Kraken" "Sell BTC" ; txn:01J2N9R4
+    assets:exchange:kraken:btc   -0.25 BTC { 30000 USD } @ 50000 USD
+    assets:exchange:kraken:usd    12500.00 USD
+    income:trading:pnl           -5000.00 USD
+
+price 2026-01-20 BTC 45000 USD
+price 2026-02-15 BTC 60000 USD
+"#;
+
+  #[test]
+  fn values_lots_per_account_and_commodity() {
+    let result = parse_transactions(LEDGER);
+    assert!(result.ok, "{:?}", result.diagnostics);
+
+    let report = holdings(&result, BookingMethod::Fifo, "USD", "2026-02-20").unwrap();
+    let rows: Vec<String> = report
+      .holdings
+      .iter()
+      .map(|h| {
+        let (value, gain) = (h.value.unwrap_or_default(), h.gain.unwrap_or_default());
+        format!("{} {} {} {} {value} {gain}", h.account, h.quantity, h.commodity, h.cost)
+      })
+      .collect();
+    assert_eq!(
+      rows,
+      [
+        "assets:exchange:binance:btc 0.25 BTC 10000.00 15000.00 5000.00",
+        "assets:exchange:binance:sol 10 SOL 230.00 230.00 0.00",
+        "assets:exchange:kraken:btc 0.25 BTC 7500.00 15000.00 7500.00",
+      ]
+    );
+    // SOL has no market price; the cost annotation's price is the latest one.
+    assert_eq!(report.holdings[1].rate.unwrap().to_string(), "23");
+    assert!(report.missing_prices.is_empty());
+
+    let btc = &report.commodities[0];
+    assert_eq!(btc.quantity.to_string(), "0.50");
+    assert_eq!(btc.rate.unwrap().to_string(), "60000");
+    assert_eq!(btc.gain.unwrap().to_string(), "12500.00");
+    assert_eq!(report.gain.to_string(), "12500.00");
+    assert_eq!(report.cost.to_string(), "17730.00");
+  }
+
+  #[test]
+  fn values_holdings_as_of_an_earlier_date() {
+    let result = parse_transactions(LEDGER);
+    let report = holdings(&result, BookingMethod::Fifo, "USD", "2026-01-31").unwrap();
+
+    let kraken = &report.holdings[2];
+    assert_eq!(kraken.quantity.to_string(), "0.5");
+    assert_eq!(kraken.rate.unwrap().to_string(), "45000");
+    let lot = &kraken.lots[0];
+    assert_eq!(lot.lot.id, "01J2N9R1/1");
+    let text = |amount: Option<Amount>| amount.unwrap().to_string();
+    assert_eq!(text(lot.cost), "15000.00");
+    assert_eq!(text(lot.value), "22500.00");
+    assert_eq!(text(lot.gain), "7500.00");
+
+    let euro = holdings(&result, BookingMethod::Fifo, "EUR", "2026-01-31").unwrap();
+    assert_eq!(euro.missing_prices, ["BTC", "SOL", "USD"]);
+    assert_eq!((euro.holdings[0].value, euro.holdings[0].cost), (None, Amount::ZERO));
+  }
+}
@@ -1,59 +1,518 @@
+use crate::accounts::{AccountType, AccountTypes};
+use crate::amount::{Amount, MAX_SCALE};
 use regex::Regex;
 use serde::Serialize;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+  #[default]
+  Error,
+  /// Reported but doesn't fail the parse, e.g. an asset account going negative.
+  Warning,
+}
 
 #[derive(Debug, Clone, Serialize, PartialEq, Eq)]
 pub struct Diagnostic {
   pub line: usize,
   pub column: usize,
   pub message: String,
+  /// Source path, set when parsing a set of files with [`parse_sources`].
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub file: Option<String>,
+  pub severity: Severity,
 }
 
-#[derive(Debug, Clone, Serialize, PartialEq)]
+impl Diagnostic {
+  pub fn is_error(&self) -> bool {
+    self.severity == Severity::Error
+  }
+}
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
 pub struct Posting {
+  /// 1-based line number of the posting.
+  pub line: usize,
   pub account: String,
-  pub amount: f64,
+  pub amount: Amount,
   pub amount_text: String,
   pub commodity: String,
+  pub lot_cost: Option<LotCost>,
+  pub price: Option<PriceAnnotation>,
+  /// `= <amount>`: expected running balance of the account after this posting.
+  pub balance_assertion: Option<CommodityAmount>,
+  /// Raw text of the posting's trailing meta comment (everything after `;`).
+  pub meta: Option<String>,
+  pub tags: Meta,
+  /// Raw cost/price/assertion text between the commodity and the meta comment.
   pub remainder: Option<String>,
+  /// The amount was elided in the source and inferred from the other postings.
+  pub inferred: bool,
+  /// Synthesized from the `fee:` lot field of the posting on this line (see [`FeePolicy`]).
+  pub fee_for: Option<usize>,
+}
+
+impl Posting {
+  /// Amount this posting contributes to its transaction's balance: its cost when the lot cost
+  /// carries an amount, otherwise its price, otherwise the posting amount itself. `None` when a
+  /// per-unit cost or price times the posting amount is out of range.
+  pub fn weight(&self) -> Option<CommodityAmount> {
+    let signed = |total: Amount| if self.amount.is_negative() { -total.abs() } else { total.abs() };
+
+    if let Some(cost) = self.lot_cost.as_ref().and_then(|c| c.amount.as_ref().map(|a| (c.kind, a))) {
+      let (kind, amount) = cost;
+      let total = match kind {
+        CostKind::PerUnit => amount.amount.checked_mul(&self.amount)?,
+        CostKind::Total => signed(amount.amount),
+      };
+      return Some(CommodityAmount {
+        commodity: amount.commodity.clone(),
+        amount: total,
+      });
+    }
+
+    if self.price.is_some() {
+      let total = self.total_price()?;
+      return Some(CommodityAmount {
+        commodity: total.commodity,
+        amount: signed(total.amount),
+      });
+    }
+
+    Some(CommodityAmount {
+      commodity: self.commodity.clone(),
+      amount: self.amount,
+    })
+  }
+
+  /// Total price of the posting in the price commodity (unsigned), e.g. sale proceeds for
+  /// `-0.005 BTC @@ 160.00 USD` or `-0.005 BTC @ 32000 USD`. `None` without a price, or when a
+  /// per-unit price times the posting amount is out of range.
+  pub fn total_price(&self) -> Option<CommodityAmount> {
+    let price = self.price.as_ref()?;
+    let amount = match price.kind {
+      PriceKind::PerUnit => price.amount.amount.checked_mul(&self.amount.abs())?,
+      PriceKind::Total => price.amount.amount,
+    };
+    Some(CommodityAmount {
+      commodity: price.amount.commodity.clone(),
+      amount,
+    })
+  }
+
+  /// The `fee:` lot field when `policy` folds it into the trade, which it can only do for a fee
+  /// in the commodity of the lot cost or of the price.
+  pub fn capitalized_fee(&self, policy: FeePolicy) -> Option<&CommodityAmount> {
+    let cost = self.lot_cost.as_ref().filter(|_| policy == FeePolicy::Capitalize)?;
+    let fee = cost.fee()?;
+    let price = self.price.as_ref().map(|p| &p.amount);
+    let same =
+      |amount: Option<&CommodityAmount>| amount.is_some_and(|a| a.commodity == fee.commodity);
+    (same(cost.amount.as_ref()) || same(price)).then_some(fee)
+  }
+
+  /// [`Posting::weight`] with a capitalized `fee:` folded in (see [`net_of_fee`]). A reduction
+  /// weighed at its lot cost is left as is: its fee comes off the proceeds, i.e. the gain.
+  pub fn trade_weight(&self, policy: FeePolicy) -> Option<CommodityAmount> {
+    let weight = self.weight()?;
+    let Some(fee) = self.capitalized_fee(policy) else {
+      return Some(weight);
+    };
+    let acquisition = self.amount.is_positive();
+    if !acquisition && self.lot_cost.as_ref().is_some_and(|c| c.amount.is_some()) {
+      return Some(weight);
+    }
+    let gross = CommodityAmount {
+      commodity: weight.commodity,
+      amount: weight.amount.abs(),
+    };
+    let net = net_of_fee(&gross, fee, acquisition)?;
+    let amount = if self.amount.is_negative() { -net.amount } else { net.amount };
+    Some(CommodityAmount { amount, ..net })
+  }
+}
+
+/// The cost of an acquisition or the proceeds of a reduction (`gross`) with a capitalized `fee`
+/// applied: the fee adds to what a purchase cost and comes off what a sale brought in. A fee in
+/// another commodity leaves `gross` unchanged. `None` when out of range.
+pub fn net_of_fee(
+  gross: &CommodityAmount,
+  fee: &CommodityAmount,
+  acquisition: bool,
+) -> Option<CommodityAmount> {
+  let fee_amount = fee.amount.abs();
+  let amount = if fee.commodity != gross.commodity {
+    gross.amount
+  } else if acquisition {
+    gross.amount.checked_add(&fee_amount)?
+  } else {
+    gross.amount.checked_sub(&fee_amount)?
+  };
+  Some(CommodityAmount {
+    commodity: gross.commodity.clone(),
+    amount,
+  })
+}
+
+/// Whether a lot cost annotation is per unit (`{ ... }`) or for the whole posting (`{{ ... }}`).
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CostKind {
+  #[default]
+  PerUnit,
+  Total,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum LotValue {
+  Amount(CommodityAmount),
+  Date(String),
+  Text(String),
+}
+
+/// Structured contents of a `{ ... }` / `{{ ... }}` annotation.
+///
+/// `date:` and `lot:` (or a positional datetime / quoted label) are lifted into typed fields;
+/// every other `key:value` pair (`fee`, `fee_to`, `venue`, `method`, ...) lands in `fields`.
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct LotCost {
+  pub kind: CostKind,
+  pub amount: Option<CommodityAmount>,
+  pub date: Option<String>,
+  pub label: Option<String>,
+  pub lot_id: Option<String>,
+  /// Positional identifiers without a key, e.g. `{ 30000 USD, kraken }`.
+  pub tags: Vec<String>,
+  /// Positional amounts after the primary cost amount.
+  pub extra_amounts: Vec<CommodityAmount>,
+  pub fields: BTreeMap<String, LotValue>,
+}
+
+/// Whether a price annotation is per unit (`@`) or for the whole posting (`@@`).
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceKind {
+  PerUnit,
+  Total,
 }
 
 #[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PriceAnnotation {
+  pub kind: PriceKind,
+  pub amount: CommodityAmount,
+}
+
+impl LotCost {
+  pub fn field(&self, key: &str) -> Option<&LotValue> {
+    self.fields.get(key)
+  }
+
+  /// The `fee:` field, if it holds an amount.
+  pub fn fee(&self) -> Option<&CommodityAmount> {
+    match self.field("fee") {
+      Some(LotValue::Amount(fee)) => Some(fee),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
 pub struct Transaction {
+  /// 1-based line number of the header.
+  pub line: usize,
+  /// Source path, set when parsing a set of files with [`parse_sources`].
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub file: Option<String>,
   pub date: String,
   pub datetime: String,
   pub status: Option<char>,
   pub payee: Option<String>,
   pub narration: Option<String>,
+  /// Raw text of the header meta comment (everything after `;`).
   pub meta: Option<String>,
+  /// Header meta comment parsed into `key:value` tags.
+  pub tags: Meta,
   pub postings: Vec<Posting>,
 }
 
+impl Transaction {
+  /// The `txn:<ID>` tag from the header, if present and non-empty.
+  pub fn txn_id(&self) -> Option<&str> {
+    self.tags.get("txn").filter(|id| !id.is_empty())
+  }
+}
+
+/// Ordered `key:value` tags from a `; ...` meta comment. Keys are unique; the first occurrence
+/// wins. Serializes as a JSON object in source order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Meta {
+  entries: Vec<(String, String)>,
+}
+
+impl Meta {
+  pub fn get(&self, key: &str) -> Option<&str> {
+    self
+      .entries
+      .iter()
+      .find(|(k, _)| k == key)
+      .map(|(_, v)| v.as_str())
+  }
+
+  pub fn contains_key(&self, key: &str) -> bool {
+    self.entries.iter().any(|(k, _)| k == key)
+  }
+
+  /// Appends a tag, returning `false` (and leaving the map unchanged) if `key` already exists.
+  pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) -> bool {
+    let key = key.into();
+    if self.contains_key(&key) {
+      return false;
+    }
+    self.entries.push((key, value.into()));
+    true
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+    self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+}
+
+impl Serialize for Meta {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    use serde::ser::SerializeMap;
+    let mut map = serializer.serialize_map(Some(self.entries.len()))?;
+    for (k, v) in &self.entries {
+      map.serialize_entry(k, v)?;
+    }
+    map.end()
+  }
+}
+
 #[derive(Debug, Clone, Serialize, PartialEq)]
 pub struct CommodityAmount {
   pub commodity: String,
-  pub amount: f64,
+  pub amount: Amount,
 }
 
 #[derive(Debug, Clone, Serialize, PartialEq)]
 pub struct AccountBalance {
   pub account: String,
+  /// Totals per commodity, rounded to the declared precision of each commodity.
   pub totals: Vec<CommodityAmount>,
+  /// Display text per commodity, using the declared symbol and precision.
+  pub display: BTreeMap<String, String>,
 }
 
-#[derive(Debug, Clone, Serialize, PartialEq)]
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
 pub struct ParseResult {
+  /// No error diagnostics (warnings are allowed).
   pub ok: bool,
   pub diagnostics: Vec<Diagnostic>,
   pub transactions: Vec<Transaction>,
+  /// Balances of open accounts.
   pub balances: Vec<AccountBalance>,
+  /// Final balances of accounts declared with `close`, kept for historical reports.
+  pub closed_balances: Vec<AccountBalance>,
+  pub accounts: Vec<AccountDeclaration>,
+  pub commodities: Vec<CommodityDeclaration>,
+  pub prices: Vec<PriceDirective>,
+  /// Strict mode was on, from [`ParseOptions::strict`] or an `option strict` directive.
+  pub strict: bool,
+  /// Type of every declared or posted-to account (see [`ParseOptions::account_types`]).
+  pub account_types: BTreeMap<String, AccountType>,
+  /// From [`ParseOptions::fee_policy`].
+  pub fee_policy: FeePolicy,
+}
+
+impl ParseResult {
+  pub fn commodity(&self, commodity: &str) -> Option<&CommodityDeclaration> {
+    self.commodities.iter().find(|c| c.commodity == commodity)
+  }
+
+  /// Formats `amount` with the declared symbol and precision of `commodity`, if declared.
+  pub fn format_amount(&self, commodity: &str, amount: Amount) -> String {
+    match self.commodity(commodity) {
+      Some(decl) => decl.format(amount),
+      None => format!("{amount} {commodity}"),
+    }
+  }
+}
+
+/// Accepted syntax for `txn:<ID>` values when txn ids are required.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TxnIdSyntax {
+  /// Any non-empty meta value.
+  #[default]
+  Any,
+  /// 26-character Crockford base32 ULID, e.g. `01J2N9R9XK7Q4W3M5T8V6Y2Z1A`.
+  Ulid,
+}
+
+/// Account a `fee:` lot field is expensed to when the annotation has no `fee_to:` field.
+pub const DEFAULT_FEE_ACCOUNT: &str = "expenses:fees";
+
+/// How `fee:` lot fields are booked.
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FeePolicy {
+  /// The fee is posted to the `fee_to:` account (default [`DEFAULT_FEE_ACCOUNT`]): the posting
+  /// is synthesized unless the transaction already writes it.
+  #[default]
+  Expense,
+  /// A fee in the cost commodity is part of the trade: it's added to the cost basis of a lot
+  /// bought and deducted from the proceeds of a lot sold. Fees in other commodities are still
+  /// expensed.
+  Capitalize,
+}
+
+/// Knobs for the semantic pass run after parsing.
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+  /// Per-commodity tolerance for transaction balancing. Commodities without an entry use half a
+  /// unit of the most precise amount written in that commodity (e.g. `0.005` for `10.00 USD`).
+  pub balance_tolerances: BTreeMap<String, Amount>,
+  /// Strict mode for `txn:<ID>`: every header must carry one, ids must match `txn_id_syntax`,
+  /// and an id may only be used once across everything parsed together.
+  pub require_txn_ids: bool,
+  pub txn_id_syntax: TxnIdSyntax,
+  /// Every posting account must be declared with `account` and every commodity with
+  /// `commodity`, and posting amounts may not be written with more decimal places than the
+  /// declared precision. A file can also opt in with an `option strict` directive, which
+  /// applies to everything parsed together with it.
+  pub strict: bool,
+  /// Account type of each root. An account declaration tagged `type:<TYPE>` overrides the root
+  /// for itself and its sub-accounts; an account that maps to no type is an error.
+  pub account_types: AccountTypes,
+  pub fee_policy: FeePolicy,
+  /// Skip `= <amount>` balance assertions, for text checked apart from the ledger it joins.
+  pub skip_balance_assertions: bool,
+}
+
+/// A named file to parse as part of a set (see [`parse_sources`]).
+#[derive(Debug, Clone)]
+pub struct LedgerSource {
+  pub path: String,
+  pub contents: String,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct AccountDeclaration {
+  /// 1-based line number of the `account` line.
+  pub line: usize,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub file: Option<String>,
+  pub account: String,
+  pub default_commodity: Option<String>,
+  /// `open <DATE>`: postings dated before this are rejected.
+  pub open_date: Option<String>,
+  /// `close <DATE>`: postings dated after this are rejected, and the account is listed in
+  /// [`ParseResult::closed_balances`] rather than [`ParseResult::balances`].
+  pub close_date: Option<String>,
+  /// `opening [DATE] <amount> [COMMODITY]` entries, at most one per commodity.
+  pub openings: Vec<Opening>,
+  /// Meta tags from the `account` line and its indented entries, in source order.
+  pub tags: Meta,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CommodityKind {
+  Fiat,
+  Crypto,
+  Stock,
+}
+
+/// A `commodity` directive:
+///
+/// ```text
+/// commodity USD ; region:us
+///     precision 2
+///     symbol "$"
+///     name "US Dollar"
+///     kind fiat
+/// ```
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct CommodityDeclaration {
+  /// 1-based line number of the `commodity` line.
+  pub line: usize,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub file: Option<String>,
+  pub commodity: String,
+  /// Decimal places used when displaying amounts (and the most allowed in strict mode).
+  pub precision: Option<u32>,
+  pub symbol: Option<String>,
+  pub name: Option<String>,
+  pub kind: Option<CommodityKind>,
+  /// Meta tags from the `commodity` line and its indented entries, in source order.
+  pub tags: Meta,
+}
+
+impl CommodityDeclaration {
+  /// Rounds `amount` to the declared precision, if any. An amount too large to be padded to
+  /// that precision is returned as is.
+  pub fn round(&self, amount: Amount) -> Amount {
+    match self.precision {
+      Some(precision) => amount.checked_rescale(precision).unwrap_or(amount),
+      None => amount,
+    }
+  }
+
+  /// Formats `amount` for display: `-$1234.50` with a symbol, `1234.50 USD` without.
+  pub fn format(&self, amount: Amount) -> String {
+    let amount = self.round(amount);
+    match self.symbol.as_deref() {
+      Some(symbol) if amount.is_negative() => format!("-{symbol}{}", amount.abs()),
+      Some(symbol) => format!("{symbol}{amount}"),
+      None => format!("{amount} {}", self.commodity),
+    }
+  }
+}
+
+/// A `price` directive: the market price of one unit of `commodity`, e.g.
+/// `price 2026-01-15 BTC 98000.00 AUD ; src:coingecko`.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PriceDirective {
+  /// 1-based line number of the directive.
+  pub line: usize,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub file: Option<String>,
+  pub date: String,
+  pub datetime: String,
+  pub commodity: String,
+  /// Price of one unit of `commodity`, in the quote commodity.
+  pub price: CommodityAmount,
+  pub tags: Meta,
+}
+
+/// Opening balance of an account in one commodity.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Opening {
+  /// Date the balance applies from; undated openings apply before every transaction.
+  pub date: Option<String>,
+  pub commodity: String,
+  pub amount: Amount,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-struct AccountDeclaration {
-  account: String,
-  default_commodity: Option<String>,
-  opening: Option<CommodityAmount>,
+/// Output of the line-by-line pass over one file, before checks that span transactions.
+#[derive(Debug, Default)]
+struct Document {
+  diagnostics: Vec<Diagnostic>,
+  transactions: Vec<Transaction>,
+  account_declarations: Vec<AccountDeclaration>,
+  commodity_declarations: Vec<CommodityDeclaration>,
+  prices: Vec<PriceDirective>,
+  /// The file contains an `option strict` directive.
+  strict: bool,
 }
 
 fn diag(line: usize, column: usize, message: impl Into<String>) -> Diagnostic {
@@ -61,6 +520,15 @@ fn diag(line: usize, column: usize, message: impl Into<String>) -> Diagnostic {
     line,
     column,
     message: message.into(),
+    file: None,
+    severity: Severity::Error,
+  }
+}
+
+fn warning(line: usize, column: usize, message: impl Into<String>) -> Diagnostic {
+  Diagnostic {
+    severity: Severity::Warning,
+    ..diag(line, column, message)
   }
 }
 
@@ -94,6 +562,37 @@ fn commodity_re() -> Regex {
   Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").expect("commodity regex")
 }
 
+fn datetime_re() -> Regex {
+  Regex::new(r"^\d{4}-\d{2}-\d{2}(?:T\d{2}:\d{2}:\d{2}(?:\.\d{1,6})?(?:Z|[+-]\d{2}:\d{2})?)?$")
+    .expect("datetime regex")
+}
+
+fn bare_value_re() -> Regex {
+  // Unquoted values: identifiers, numbers, pathlike strings and datetimes.
+  Regex::new(r"^[A-Za-z0-9_./:+-]+$").expect("bare value regex")
+}
+
+/// Compiled token patterns shared by the line parsers.
+struct Patterns {
+  account: Regex,
+  amount: Regex,
+  commodity: Regex,
+  datetime: Regex,
+  bare_value: Regex,
+}
+
+impl Patterns {
+  fn new() -> Self {
+    Self {
+      account: account_re(),
+      amount: amount_re(),
+      commodity: commodity_re(),
+      datetime: datetime_re(),
+      bare_value: bare_value_re(),
+    }
+  }
+}
+
 fn take_token(input: &str) -> Option<(String, &str)> {
   let trimmed = input.trim_start_matches([' ', '\t']);
   if trimmed.is_empty() {
@@ -163,115 +662,1581 @@ fn parse_header_fields(header_after_datetime: &str) -> (Option<char>, Option<Str
   (status, payee, narration)
 }
 
-fn flush_transaction(
-  current: &mut Option<(usize, Transaction)>,
+/// Splits off the next whitespace-delimited word, advancing `input` past it.
+fn next_word<'a>(input: &mut &'a str) -> Option<&'a str> {
+  let trimmed = input.trim_start_matches([' ', '\t']);
+  if trimmed.is_empty() {
+    *input = trimmed;
+    return None;
+  }
+  let end = trimmed.find([' ', '\t']).unwrap_or(trimmed.len());
+  *input = &trimmed[end..];
+  Some(&trimmed[..end])
+}
+
+/// Byte offset of the first `needle` outside double quotes.
+fn find_unquoted(input: &str, needle: &str) -> Option<usize> {
+  let bytes = input.as_bytes();
+  let mut in_quote = false;
+  let mut i = 0;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'\\' if in_quote => i += 1,
+      b'"' => in_quote = !in_quote,
+      _ if !in_quote && bytes[i..].starts_with(needle.as_bytes()) => return Some(i),
+      _ => {}
+    }
+    i += 1;
+  }
+  None
+}
+
+/// Splits on `sep` outside double quotes, returning each piece with its byte offset.
+fn split_unquoted(input: &str, sep: char) -> Vec<(usize, &str)> {
+  let mut out = Vec::new();
+  let mut start = 0;
+  let sep_text = sep.to_string();
+  while let Some(pos) = find_unquoted(&input[start..], &sep_text) {
+    out.push((start, &input[start..start + pos]));
+    start += pos + sep.len_utf8();
+  }
+  out.push((start, &input[start..]));
+  out
+}
+
+/// Parses a complete quoted string (`"..."`), rejecting trailing text.
+fn parse_quoted(text: &str) -> Option<String> {
+  if !text.starts_with('"') || text.len() < 2 || !text.ends_with('"') {
+    return None;
+  }
+  match take_token(text) {
+    Some((value, rest)) if rest.trim().is_empty() => Some(value),
+    _ => None,
+  }
+}
+
+/// Parses `<amount> <COMMODITY>` as used inside annotations, e.g. `0.10 USD`.
+fn parse_amount_spec(
+  text: &str,
+  line_no: usize,
+  column: usize,
+  patterns: &Patterns,
   diagnostics: &mut Vec<Diagnostic>,
-  transactions: &mut Vec<Transaction>,
-) {
-  if let Some((header_line, txn)) = current.take() {
-    if txn.postings.is_empty() {
-      diagnostics.push(diag(header_line, 0, "transaction missing postings"));
+) -> Option<CommodityAmount> {
+  let mut rest = text;
+  let amount_text = next_word(&mut rest)?;
+  if !patterns.amount.is_match(amount_text) {
+    return None;
+  }
+  let Some(commodity) = next_word(&mut rest) else {
+    diagnostics.push(diag(line_no, column, format!("missing commodity after amount {amount_text}")));
+    return None;
+  };
+  if next_word(&mut rest).is_some() {
+    return None;
+  }
+  if !patterns.commodity.is_match(commodity) {
+    diagnostics.push(diag(line_no, column, format!("invalid commodity: {commodity}")));
+    return None;
+  }
+  match amount_text.parse::<Amount>() {
+    Ok(amount) => Some(CommodityAmount {
+      commodity: commodity.to_string(),
+      amount,
+    }),
+    Err(e) => {
+      diagnostics.push(diag(line_no, column, e.to_string()));
+      None
     }
-    transactions.push(txn);
   }
 }
 
-pub fn parse_transactions(contents: &str) -> ParseResult {
-  let header_re = header_datetime_re();
-  let account_re = account_re();
-  let amount_re = amount_re();
-  let commodity_re = commodity_re();
+fn parse_lot_value(
+  key: &str,
+  text: &str,
+  line_no: usize,
+  column: usize,
+  patterns: &Patterns,
+  diagnostics: &mut Vec<Diagnostic>,
+) -> Option<LotValue> {
+  if text.is_empty() {
+    diagnostics.push(diag(line_no, column, format!("lot field {key} missing value")));
+    return None;
+  }
+  if text.starts_with('"') {
+    let value = parse_quoted(text);
+    if value.is_none() {
+      diagnostics.push(diag(line_no, column, format!("invalid quoted value for lot field {key}")));
+    }
+    return value.map(LotValue::Text);
+  }
+  if patterns.datetime.is_match(text) {
+    return Some(LotValue::Date(text.to_string()));
+  }
+  if text.contains([' ', '\t']) {
+    let before = diagnostics.len();
+    let amount = parse_amount_spec(text, line_no, column, patterns, diagnostics);
+    if amount.is_none() && diagnostics.len() == before {
+      diagnostics.push(diag(line_no, column, format!("invalid value for lot field {key}: {text}")));
+    }
+    return amount.map(LotValue::Amount);
+  }
+  if patterns.bare_value.is_match(text) {
+    return Some(LotValue::Text(text.to_string()));
+  }
+  diagnostics.push(diag(line_no, column, format!("invalid value for lot field {key}: {text}")));
+  None
+}
 
-  let mut diagnostics: Vec<Diagnostic> = Vec::new();
-  let mut current: Option<(usize, Transaction)> = None;
-  let mut current_account: Option<(usize, AccountDeclaration)> = None;
-  let mut transactions: Vec<Transaction> = Vec::new();
-  let mut account_declarations: Vec<AccountDeclaration> = Vec::new();
+fn apply_lot_field(
+  cost: &mut LotCost,
+  position: usize,
+  field: &str,
+  line_no: usize,
+  column: usize,
+  patterns: &Patterns,
+  diagnostics: &mut Vec<Diagnostic>,
+) {
+  if field.starts_with('"') {
+    let Some(label) = parse_quoted(field) else {
+      diagnostics.push(diag(line_no, column, format!("invalid lot field: {field}")));
+      return;
+    };
+    if cost.label.is_some() {
+      diagnostics.push(diag(line_no, column, "duplicate lot label"));
+      return;
+    }
+    cost.label = Some(label);
+    return;
+  }
 
-  for (idx, raw_line) in contents.lines().enumerate() {
-    let line_no = idx + 1;
-    let line = raw_line.trim_end_matches('\r');
+  if patterns.datetime.is_match(field) {
+    if cost.date.is_some() {
+      diagnostics.push(diag(line_no, column, "duplicate lot date"));
+      return;
+    }
+    cost.date = Some(field.to_string());
+    return;
+  }
 
-    if is_blank(line) {
-      flush_transaction(&mut current, &mut diagnostics, &mut transactions);
-      if let Some((_, decl)) = current_account.take() {
-        account_declarations.push(decl);
+  if field.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+') {
+    let before = diagnostics.len();
+    match parse_amount_spec(field, line_no, column, patterns, diagnostics) {
+      Some(amount) if position == 0 => cost.amount = Some(amount),
+      Some(amount) => cost.extra_amounts.push(amount),
+      None if diagnostics.len() == before => {
+        diagnostics.push(diag(line_no, column, format!("invalid lot field: {field}")));
       }
-      continue;
+      None => {}
     }
+    return;
+  }
 
-    if is_directive(line) {
-      continue;
+  let Some((key, value_text)) = field.split_once(':') else {
+    if patterns.commodity.is_match(field) {
+      cost.tags.push(field.to_string());
+    } else {
+      diagnostics.push(diag(line_no, column, format!("invalid lot field: {field}")));
     }
+    return;
+  };
 
-    if line == "account" || line.starts_with("account ") || line.starts_with("account\t") {
-      flush_transaction(&mut current, &mut diagnostics, &mut transactions);
-      if let Some((_, decl)) = current_account.take() {
-        account_declarations.push(decl);
-      }
+  let key = key.trim();
+  if !patterns.commodity.is_match(key) {
+    diagnostics.push(diag(line_no, column, format!("invalid lot field key: {key}")));
+    return;
+  }
 
-      let before_meta = line.split_once(';').map(|(l, _)| l).unwrap_or(line);
-      let mut parts = before_meta.split_whitespace();
-      let kw = parts.next().unwrap_or_default();
-      if kw != "account" {
-        diagnostics.push(diag(
-          line_no,
-          0,
-          "invalid line: expected transaction header, posting, or directive",
-        ));
-        continue;
-      }
-      let Some(account) = parts.next() else {
-        diagnostics.push(diag(line_no, 0, "account declaration missing account path"));
-        continue;
-      };
+  let value_column = column + key.len() + 1;
+  let Some(value) = parse_lot_value(key, value_text.trim(), line_no, value_column, patterns, diagnostics) else {
+    return;
+  };
 
-      if !account_re.is_match(account) {
-        diagnostics.push(diag(
-          line_no,
-          0,
-          format!("invalid account path: {account}"),
-        ));
+  match (key, value) {
+    ("date", LotValue::Date(date)) => {
+      if cost.date.is_some() {
+        diagnostics.push(diag(line_no, column, "duplicate lot date"));
+      } else {
+        cost.date = Some(date);
       }
-
-      let default_commodity = parts.next().map(|s| s.to_string());
-      if let Some(c) = default_commodity.as_deref() {
-        if !commodity_re.is_match(c) {
-          diagnostics.push(diag(line_no, 0, format!("invalid commodity: {c}")));
-        }
+    }
+    ("date", _) => {
+      diagnostics.push(diag(line_no, value_column, "lot date must be a date or datetime"));
+    }
+    ("lot", LotValue::Text(id)) => {
+      if cost.lot_id.is_some() {
+        diagnostics.push(diag(line_no, column, "duplicate lot id"));
+      } else {
+        cost.lot_id = Some(id);
       }
-
-      if parts.next().is_some() {
-        diagnostics.push(diag(
-          line_no,
-          0,
-          "unexpected extra tokens in account declaration (expected: account <path> [COMMODITY])",
-        ));
+    }
+    ("lot", _) => {
+      diagnostics.push(diag(line_no, value_column, "lot id must be an identifier"));
+    }
+    ("fee", LotValue::Date(_) | LotValue::Text(_)) => {
+      diagnostics.push(diag(line_no, value_column, "lot fee must be an amount, e.g. fee:0.10 USD"));
+    }
+    ("fee_to", LotValue::Text(account)) if !patterns.account.is_match(&account) => {
+      diagnostics.push(diag(line_no, value_column, "lot fee_to must be an account"));
+    }
+    ("fee_to", LotValue::Amount(_) | LotValue::Date(_)) => {
+      diagnostics.push(diag(line_no, value_column, "lot fee_to must be an account"));
+    }
+    (key, value) => {
+      if cost.fields.contains_key(key) {
+        diagnostics.push(diag(line_no, column, format!("duplicate lot field: {key}")));
+      } else {
+        cost.fields.insert(key.to_string(), value);
       }
-
-      current_account = Some((
-        line_no,
-        AccountDeclaration {
-          account: account.to_string(),
-          default_commodity,
-          opening: None,
-        },
-      ));
-      continue;
+    }
+  }
+}
+
+/// Parses a leading `{ ... }` or `{{ ... }}` annotation from `input` (which must already be
+/// trimmed), returning the lot cost (if any) and the unparsed text after the closing brace.
+fn parse_lot_cost<'a>(
+  input: &'a str,
+  line_no: usize,
+  column: usize,
+  patterns: &Patterns,
+  diagnostics: &mut Vec<Diagnostic>,
+) -> (Option<LotCost>, &'a str) {
+  let (kind, open, close) = if input.starts_with("{{") {
+    (CostKind::Total, "{{", "}}")
+  } else if input.starts_with('{') {
+    (CostKind::PerUnit, "{", "}")
+  } else {
+    return (None, input);
+  };
+
+  let body_and_rest = &input[open.len()..];
+  let Some(end) = find_unquoted(body_and_rest, close) else {
+    diagnostics.push(diag(
+      line_no,
+      column,
+      format!("unterminated lot cost annotation (expected '{close}')"),
+    ));
+    return (None, "");
+  };
+  let body = &body_and_rest[..end];
+  let rest = &body_and_rest[end + close.len()..];
+
+  let mut cost = LotCost {
+    kind,
+    ..LotCost::default()
+  };
+  if body.trim().is_empty() {
+    return (Some(cost), rest);
+  }
+
+  for (position, (offset, raw)) in split_unquoted(body, ',').into_iter().enumerate() {
+    let field = raw.trim();
+    let field_column = column + open.len() + offset + (raw.len() - raw.trim_start().len());
+    if field.is_empty() {
+      diagnostics.push(diag(line_no, field_column, "empty lot field"));
+      continue;
+    }
+    apply_lot_field(&mut cost, position, field, line_no, field_column, patterns, diagnostics);
+  }
+
+  (Some(cost), rest)
+}
+
+/// Parses a leading `@ <amount>` or `@@ <amount>` annotation from `input` (already trimmed),
+/// returning the price (if any) and the unparsed text from the balance assertion or meta
+/// comment onwards.
+fn parse_price<'a>(
+  input: &'a str,
+  line_no: usize,
+  column: usize,
+  posting_commodity: &str,
+  patterns: &Patterns,
+  diagnostics: &mut Vec<Diagnostic>,
+) -> (Option<PriceAnnotation>, &'a str) {
+  let (kind, marker) = if input.starts_with("@@") {
+    (PriceKind::Total, "@@")
+  } else if input.starts_with('@') {
+    (PriceKind::PerUnit, "@")
+  } else {
+    return (None, input);
+  };
+
+  let after_marker = &input[marker.len()..];
+  let end = [";", "="]
+    .iter()
+    .filter_map(|stop| find_unquoted(after_marker, stop))
+    .min()
+    .unwrap_or(after_marker.len());
+  let spec = after_marker[..end].trim();
+  let rest = &after_marker[end..];
+  let spec_column = column + marker.len() + (after_marker.len() - after_marker.trim_start().len());
+
+  if spec.is_empty() {
+    diagnostics.push(diag(line_no, column, format!("price annotation '{marker}' missing amount")));
+    return (None, rest);
+  }
+
+  let before = diagnostics.len();
+  let Some(amount) = parse_amount_spec(spec, line_no, spec_column, patterns, diagnostics) else {
+    if diagnostics.len() == before {
+      diagnostics.push(diag(line_no, spec_column, format!("invalid price annotation: {spec}")));
+    }
+    return (None, rest);
+  };
+
+  if amount.amount.is_negative() {
+    diagnostics.push(diag(line_no, spec_column, format!("price must not be negative: {spec}")));
+  }
+  if amount.commodity == posting_commodity {
+    diagnostics.push(diag(
+      line_no,
+      spec_column,
+      format!("price commodity must differ from posting commodity: {posting_commodity}"),
+    ));
+  }
+
+  (Some(PriceAnnotation { kind, amount }), rest)
+}
+
+/// Scale of the most precise amount written in `commodity`, preferring posting amounts over
+/// cost/price annotations.
+fn written_scale(txn: &Transaction, commodity: &str) -> u32 {
+  let units = txn
+    .postings
+    .iter()
+    .filter(|p| p.commodity == commodity)
+    .map(|p| p.amount.scale())
+    .max();
+  units
+    .or_else(|| {
+      txn
+        .postings
+        .iter()
+        .flat_map(|p| {
+          let cost = p.lot_cost.as_ref().and_then(|c| c.amount.as_ref());
+          let price = p.price.as_ref().map(|pr| &pr.amount);
+          cost.into_iter().chain(price)
+        })
+        .filter(|a| a.commodity == commodity)
+        .map(|a| a.amount.scale())
+        .max()
+    })
+    .unwrap_or(0)
+}
+
+/// Sums posting weights per commodity (see [`Posting::trade_weight`]).
+///
+/// Fails with the message to report when a weight or a sum is out of range.
+fn residuals<'a>(
+  postings: impl IntoIterator<Item = &'a Posting>,
+  policy: FeePolicy,
+) -> Result<BTreeMap<String, Amount>, String> {
+  let mut residuals: BTreeMap<String, Amount> = BTreeMap::new();
+  let mut add = |commodity: &str, amount: &Amount| {
+    let residual = residuals.entry(commodity.to_string()).or_default();
+    *residual = residual
+      .checked_add(amount)
+      .ok_or_else(|| format!("transaction total out of range in {commodity}"))?;
+    Ok::<(), String>(())
+  };
+  for posting in postings {
+    let weight = posting.trade_weight(policy).ok_or_else(|| {
+      format!(
+        "amount out of range: {} {} at its cost or price (line {})",
+        posting.amount, posting.commodity, posting.line
+      )
+    })?;
+    add(&weight.commodity, &weight.amount)?;
+  }
+  Ok(residuals)
+}
+
+/// Checks the `fee:`/`fee_to:` lot fields of `txn` against its postings and, for expensed
+/// fees, appends the implied postings to each fee account unless the transaction already posts
+/// to it. Written postings must add up to every lot fee charged to that account.
+fn apply_fees(txn: &mut Transaction, policy: FeePolicy, diagnostics: &mut Vec<Diagnostic>) {
+  let is_written = |p: &Posting, account: &str, commodity: &str| {
+    !p.inferred && p.account == account && p.commodity == commodity
+  };
+  // Expensed fees by (fee account, commodity), with the line of each lot charging one.
+  let mut expensed: BTreeMap<(String, String), Vec<(usize, Amount)>> = BTreeMap::new();
+  for posting in &txn.postings {
+    let Some(cost) = &posting.lot_cost else {
+      continue;
+    };
+    let fee_to = match cost.field("fee_to") {
+      Some(LotValue::Text(account)) => Some(account.as_str()),
+      _ => None,
+    };
+    let Some(fee) = cost.fee() else {
+      if fee_to.is_some() && cost.field("fee").is_none() {
+        diagnostics.push(diag(posting.line, 0, "lot field fee_to without a fee"));
+      }
+      continue;
+    };
+
+    let account = fee_to.unwrap_or(DEFAULT_FEE_ACCOUNT);
+    let amount = fee.amount.abs();
+    let commodity = &fee.commodity;
+    if posting.capitalized_fee(policy).is_some() {
+      if txn.postings.iter().any(|p| is_written(p, account, commodity)) {
+        let message = format!(
+          "fee {amount} {commodity} is capitalized into the lot cost but also posted to {account}"
+        );
+        diagnostics.push(diag(posting.line, 0, message));
+      }
+      continue;
+    }
+    let lots = expensed.entry((account.to_string(), commodity.clone())).or_default();
+    lots.push((posting.line, amount));
+  }
+
+  let mut implied = Vec::new();
+  for ((account, commodity), lots) in expensed {
+    let written: Vec<&Posting> =
+      txn.postings.iter().filter(|p| is_written(p, &account, &commodity)).collect();
+    if written.is_empty() {
+      implied.extend(lots.into_iter().map(|(line, amount)| Posting {
+        line,
+        account: account.clone(),
+        amount,
+        amount_text: amount.to_string(),
+        commodity: commodity.clone(),
+        fee_for: Some(line),
+        ..Posting::default()
+      }));
+      continue;
+    }
+    let first = lots[0].0;
+    let expected = lots.iter().try_fold(Amount::ZERO, |total, (_, fee)| total.checked_add(fee));
+    let paid = written.iter().try_fold(Amount::ZERO, |total, p| total.checked_add(&p.amount));
+    let message = match expected.zip(paid) {
+      None => format!("fees to {account} out of range in {commodity}"),
+      Some((expected, paid)) if paid == expected => continue,
+      Some((expected, paid)) if lots.len() == 1 => format!(
+        "fee {expected} {commodity} disagrees with the {paid} {commodity} posted to {account}"
+      ),
+      Some((expected, paid)) => format!(
+        "fees {expected} {commodity} of {} lots disagree with the {paid} {commodity} posted to \
+         {account}",
+        lots.len()
+      ),
+    };
+    diagnostics.push(diag(first, 0, message));
+  }
+  txn.postings.extend(implied);
+}
+
+/// Note for an unbalanced transaction whose residual in a commodity is exactly its `fee:` lot
+/// fields: fees that no posting pays. `unbalanced` holds each residual and its display scale.
+fn unpaid_fee_note(txn: &Transaction, unbalanced: &[(String, Amount, u32)]) -> String {
+  let mut fees: BTreeMap<&str, Option<Amount>> = BTreeMap::new();
+  for fee in txn.postings.iter().filter_map(|p| p.lot_cost.as_ref()?.fee()) {
+    let total = fees.entry(&fee.commodity).or_insert(Some(Amount::ZERO));
+    *total = total.and_then(|total| total.checked_add(&fee.amount.abs()));
+  }
+  let unpaid: Vec<String> = unbalanced
+    .iter()
+    .filter(|(commodity, residual, _)| fees.get(commodity.as_str()) == Some(&Some(*residual)))
+    .map(|(commodity, residual, scale)| {
+      format!("{} {commodity}", residual.checked_rescale(*scale).unwrap_or(*residual))
+    })
+    .collect();
+  if unpaid.is_empty() {
+    String::new()
+  } else {
+    format!("; no posting pays the lot fee of {}", unpaid.join(", "))
+  }
+}
+
+/// Fills in the amount of the (single) posting written without one, so the transaction
+/// balances. A residual in several commodities splits it into one posting per commodity.
+fn infer_elided_amounts(
+  txn: &mut Transaction,
+  policy: FeePolicy,
+  diagnostics: &mut Vec<Diagnostic>,
+) {
+  let mut elided = txn.postings.iter().filter(|p| p.inferred);
+  let Some(first) = elided.next() else {
+    return;
+  };
+  let first_line = first.line;
+
+  for extra in elided {
+    diagnostics.push(diag(
+      extra.line,
+      4 + extra.account.len() + 1,
+      "missing amount (only one posting per transaction may omit its amount)",
+    ));
+  }
+
+  let residuals = residuals(txn.postings.iter().filter(|p| !p.inferred), policy);
+  let index = txn.postings.iter().position(|p| p.line == first_line).unwrap_or_default();
+  let template = txn.postings[index].clone();
+  txn.postings.retain(|p| !p.inferred);
+  // Out of range amounts are reported once, by the balance check.
+  let Ok(residuals) = residuals else {
+    return;
+  };
+
+  if residuals.is_empty() {
+    diagnostics.push(diag(
+      template.line,
+      4 + template.account.len() + 1,
+      "missing amount (no other postings to infer it from)",
+    ));
+    return;
+  }
+
+  let mut inferred: Vec<(String, Amount)> = residuals
+    .iter()
+    .filter(|(_, residual)| !residual.is_zero())
+    .map(|(commodity, residual)| {
+      let amount = -*residual;
+      let scale = max_written_scale(txn, commodity);
+      (commodity.clone(), amount.checked_rescale(scale).unwrap_or(amount))
+    })
+    .collect();
+  if inferred.is_empty() {
+    // Already balanced: the posting carries a zero amount in the transaction's first commodity.
+    let commodity = residuals.keys().next().cloned().unwrap_or_default();
+    inferred.push((commodity, Amount::ZERO));
+  }
+
+  // A balance assertion is kept on the last part only, so it is checked once all are applied.
+  let last = inferred.len() - 1;
+  let postings = inferred.into_iter().enumerate().map(|(i, (commodity, amount))| {
+    let mut posting = Posting {
+      amount,
+      amount_text: amount.to_string(),
+      commodity,
+      ..template.clone()
+    };
+    if i != last {
+      posting.balance_assertion = None;
+      posting.remainder = None;
+    }
+    posting
+  });
+  txn.postings.splice(index..index, postings);
+}
+
+/// Largest number of fractional digits written for `commodity` anywhere in the transaction.
+fn max_written_scale(txn: &Transaction, commodity: &str) -> u32 {
+  txn
+    .postings
+    .iter()
+    .flat_map(|p| {
+      let units = (p.commodity == commodity).then_some(p.amount.scale());
+      let cost = p.lot_cost.as_ref().and_then(|c| c.amount.as_ref());
+      let fee = match p.lot_cost.as_ref().and_then(|c| c.field("fee")) {
+        Some(LotValue::Amount(fee)) => Some(fee),
+        _ => None,
+      };
+      let price = p.price.as_ref().map(|pr| &pr.amount);
+      let annotations = cost
+        .into_iter()
+        .chain(fee)
+        .chain(price)
+        .filter(|a| a.commodity == commodity)
+        .map(|a| a.amount.scale());
+      units.into_iter().chain(annotations)
+    })
+    .max()
+    .unwrap_or(0)
+}
+
+/// Reports any per-commodity residual of the posting weights outside tolerance.
+fn check_balance(
+  header_line: usize,
+  txn: &Transaction,
+  options: &ParseOptions,
+  diagnostics: &mut Vec<Diagnostic>,
+) {
+  let residuals = match residuals(&txn.postings, options.fee_policy) {
+    Ok(residuals) => residuals,
+    Err(message) => {
+      diagnostics.push(diag(header_line, 0, message));
+      return;
+    }
+  };
+  let unbalanced: Vec<(String, Amount, u32)> = residuals
+    .into_iter()
+    .filter_map(|(commodity, residual)| {
+      let scale = written_scale(txn, &commodity);
+      let tolerance = options
+        .balance_tolerances
+        .get(&commodity)
+        .copied()
+        .unwrap_or_else(|| Amount::new(5, scale + 1));
+      (residual.abs() > tolerance).then_some((commodity, residual, scale))
+    })
+    .collect();
+
+  if !unbalanced.is_empty() {
+    let listed: Vec<String> = unbalanced
+      .iter()
+      .map(|(commodity, residual, scale)| {
+        format!("{} {commodity}", residual.checked_rescale(*scale).unwrap_or(*residual))
+      })
+      .collect();
+    let message = format!(
+      "transaction does not balance (residual: {}){}",
+      listed.join(", "),
+      unpaid_fee_note(txn, &unbalanced)
+    );
+    diagnostics.push(diag(header_line, 0, message));
+  }
+}
+
+/// Parses a leading `= <amount>` balance assertion from `input` (already trimmed), returning
+/// the asserted balance (if any) and the unparsed text from the meta comment onwards.
+fn parse_balance_assertion<'a>(
+  input: &'a str,
+  line_no: usize,
+  column: usize,
+  patterns: &Patterns,
+  diagnostics: &mut Vec<Diagnostic>,
+) -> (Option<CommodityAmount>, &'a str) {
+  let Some(after_marker) = input.strip_prefix('=') else {
+    return (None, input);
+  };
+
+  let end = find_unquoted(after_marker, ";").unwrap_or(after_marker.len());
+  let spec = after_marker[..end].trim();
+  let rest = &after_marker[end..];
+  let spec_column = column + 1 + (after_marker.len() - after_marker.trim_start().len());
+
+  if spec.is_empty() {
+    diagnostics.push(diag(line_no, column, "balance assertion missing amount"));
+    return (None, rest);
+  }
+
+  let before = diagnostics.len();
+  let assertion = parse_amount_spec(spec, line_no, spec_column, patterns, diagnostics);
+  if assertion.is_none() && diagnostics.len() == before {
+    diagnostics.push(diag(line_no, spec_column, format!("invalid balance assertion: {spec}")));
+  }
+  (assertion, rest)
+}
+
+/// Parses the tag list of a meta comment (`text` is everything after `;`, starting at `column`).
+fn parse_meta(
+  text: &str,
+  line_no: usize,
+  column: usize,
+  patterns: &Patterns,
+  diagnostics: &mut Vec<Diagnostic>,
+) -> Meta {
+  let mut meta = Meta::default();
+  if text.trim().is_empty() {
+    return meta;
+  }
+
+  for (offset, raw) in split_unquoted(text, ',') {
+    let tag = raw.trim();
+    let tag_column = column + offset + (raw.len() - raw.trim_start().len());
+    if tag.is_empty() {
+      diagnostics.push(diag(line_no, tag_column, "empty meta tag"));
+      continue;
+    }
+
+    let Some((key, value_text)) = tag.split_once(':') else {
+      diagnostics.push(diag(
+        line_no,
+        tag_column,
+        format!("malformed meta tag (expected key:value): {tag}"),
+      ));
+      continue;
+    };
+
+    let (key, value) = if patterns.commodity.is_match(key) {
+      let value_text = value_text.trim();
+      let value = if value_text.starts_with('"') {
+        parse_quoted(value_text)
+      } else if patterns.bare_value.is_match(value_text) {
+        Some(value_text.to_string())
+      } else {
+        None
+      };
+      match value {
+        Some(value) => (key, value),
+        None if value_text.is_empty() => {
+          diagnostics.push(diag(line_no, tag_column, format!("meta tag {key} missing value")));
+          continue;
+        }
+        None => {
+          diagnostics.push(diag(
+            line_no,
+            tag_column + key.len() + 1,
+            format!("invalid value for meta tag {key}: {value_text}"),
+          ));
+          continue;
+        }
+      }
+    } else if patterns.account.is_match(tag) {
+      // A bare pathlike tag such as `2026-q1:review`.
+      (tag, String::new())
+    } else {
+      diagnostics.push(diag(line_no, tag_column, format!("invalid meta tag key: {key}")));
+      continue;
+    };
+
+    if !meta.insert(key, value) {
+      diagnostics.push(diag(line_no, tag_column, format!("duplicate meta tag: {key}")));
+    }
+  }
+
+  meta
+}
+
+fn is_ulid(id: &str) -> bool {
+  id.len() == 26
+    && id.starts_with(|c: char| ('0'..='7').contains(&c))
+    && id
+      .bytes()
+      .all(|b| b.is_ascii_digit() || (b.is_ascii_uppercase() && !b"ILOU".contains(&b)))
+}
+
+fn check_txn_id(
+  header_line: usize,
+  txn: &Transaction,
+  options: &ParseOptions,
+  diagnostics: &mut Vec<Diagnostic>,
+) {
+  if !options.require_txn_ids {
+    return;
+  }
+  let Some(id) = txn.txn_id() else {
+    diagnostics.push(diag(header_line, 0, "missing txn id (expected txn:<ID> in header meta)"));
+    return;
+  };
+  if options.txn_id_syntax == TxnIdSyntax::Ulid && !is_ulid(id) {
+    diagnostics.push(diag(header_line, 0, format!("invalid txn id (expected ULID): {id}")));
+  }
+}
+
+fn location(file: Option<&str>, line: usize) -> String {
+  match file {
+    Some(file) => format!("{file}:{line}"),
+    None => format!("line {line}"),
+  }
+}
+
+/// Reports every reuse of a txn id, pointing back at its first occurrence.
+fn check_duplicate_txn_ids(transactions: &[Transaction], diagnostics: &mut Vec<Diagnostic>) {
+  let mut first_seen: BTreeMap<&str, &Transaction> = BTreeMap::new();
+  for txn in transactions {
+    let Some(id) = txn.txn_id() else {
+      continue;
+    };
+    match first_seen.get(id) {
+      Some(first) => {
+        let mut d = diag(
+          txn.line,
+          0,
+          format!(
+            "duplicate txn id {id} (first used at {})",
+            location(first.file.as_deref(), first.line)
+          ),
+        );
+        d.file = txn.file.clone();
+        diagnostics.push(d);
+      }
+      None => {
+        first_seen.insert(id, txn);
+      }
+    }
+  }
+}
+
+/// Reports commodities declared more than once across everything parsed together.
+fn check_duplicate_commodities(
+  commodities: &[CommodityDeclaration],
+  diagnostics: &mut Vec<Diagnostic>,
+) {
+  let mut first_seen: BTreeMap<&str, &CommodityDeclaration> = BTreeMap::new();
+  for decl in commodities {
+    match first_seen.get(decl.commodity.as_str()) {
+      Some(first) => {
+        let mut d = diag(
+          decl.line,
+          0,
+          format!(
+            "duplicate commodity declaration {} (first declared at {})",
+            decl.commodity,
+            location(first.file.as_deref(), first.line)
+          ),
+        );
+        d.file = decl.file.clone();
+        diagnostics.push(d);
+      }
+      None => {
+        first_seen.insert(&decl.commodity, decl);
+      }
+    }
+  }
+}
+
+/// Optimal string alignment distance: insertions, deletions, substitutions and transpositions
+/// of adjacent characters each cost one.
+fn edit_distance(a: &str, b: &str) -> usize {
+  let a: Vec<char> = a.chars().collect();
+  let b: Vec<char> = b.chars().collect();
+  let mut rows = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+  for (i, row) in rows.iter_mut().enumerate() {
+    row[0] = i;
+  }
+  for (j, cell) in rows[0].iter_mut().enumerate() {
+    *cell = j;
+  }
+  for i in 1..=a.len() {
+    for j in 1..=b.len() {
+      let cost = usize::from(a[i - 1] != b[j - 1]);
+      let mut best = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+      if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+        best = best.min(rows[i - 2][j - 2] + 1);
+      }
+      rows[i][j] = best;
+    }
+  }
+  rows[a.len()][b.len()]
+}
+
+/// Closest declared name to `name`, if it is within a third of its length in edits.
+fn did_you_mean<'a>(name: &str, declared: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+  let limit = (name.chars().count() / 3).max(1);
+  declared
+    .into_iter()
+    .map(|candidate| (edit_distance(name, candidate), candidate))
+    .filter(|(distance, _)| *distance <= limit)
+    .min()
+    .map(|(_, candidate)| candidate)
+}
+
+/// Strict mode: every posting account must be declared with an `account` block.
+fn check_declared_accounts(
+  transactions: &[Transaction],
+  account_declarations: &[AccountDeclaration],
+  diagnostics: &mut Vec<Diagnostic>,
+) {
+  let declared: BTreeSet<&str> = account_declarations.iter().map(|d| d.account.as_str()).collect();
+  for txn in transactions {
+    for posting in &txn.postings {
+      let account = posting.account.as_str();
+      if declared.contains(account) {
+        continue;
+      }
+      let mut message = format!("undeclared account: {account}");
+      if let Some(suggestion) = did_you_mean(account, declared.iter().copied()) {
+        message.push_str(&format!(" (did you mean {suggestion}?)"));
+      }
+      let mut d = diag(posting.line, 4, message);
+      d.file = txn.file.clone();
+      diagnostics.push(d);
+    }
+  }
+}
+
+/// Strict mode: every commodity used must be declared, and posting amounts may not carry more
+/// decimal places than the declared precision.
+fn check_declared_commodities(
+  transactions: &[Transaction],
+  account_declarations: &[AccountDeclaration],
+  commodities: &[CommodityDeclaration],
+  prices: &[PriceDirective],
+  diagnostics: &mut Vec<Diagnostic>,
+) {
+  let declared: BTreeMap<&str, &CommodityDeclaration> =
+    commodities.iter().map(|c| (c.commodity.as_str(), c)).collect();
+  let mut report = |file: &Option<String>, line: usize, used: BTreeSet<&str>| {
+    for commodity in used.into_iter().filter(|c| !declared.contains_key(c)) {
+      let mut message = format!("undeclared commodity: {commodity}");
+      if let Some(suggestion) = did_you_mean(commodity, declared.keys().copied()) {
+        message.push_str(&format!(" (did you mean {suggestion}?)"));
+      }
+      let mut d = diag(line, 0, message);
+      d.file = file.clone();
+      diagnostics.push(d);
+    }
+  };
+
+  for decl in account_declarations {
+    let used = decl
+      .default_commodity
+      .iter()
+      .map(String::as_str)
+      .chain(decl.openings.iter().map(|o| o.commodity.as_str()))
+      .collect();
+    report(&decl.file, decl.line, used);
+  }
+
+  for price in prices {
+    let used = [price.commodity.as_str(), price.price.commodity.as_str()].into();
+    report(&price.file, price.line, used);
+  }
+
+  for txn in transactions {
+    for posting in &txn.postings {
+      let cost = posting.lot_cost.as_ref();
+      let fee = match cost.and_then(|c| c.field("fee")) {
+        Some(LotValue::Amount(fee)) => Some(fee.commodity.as_str()),
+        _ => None,
+      };
+      let used = std::iter::once(posting.commodity.as_str())
+        .filter(|c| !c.is_empty())
+        .chain(cost.and_then(|c| c.amount.as_ref()).map(|a| a.commodity.as_str()))
+        .chain(cost.into_iter().flat_map(|c| &c.extra_amounts).map(|a| a.commodity.as_str()))
+        .chain(fee)
+        .chain(posting.price.as_ref().map(|p| p.amount.commodity.as_str()))
+        .chain(posting.balance_assertion.as_ref().map(|a| a.commodity.as_str()))
+        .collect();
+      report(&txn.file, posting.line, used);
+    }
+  }
+
+  for txn in transactions {
+    for posting in txn.postings.iter().filter(|p| !p.inferred) {
+      let declared = declared.get(posting.commodity.as_str());
+      let Some(precision) = declared.and_then(|c| c.precision) else {
+        continue;
+      };
+      if posting.amount.scale() > precision {
+        let mut d = diag(
+          posting.line,
+          4 + posting.account.len() + 1,
+          format!(
+            "amount {} {} has more decimal places than the declared precision ({precision})",
+            posting.amount_text, posting.commodity
+          ),
+        );
+        d.file = txn.file.clone();
+        diagnostics.push(d);
+      }
+    }
+  }
+}
+
+/// Flags postings dated before their account's `open` date or after its `close` date.
+fn check_account_lifecycle(
+  transactions: &[Transaction],
+  account_declarations: &[AccountDeclaration],
+  diagnostics: &mut Vec<Diagnostic>,
+) {
+  let declarations: BTreeMap<&str, &AccountDeclaration> = account_declarations
+    .iter()
+    .map(|d| (d.account.as_str(), d))
+    .collect();
+  for txn in transactions {
+    for posting in &txn.postings {
+      let Some(decl) = declarations.get(posting.account.as_str()) else {
+        continue;
+      };
+      let problem = match (&decl.open_date, &decl.close_date) {
+        (Some(open), _) if txn.date < *open => format!("before it was opened on {open}"),
+        (_, Some(close)) if txn.date > *close => format!("after it was closed on {close}"),
+        _ => continue,
+      };
+      let message = format!("posting to {} dated {} {problem}", posting.account, txn.date);
+      let mut d = diag(posting.line, 4, message);
+      d.file = txn.file.clone();
+      diagnostics.push(d);
+    }
+  }
+}
+
+/// Resolves the type of every declared or posted-to account, reporting malformed `type:` tags
+/// and accounts that map to no type.
+fn classify_accounts(
+  transactions: &[Transaction],
+  account_declarations: &[AccountDeclaration],
+  roots: &AccountTypes,
+  diagnostics: &mut Vec<Diagnostic>,
+) -> BTreeMap<String, AccountType> {
+  let mut tagged: BTreeMap<&str, AccountType> = BTreeMap::new();
+  for decl in account_declarations {
+    let Some(value) = decl.tags.get("type") else {
+      continue;
+    };
+    match AccountType::parse(value) {
+      Some(account_type) => {
+        tagged.entry(decl.account.as_str()).or_insert(account_type);
+      }
+      None => {
+        let message = format!(
+          "unknown account type: {value} (expected asset, liability, equity, income or expense)"
+        );
+        let mut d = diag(decl.line, 0, message);
+        d.file = decl.file.clone();
+        diagnostics.push(d);
+      }
+    }
+  }
+
+  let used = account_declarations
+    .iter()
+    .map(|d| (d.account.as_str(), &d.file, d.line))
+    .chain(transactions.iter().flat_map(|txn| {
+      txn.postings.iter().map(move |p| (p.account.as_str(), &txn.file, p.line))
+    }));
+  let mut types = BTreeMap::new();
+  let mut unmapped: BTreeSet<&str> = BTreeSet::new();
+  for (account, file, line) in used {
+    if types.contains_key(account) || unmapped.contains(account) {
+      continue;
+    }
+    if let Some(account_type) = roots.classify(account, &tagged) {
+      types.insert(account.to_string(), account_type);
+      continue;
+    }
+    unmapped.insert(account);
+    let root = account.split(':').next().unwrap_or(account);
+    let mut message = format!("account {account} has no account type: unknown root {root}");
+    if let Some(suggestion) = did_you_mean(root, roots.roots().map(|(r, _)| r)) {
+      message.push_str(&format!(" (did you mean {suggestion}?)"));
+    }
+    let mut d = diag(line, 0, message);
+    d.file = file.clone();
+    diagnostics.push(d);
+  }
+  types
+}
+
+/// Replays postings in date order (openings first), checks every `= <amount>` assertion against
+/// the running balance of its account (unless `check_assertions` is off), and warns the first
+/// time an asset or liability balance goes negative once presented with its conventional sign.
+/// A balance out of range is an error.
+fn check_running_balances(
+  transactions: &[Transaction],
+  account_declarations: &[AccountDeclaration],
+  account_types: &BTreeMap<String, AccountType>,
+  check_assertions: bool,
+  diagnostics: &mut Vec<Diagnostic>,
+) {
+  let out_of_range = |key: (&str, &str), when: &str, file: &Option<String>, line| {
+    let (account, commodity) = key;
+    let message = format!("balance of {account} out of range in {commodity} {when}");
+    let mut d = diag(line, 0, message);
+    d.file = file.clone();
+    d
+  };
+  let mut warned: BTreeSet<(&str, &str)> = BTreeSet::new();
+  let mut overflowed: BTreeSet<(&str, &str)> = BTreeSet::new();
+  let mut running: BTreeMap<(&str, &str), Amount> = BTreeMap::new();
+  let mut dated: Vec<(&str, &AccountDeclaration, &Opening)> = Vec::new();
+  for decl in account_declarations {
+    for opening in &decl.openings {
+      if let Some(date) = opening.date.as_deref() {
+        dated.push((date, decl, opening));
+        continue;
+      }
+      let key = (decl.account.as_str(), opening.commodity.as_str());
+      let balance = running.entry(key).or_default();
+      match balance.checked_add(&opening.amount) {
+        Some(sum) => *balance = sum,
+        None if overflowed.insert(key) => {
+          diagnostics.push(out_of_range(key, "at its opening", &decl.file, decl.line));
+        }
+        None => {}
+      }
+    }
+  }
+  dated.sort_by_key(|(date, _, _)| *date);
+  let mut dated = dated.into_iter().peekable();
+
+  let mut ordered: Vec<&Transaction> = transactions.iter().collect();
+  ordered.sort_by(|a, b| a.datetime.cmp(&b.datetime));
+
+  for txn in ordered {
+    // A dated opening applies from the start of its day.
+    let day = txn.date.as_str();
+    while let Some((date, decl, opening)) = dated.next_if(|(date, _, _)| *date <= day) {
+      let key = (decl.account.as_str(), opening.commodity.as_str());
+      let balance = running.entry(key).or_default();
+      match balance.checked_add(&opening.amount) {
+        Some(sum) => *balance = sum,
+        None if overflowed.insert(key) => {
+          let when = format!("on {date}");
+          diagnostics.push(out_of_range(key, &when, &decl.file, decl.line));
+        }
+        None => {}
+      }
+    }
+
+    for posting in &txn.postings {
+      let key = (posting.account.as_str(), posting.commodity.as_str());
+      let balance = running.entry(key).or_default();
+      let Some(sum) = balance.checked_add(&posting.amount) else {
+        if overflowed.insert(key) {
+          let when = format!("on {}", txn.date);
+          diagnostics.push(out_of_range(key, &when, &txn.file, posting.line));
+        }
+        continue;
+      };
+      *balance = sum;
+
+      let account_type = account_types.get(posting.account.as_str()).copied();
+      if let Some(account_type) = account_type.filter(|t| t.expects_non_negative()) {
+        let presented = account_type.present(*balance);
+        if presented.is_negative() && warned.insert(key) {
+          let message = format!(
+            "{account_type} account {} goes negative on {}: {presented} {}",
+            posting.account, txn.date, posting.commodity
+          );
+          let mut d = warning(posting.line, 0, message);
+          d.file = txn.file.clone();
+          diagnostics.push(d);
+        }
+      }
+
+      let Some(expected) = &posting.balance_assertion else {
+        continue;
+      };
+      if !check_assertions {
+        continue;
+      }
+      let actual = running
+        .get(&(posting.account.as_str(), expected.commodity.as_str()))
+        .copied()
+        .unwrap_or_default();
+      if actual != expected.amount {
+        let scale = expected.amount.scale().max(actual.scale());
+        let mut d = diag(
+          posting.line,
+          0,
+          format!(
+            "balance assertion failed for {}: expected {} {commodity}, actual {} {commodity}",
+            posting.account,
+            expected.amount,
+            actual.checked_rescale(scale).unwrap_or(actual),
+            commodity = expected.commodity,
+          ),
+        );
+        d.file = txn.file.clone();
+        diagnostics.push(d);
+      }
+    }
+  }
+}
+
+fn flush_transaction(
+  current: &mut Option<(usize, Transaction)>,
+  options: &ParseOptions,
+  diagnostics: &mut Vec<Diagnostic>,
+  transactions: &mut Vec<Transaction>,
+) {
+  if let Some((header_line, mut txn)) = current.take() {
+    apply_fees(&mut txn, options.fee_policy, diagnostics);
+    infer_elided_amounts(&mut txn, options.fee_policy, diagnostics);
+    if txn.postings.is_empty() {
+      diagnostics.push(diag(header_line, 0, "transaction missing postings"));
+    } else {
+      check_balance(header_line, &txn, options, diagnostics);
+    }
+    check_txn_id(header_line, &txn, options, diagnostics);
+    transactions.push(txn);
+  }
+}
+
+pub fn parse_transactions(contents: &str) -> ParseResult {
+  parse_transactions_with_options(contents, &ParseOptions::default())
+}
+
+pub fn parse_transactions_with_options(contents: &str, options: &ParseOptions) -> ParseResult {
+  finish(parse_document(contents, None, options), options)
+}
+
+/// Parses several files as one ledger: diagnostics and transactions carry their source path,
+/// balances span every file, and txn ids must be unique across the whole set.
+pub fn parse_sources(sources: &[LedgerSource], options: &ParseOptions) -> ParseResult {
+  let mut combined = Document::default();
+  for source in sources {
+    let doc = parse_document(&source.contents, Some(&source.path), options);
+    combined.diagnostics.extend(doc.diagnostics);
+    combined.transactions.extend(doc.transactions);
+    combined.account_declarations.extend(doc.account_declarations);
+    combined.commodity_declarations.extend(doc.commodity_declarations);
+    combined.prices.extend(doc.prices);
+    combined.strict |= doc.strict;
+  }
+  finish(combined, options)
+}
+
+/// Parses a `price <DATETIME> <COMMODITY> <amount> <QUOTE> [; meta]` line.
+fn parse_price_directive(
+  line: &str,
+  line_no: usize,
+  patterns: &Patterns,
+  diagnostics: &mut Vec<Diagnostic>,
+) -> Option<PriceDirective> {
+  let (before_meta, tags) = match find_unquoted(line, ";") {
+    Some(pos) => (
+      &line[..pos],
+      parse_meta(&line[pos + 1..], line_no, pos + 1, patterns, diagnostics),
+    ),
+    None => (line, Meta::default()),
+  };
+  let mut rest = &before_meta["price".len()..];
+  let (Some(datetime), Some(commodity)) = (next_word(&mut rest), next_word(&mut rest)) else {
+    diagnostics.push(diag(
+      line_no,
+      0,
+      "incomplete price directive (expected: price <DATE> <COMMODITY> <amount> <COMMODITY>)",
+    ));
+    return None;
+  };
+
+  let before = diagnostics.len();
+  if !patterns.datetime.is_match(datetime) {
+    diagnostics.push(diag(line_no, 6, format!("invalid price date: {datetime}")));
+  }
+  if !patterns.commodity.is_match(commodity) {
+    diagnostics.push(diag(line_no, 0, format!("invalid commodity: {commodity}")));
+  }
+
+  let spec = rest.trim();
+  let spec_column = before_meta.len() - rest.trim_start().len();
+  if spec.is_empty() {
+    diagnostics.push(diag(line_no, spec_column, "price directive missing amount"));
+    return None;
+  }
+  let price = parse_amount_spec(spec, line_no, spec_column, patterns, diagnostics);
+  let Some(price) = price else {
+    if diagnostics.len() == before {
+      diagnostics.push(diag(line_no, spec_column, format!("invalid price: {spec}")));
+    }
+    return None;
+  };
+
+  if !price.amount.is_positive() {
+    diagnostics.push(diag(line_no, spec_column, "price must be positive"));
+  }
+  if price.commodity == commodity {
+    diagnostics.push(diag(
+      line_no,
+      spec_column,
+      format!("price of {commodity} must be quoted in another commodity"),
+    ));
+  }
+  if diagnostics.len() > before {
+    return None;
+  }
+
+  Some(PriceDirective {
+    line: line_no,
+    file: None,
+    date: datetime[..10].to_string(),
+    datetime: datetime.to_string(),
+    commodity: commodity.to_string(),
+    price,
+    tags,
+  })
+}
+
+/// Parses a `commodity <CODE> [; meta]` line, opening a declaration block.
+fn parse_commodity_header(
+  line: &str,
+  line_no: usize,
+  patterns: &Patterns,
+  diagnostics: &mut Vec<Diagnostic>,
+) -> Option<CommodityDeclaration> {
+  let (before_meta, tags) = match find_unquoted(line, ";") {
+    Some(pos) => (
+      &line[..pos],
+      parse_meta(&line[pos + 1..], line_no, pos + 1, patterns, diagnostics),
+    ),
+    None => (line, Meta::default()),
+  };
+  let mut parts = before_meta.split_whitespace().skip(1);
+  let Some(commodity) = parts.next() else {
+    diagnostics.push(diag(line_no, 0, "commodity declaration missing commodity"));
+    return None;
+  };
+
+  if !patterns.commodity.is_match(commodity) {
+    diagnostics.push(diag(line_no, 10, format!("invalid commodity: {commodity}")));
+  }
+
+  if parts.next().is_some() {
+    diagnostics.push(diag(
+      line_no,
+      0,
+      "unexpected extra tokens in commodity declaration (expected: commodity <COMMODITY>)",
+    ));
+  }
+
+  Some(CommodityDeclaration {
+    line: line_no,
+    commodity: commodity.to_string(),
+    tags,
+    ..CommodityDeclaration::default()
+  })
+}
+
+/// Parses an indented `precision`/`symbol`/`name`/`kind` entry of a `commodity` block.
+fn parse_commodity_entry(
+  decl: &mut CommodityDeclaration,
+  line: &str,
+  line_no: usize,
+  patterns: &Patterns,
+  diagnostics: &mut Vec<Diagnostic>,
+) {
+  let after_indent = &line[4..];
+  let before_meta = match find_unquoted(after_indent, ";") {
+    Some(pos) => {
+      let column = 4 + pos + 1;
+      let tags = parse_meta(&after_indent[pos + 1..], line_no, column, patterns, diagnostics);
+      for (key, value) in tags.iter() {
+        if !decl.tags.insert(key, value) {
+          diagnostics.push(diag(line_no, column, format!("duplicate meta tag: {key}")));
+        }
+      }
+      &after_indent[..pos]
+    }
+    None => after_indent,
+  };
+
+  let entry = before_meta.trim();
+  if entry.is_empty() {
+    return;
+  }
+  let (kind, value) = entry.split_once([' ', '\t']).unwrap_or((entry, ""));
+  let value = value.trim();
+  let value_column = line.len() - after_indent.trim_start().len() + kind.len() + 1;
+  if value.is_empty() {
+    diagnostics.push(diag(line_no, 4, format!("commodity {kind} missing value")));
+    return;
+  }
+
+  let duplicate = match kind {
+    "precision" => match value.parse::<u32>() {
+      Ok(precision) if precision <= MAX_SCALE => {
+        decl.precision.replace(precision).is_some()
+      }
+      _ => {
+        diagnostics.push(diag(
+          line_no,
+          value_column,
+          format!("invalid commodity precision: {value}"),
+        ));
+        false
+      }
+    },
+    "symbol" | "name" => {
+      let parsed = if value.starts_with('"') {
+        parse_quoted(value)
+      } else {
+        Some(value.to_string()).filter(|v| !v.contains(char::is_whitespace))
+      };
+      let slot = if kind == "symbol" { &mut decl.symbol } else { &mut decl.name };
+      match parsed {
+        Some(text) => slot.replace(text).is_some(),
+        None => {
+          diagnostics.push(diag(
+            line_no,
+            value_column,
+            format!("invalid commodity {kind}: {value}"),
+          ));
+          false
+        }
+      }
+    }
+    "kind" => {
+      let parsed = match value {
+        "fiat" => Some(CommodityKind::Fiat),
+        "crypto" => Some(CommodityKind::Crypto),
+        "stock" => Some(CommodityKind::Stock),
+        _ => None,
+      };
+      match parsed {
+        Some(parsed) => decl.kind.replace(parsed).is_some(),
+        None => {
+          diagnostics.push(diag(
+            line_no,
+            value_column,
+            format!("unknown commodity kind: {value} (expected fiat, crypto or stock)"),
+          ));
+          false
+        }
+      }
+    }
+    _ => {
+      diagnostics.push(diag(
+        line_no,
+        4,
+        format!("unknown commodity declaration entry: {kind}"),
+      ));
+      false
+    }
+  };
+
+  if duplicate {
+    diagnostics.push(diag(line_no, 4, format!("duplicate {kind} declaration")));
+  }
+}
+
+fn parse_document(contents: &str, file: Option<&str>, options: &ParseOptions) -> Document {
+  let header_re = header_datetime_re();
+  let patterns = Patterns::new();
+
+  let mut diagnostics: Vec<Diagnostic> = Vec::new();
+  let mut current: Option<(usize, Transaction)> = None;
+  let mut current_account: Option<(usize, AccountDeclaration)> = None;
+  let mut current_commodity: Option<CommodityDeclaration> = None;
+  let mut transactions: Vec<Transaction> = Vec::new();
+  let mut account_declarations: Vec<AccountDeclaration> = Vec::new();
+  let mut commodity_declarations: Vec<CommodityDeclaration> = Vec::new();
+  let mut prices: Vec<PriceDirective> = Vec::new();
+  let mut strict = false;
+
+  for (idx, raw_line) in contents.lines().enumerate() {
+    let line_no = idx + 1;
+    let line = raw_line.trim_end_matches('\r');
+
+    if is_blank(line) {
+      flush_transaction(&mut current, options, &mut diagnostics, &mut transactions);
+      if let Some((_, decl)) = current_account.take() {
+        account_declarations.push(decl);
+      }
+      commodity_declarations.extend(current_commodity.take());
+      continue;
+    }
+
+    if is_directive(line) {
+      continue;
+    }
+
+    if line.starts_with("option ") || line.starts_with("option\t") {
+      flush_transaction(&mut current, options, &mut diagnostics, &mut transactions);
+      if let Some((_, decl)) = current_account.take() {
+        account_declarations.push(decl);
+      }
+      commodity_declarations.extend(current_commodity.take());
+
+      let name = line["option".len()..].split(';').next().unwrap_or_default().trim();
+      match name {
+        "strict" => strict = true,
+        _ => diagnostics.push(diag(line_no, 7, format!("unknown option: {name}"))),
+      }
+      continue;
+    }
+
+    if line == "price" || line.starts_with("price ") || line.starts_with("price\t") {
+      flush_transaction(&mut current, options, &mut diagnostics, &mut transactions);
+      if let Some((_, decl)) = current_account.take() {
+        account_declarations.push(decl);
+      }
+      commodity_declarations.extend(current_commodity.take());
+
+      prices.extend(parse_price_directive(line, line_no, &patterns, &mut diagnostics));
+      continue;
+    }
+
+    if line == "commodity" || line.starts_with("commodity ") || line.starts_with("commodity\t") {
+      flush_transaction(&mut current, options, &mut diagnostics, &mut transactions);
+      if let Some((_, decl)) = current_account.take() {
+        account_declarations.push(decl);
+      }
+      commodity_declarations.extend(current_commodity.take());
+
+      current_commodity = parse_commodity_header(line, line_no, &patterns, &mut diagnostics);
+      continue;
+    }
+
+    if line == "account" || line.starts_with("account ") || line.starts_with("account\t") {
+      flush_transaction(&mut current, options, &mut diagnostics, &mut transactions);
+      if let Some((_, decl)) = current_account.take() {
+        account_declarations.push(decl);
+      }
+      commodity_declarations.extend(current_commodity.take());
+
+      let (before_meta, tags) = match find_unquoted(line, ";") {
+        Some(pos) => (
+          &line[..pos],
+          parse_meta(&line[pos + 1..], line_no, pos + 1, &patterns, &mut diagnostics),
+        ),
+        None => (line, Meta::default()),
+      };
+      let mut parts = before_meta.split_whitespace();
+      let kw = parts.next().unwrap_or_default();
+      if kw != "account" {
+        diagnostics.push(diag(
+          line_no,
+          0,
+          "invalid line: expected transaction header, posting, or directive",
+        ));
+        continue;
+      }
+      let Some(account) = parts.next() else {
+        diagnostics.push(diag(line_no, 0, "account declaration missing account path"));
+        continue;
+      };
+
+      if !patterns.account.is_match(account) {
+        diagnostics.push(diag(
+          line_no,
+          0,
+          format!("invalid account path: {account}"),
+        ));
+      }
+
+      let default_commodity = parts.next().map(|s| s.to_string());
+      if let Some(c) = default_commodity.as_deref() {
+        if !patterns.commodity.is_match(c) {
+          diagnostics.push(diag(line_no, 0, format!("invalid commodity: {c}")));
+        }
+      }
+
+      if parts.next().is_some() {
+        diagnostics.push(diag(
+          line_no,
+          0,
+          "unexpected extra tokens in account declaration (expected: account <path> [COMMODITY])",
+        ));
+      }
+
+      current_account = Some((
+        line_no,
+        AccountDeclaration {
+          line: line_no,
+          file: None,
+          account: account.to_string(),
+          default_commodity,
+          open_date: None,
+          close_date: None,
+          openings: Vec::new(),
+          tags,
+        },
+      ));
+      continue;
     }
 
     if line.starts_with("    ") {
       let Some((_, txn)) = current.as_mut() else {
+        if let Some(decl) = current_commodity.as_mut() {
+          parse_commodity_entry(decl, line, line_no, &patterns, &mut diagnostics);
+          continue;
+        }
+
         let Some((_, decl)) = current_account.as_mut() else {
           diagnostics.push(diag(line_no, 0, "unexpected indented line"));
           continue;
         };
 
         let after_indent = &line[4..];
-        let before_meta = after_indent
-          .split_once(';')
-          .map(|(l, _)| l)
-          .unwrap_or(after_indent);
+        let before_meta = match find_unquoted(after_indent, ";") {
+          Some(pos) => {
+            let column = 4 + pos + 1;
+            let tags = parse_meta(&after_indent[pos + 1..], line_no, column, &patterns, &mut diagnostics);
+            for (key, value) in tags.iter() {
+              if !decl.tags.insert(key, value) {
+                diagnostics.push(diag(line_no, column, format!("duplicate meta tag: {key}")));
+              }
+            }
+            &after_indent[..pos]
+          }
+          None => after_indent,
+        };
         let mut parts = before_meta.split_whitespace();
         let Some(kind) = parts.next() else {
           diagnostics.push(diag(line_no, 4, "invalid account declaration line"));
@@ -280,13 +2245,20 @@ pub fn parse_transactions(contents: &str) -> ParseResult {
 
         match kind {
           "opening" => {
-            let Some(amount_text) = parts.next() else {
+            let mut first = parts.next();
+            let date = first.filter(|t| t.len() == 10 && patterns.datetime.is_match(t));
+            if date.is_some() {
+              first = parts.next();
+            }
+            let Some(amount_text) = first else {
               diagnostics.push(diag(line_no, 4, "opening missing amount"));
               continue;
             };
 
-            if !amount_re.is_match(amount_text) {
+            if !patterns.amount.is_match(amount_text) {
               diagnostics.push(diag(line_no, 4, format!("invalid amount: {amount_text}")));
+            } else if let Err(e) = amount_text.parse::<Amount>() {
+              diagnostics.push(diag(line_no, 4, e.to_string()));
             }
 
             let commodity = match parts.next() {
@@ -303,7 +2275,7 @@ pub fn parse_transactions(contents: &str) -> ParseResult {
               continue;
             };
 
-            if !commodity_re.is_match(&commodity) {
+            if !patterns.commodity.is_match(&commodity) {
               diagnostics.push(diag(line_no, 4, format!("invalid commodity: {commodity}")));
             }
 
@@ -311,17 +2283,59 @@ pub fn parse_transactions(contents: &str) -> ParseResult {
               diagnostics.push(diag(
                 line_no,
                 4,
-                "unexpected extra tokens in opening declaration (expected: opening <amount> [COMMODITY])",
+                "unexpected extra tokens in opening declaration (expected: opening [DATE] <amount> [COMMODITY])",
               ));
             }
 
-            if decl.opening.is_some() {
-              diagnostics.push(diag(line_no, 4, "duplicate opening declaration"));
+            if decl.openings.iter().any(|o| o.commodity == commodity) {
+              diagnostics.push(diag(
+                line_no,
+                4,
+                format!("duplicate opening declaration for {commodity}"),
+              ));
               continue;
             }
 
-            let amount: f64 = amount_text.parse().unwrap_or(0.0);
-            decl.opening = Some(CommodityAmount { commodity, amount });
+            decl.openings.push(Opening {
+              date: date.map(str::to_string),
+              commodity,
+              amount: amount_text.parse().unwrap_or_default(),
+            });
+          }
+          "open" | "close" => {
+            let Some(date) = parts.next() else {
+              diagnostics.push(diag(line_no, 4, format!("{kind} missing date")));
+              continue;
+            };
+            if date.len() != 10 || !patterns.datetime.is_match(date) {
+              diagnostics.push(diag(
+                line_no,
+                4 + kind.len() + 1,
+                format!("invalid {kind} date: {date} (expected YYYY-MM-DD)"),
+              ));
+              continue;
+            }
+            if parts.next().is_some() {
+              diagnostics.push(diag(
+                line_no,
+                4,
+                format!("unexpected extra tokens in {kind} declaration (expected: {kind} <DATE>)"),
+              ));
+            }
+
+            let slot = if kind == "open" { &mut decl.open_date } else { &mut decl.close_date };
+            if slot.replace(date.to_string()).is_some() {
+              diagnostics.push(diag(line_no, 4, format!("duplicate {kind} declaration")));
+            }
+            if let (Some(open), Some(close)) = (&decl.open_date, &decl.close_date) {
+              if close < open {
+                diagnostics.push(diag(
+                  line_no,
+                  4,
+                  format!("account closed on {close} before it was opened on {open}"),
+                ));
+              }
+            }
           }
           _ => {
             diagnostics.push(diag(
@@ -344,11 +2358,11 @@ pub fn parse_transactions(contents: &str) -> ParseResult {
         continue;
       }
 
-      let after_indent = &line[4..];
-      let mut parts = after_indent.split_whitespace();
-      let account = parts.next();
-      let amount = parts.next();
-      let commodity = parts.next();
+      let mut rest = &line[4..];
+      let account = next_word(&mut rest);
+      let after_account = rest.trim_start();
+      let amount = next_word(&mut rest);
+      let commodity = next_word(&mut rest);
 
       if account.is_none() {
         diagnostics.push(diag(line_no, 4, "missing account"));
@@ -356,7 +2370,7 @@ pub fn parse_transactions(contents: &str) -> ParseResult {
       }
 
       let account = account.unwrap();
-      if !account_re.is_match(account) {
+      if !patterns.account.is_match(account) {
         diagnostics.push(diag(
           line_no,
           4,
@@ -364,18 +2378,48 @@ pub fn parse_transactions(contents: &str) -> ParseResult {
         ));
       }
 
-      if amount.is_none() {
-        diagnostics.push(diag(line_no, 4 + account.len() + 1, "missing amount"));
+      // An elided amount (`account` alone, or followed only by a balance assertion and meta
+      // comment) is inferred from the other postings when the transaction is flushed.
+      let Some(amount) = amount.filter(|a| !a.starts_with([';', '='])) else {
+        let assertion_column = line.len() - after_account.len();
+        let (balance_assertion, after_assertion) = parse_balance_assertion(
+          after_account,
+          line_no,
+          assertion_column,
+          &patterns,
+          &mut diagnostics,
+        );
+        let after_assertion = after_assertion.trim_start();
+        let meta_column = line.len() - after_assertion.len();
+        let (meta, tags) = match after_assertion.strip_prefix(';') {
+          Some(comment) => (
+            Some(comment.trim().to_string()),
+            parse_meta(comment, line_no, meta_column + 1, &patterns, &mut diagnostics),
+          ),
+          None => (None, Meta::default()),
+        };
+        let annotations = line[assertion_column..meta_column].trim();
+        txn.postings.push(Posting {
+          line: line_no,
+          account: account.to_string(),
+          balance_assertion,
+          meta,
+          tags,
+          remainder: (!annotations.is_empty()).then(|| annotations.to_string()),
+          inferred: true,
+          ..Posting::default()
+        });
         continue;
-      }
+      };
 
-      let amount = amount.unwrap();
-      if !amount_re.is_match(amount) {
+      if !patterns.amount.is_match(amount) {
         diagnostics.push(diag(
           line_no,
           4 + account.len() + 1,
           format!("invalid amount: {amount}"),
         ));
+      } else if let Err(e) = amount.parse::<Amount>() {
+        diagnostics.push(diag(line_no, 4 + account.len() + 1, e.to_string()));
       }
 
       if commodity.is_none() {
@@ -388,7 +2432,7 @@ pub fn parse_transactions(contents: &str) -> ParseResult {
       }
 
       let commodity = commodity.unwrap();
-      if !commodity_re.is_match(commodity) {
+      if !patterns.commodity.is_match(commodity) {
         diagnostics.push(diag(
           line_no,
           4 + account.len() + 1 + amount.len() + 1,
@@ -396,20 +2440,66 @@ pub fn parse_transactions(contents: &str) -> ParseResult {
         ));
       }
 
-      let parsed_amount: f64 = amount.parse().unwrap_or(0.0);
-      let remainder_parts: Vec<&str> = parts.collect();
-      let remainder = if remainder_parts.is_empty() {
+      let parsed_amount: Amount = amount.parse().unwrap_or_default();
+      let tail = rest.trim_start();
+      let tail_column = line.len() - tail.len();
+      let (lot_cost, after_cost) = parse_lot_cost(tail, line_no, tail_column, &patterns, &mut diagnostics);
+      let after_cost = after_cost.trim_start();
+      let (price, after_price) = parse_price(
+        after_cost,
+        line_no,
+        line.len() - after_cost.len(),
+        commodity,
+        &patterns,
+        &mut diagnostics,
+      );
+      let after_price = after_price.trim_start();
+      let (balance_assertion, after_assertion) = parse_balance_assertion(
+        after_price,
+        line_no,
+        line.len() - after_price.len(),
+        &patterns,
+        &mut diagnostics,
+      );
+      let after_assertion = after_assertion.trim_start();
+      let meta_column = line.len() - after_assertion.len();
+      let (meta, tags) = match after_assertion.strip_prefix(';') {
+        Some(comment) => (
+          Some(comment.trim().to_string()),
+          parse_meta(comment, line_no, meta_column + 1, &patterns, &mut diagnostics),
+        ),
+        None => {
+          if !after_assertion.trim().is_empty() {
+            diagnostics.push(diag(
+              line_no,
+              meta_column,
+              format!("unexpected text after posting: {}", after_assertion.trim()),
+            ));
+          }
+          (None, Meta::default())
+        }
+      };
+      let annotations = line[tail_column..meta_column].trim();
+      let remainder = if annotations.is_empty() {
         None
       } else {
-        Some(remainder_parts.join(" "))
+        Some(annotations.to_string())
       };
 
       txn.postings.push(Posting {
+        line: line_no,
         account: account.to_string(),
         amount: parsed_amount,
         amount_text: amount.to_string(),
         commodity: commodity.to_string(),
+        lot_cost,
+        price,
+        balance_assertion,
+        meta,
+        tags,
         remainder,
+        inferred: false,
+        fee_for: None,
       });
 
       continue;
@@ -417,10 +2507,11 @@ pub fn parse_transactions(contents: &str) -> ParseResult {
 
     // Header line
     if let Some(caps) = header_re.captures(line) {
-      flush_transaction(&mut current, &mut diagnostics, &mut transactions);
+      flush_transaction(&mut current, options, &mut diagnostics, &mut transactions);
       if let Some((_, decl)) = current_account.take() {
         account_declarations.push(decl);
       }
+      commodity_declarations.extend(current_commodity.take());
 
       let date = caps
         .name("date")
@@ -432,9 +2523,13 @@ pub fn parse_transactions(contents: &str) -> ParseResult {
       let datetime_span = caps.get(0).expect("datetime capture");
       let datetime_end = datetime_span.end();
 
-      let (before_meta, meta) = match line.split_once(';') {
-        Some((left, right)) => (left, Some(right.trim().to_string())),
-        None => (line, None),
+      let (before_meta, meta, tags) = match find_unquoted(line, ";") {
+        Some(pos) => {
+          let right = &line[pos + 1..];
+          let tags = parse_meta(right, line_no, pos + 1, &patterns, &mut diagnostics);
+          (&line[..pos], Some(right.trim().to_string()), tags)
+        }
+        None => (line, None, Meta::default()),
       };
 
       let header_after_datetime = before_meta.get(datetime_end..).unwrap_or("");
@@ -442,7 +2537,7 @@ pub fn parse_transactions(contents: &str) -> ParseResult {
         diagnostics.push(diag(line_no, datetime_end, "missing transaction details"));
       }
 
-      if !line.contains(';') {
+      if meta.is_none() {
         diagnostics.push(diag(line_no, 0, "missing meta comment (expected ';')"));
       }
 
@@ -450,12 +2545,15 @@ pub fn parse_transactions(contents: &str) -> ParseResult {
       current = Some((
         line_no,
         Transaction {
+          line: line_no,
+          file: None,
           date,
           datetime,
           status,
           payee,
           narration,
           meta,
+          tags,
           postings: Vec::new(),
         },
       ));
@@ -468,51 +2566,140 @@ pub fn parse_transactions(contents: &str) -> ParseResult {
     }
   }
 
-  flush_transaction(&mut current, &mut diagnostics, &mut transactions);
+  flush_transaction(&mut current, options, &mut diagnostics, &mut transactions);
   if let Some((_, decl)) = current_account.take() {
     account_declarations.push(decl);
   }
+  commodity_declarations.extend(current_commodity.take());
 
-  let mut balances_by_account: BTreeMap<String, BTreeMap<String, f64>> = BTreeMap::new();
-  for txn in &transactions {
-    for posting in &txn.postings {
-      let entry = balances_by_account
-        .entry(posting.account.clone())
-        .or_default()
-        .entry(posting.commodity.clone())
-        .or_insert(0.0);
-      *entry += posting.amount;
+  if let Some(file) = file {
+    for d in &mut diagnostics {
+      d.file = Some(file.to_string());
+    }
+    for txn in &mut transactions {
+      txn.file = Some(file.to_string());
     }
+    for decl in &mut account_declarations {
+      decl.file = Some(file.to_string());
+    }
+    for decl in &mut commodity_declarations {
+      decl.file = Some(file.to_string());
+    }
+    for price in &mut prices {
+      price.file = Some(file.to_string());
+    }
+  }
+
+  Document {
+    diagnostics,
+    transactions,
+    account_declarations,
+    commodity_declarations,
+    prices,
+    strict,
+  }
+}
+
+fn finish(doc: Document, options: &ParseOptions) -> ParseResult {
+  let Document {
+    mut diagnostics,
+    transactions,
+    account_declarations,
+    commodity_declarations,
+    prices,
+    strict,
+  } = doc;
+
+  if options.require_txn_ids {
+    check_duplicate_txn_ids(&transactions, &mut diagnostics);
   }
-
+  check_duplicate_commodities(&commodity_declarations, &mut diagnostics);
+  let strict = options.strict || strict;
+  if strict {
+    check_declared_accounts(&transactions, &account_declarations, &mut diagnostics);
+    check_declared_commodities(
+      &transactions,
+      &account_declarations,
+      &commodity_declarations,
+      &prices,
+      &mut diagnostics,
+    );
+  }
+  check_account_lifecycle(&transactions, &account_declarations, &mut diagnostics);
+  let account_types = classify_accounts(
+    &transactions,
+    &account_declarations,
+    &options.account_types,
+    &mut diagnostics,
+  );
+  check_running_balances(
+    &transactions,
+    &account_declarations,
+    &account_types,
+    !options.skip_balance_assertions,
+    &mut diagnostics,
+  );
+
+  // Openings first, as `check_running_balances` replays them; it reports any overflow.
+  let mut balances_by_account: BTreeMap<String, BTreeMap<String, Amount>> = BTreeMap::new();
+  let openings = account_declarations.iter().flat_map(|decl| {
+    let amounts = decl.openings.iter().map(|o| (&o.commodity, o.amount));
+    amounts.map(move |(commodity, amount)| (&decl.account, commodity, amount))
+  });
+  let postings = transactions
+    .iter()
+    .flat_map(|txn| txn.postings.iter().map(|p| (&p.account, &p.commodity, p.amount)));
   for decl in &account_declarations {
     let _ = balances_by_account.entry(decl.account.clone()).or_default();
-    if let Some(opening) = &decl.opening {
-      let entry = balances_by_account
-        .entry(decl.account.clone())
-        .or_default()
-        .entry(opening.commodity.clone())
-        .or_insert(0.0);
-      *entry += opening.amount;
-    }
+  }
+  for (account, commodity, amount) in openings.chain(postings) {
+    let entry = balances_by_account
+      .entry(account.clone())
+      .or_default()
+      .entry(commodity.clone())
+      .or_default();
+    *entry = entry.checked_add(&amount).unwrap_or(*entry);
   }
 
-  let balances: Vec<AccountBalance> = balances_by_account
+  let closed: BTreeSet<&str> = account_declarations
+    .iter()
+    .filter(|d| d.close_date.is_some())
+    .map(|d| d.account.as_str())
+    .collect();
+  let (closed_balances, balances): (Vec<AccountBalance>, Vec<AccountBalance>) = balances_by_account
     .into_iter()
-    .map(|(account, by_commodity)| AccountBalance {
-      account,
-      totals: by_commodity
+    .map(|(account, by_commodity)| {
+      let mut display = BTreeMap::new();
+      let totals = by_commodity
         .into_iter()
-        .map(|(commodity, amount)| CommodityAmount { commodity, amount })
-        .collect(),
+        .map(|(commodity, amount)| {
+          let decl = commodity_declarations.iter().find(|c| c.commodity == commodity);
+          let amount = decl.map_or(amount, |d| d.round(amount));
+          let text = decl.map_or_else(|| format!("{amount} {commodity}"), |d| d.format(amount));
+          display.insert(commodity.clone(), text);
+          CommodityAmount { commodity, amount }
+        })
+        .collect();
+      AccountBalance {
+        account,
+        totals,
+        display,
+      }
     })
-    .collect();
+    .partition(|b| closed.contains(b.account.as_str()));
 
   ParseResult {
-    ok: diagnostics.is_empty(),
+    ok: !diagnostics.iter().any(Diagnostic::is_error),
     diagnostics,
     transactions,
     balances,
+    closed_balances,
+    accounts: account_declarations,
+    commodities: commodity_declarations,
+    prices,
+    strict,
+    account_types,
+    fee_policy: options.fee_policy,
   }
 }
 
@@ -522,13 +2709,369 @@ mod tests {
 
   #[test]
   fn parses_valid_fixture() {
-    let input = r#"2026-01-15 * \"Binance\" \"Buy SOL\" ; txn:01J2N9R9, src:binance:order:999
-    assets:exchange:binance:sol    10.000000 SOL {{ 230.00 USD, fee:0.10 USD, fee_to:expenses:fees:trading, venue:binance, note:\"maker fee\" }}
+    let input = r#"2026-01-15 * "Binance" "Buy SOL" ; txn:01J2N9R9, src:binance:order:999
+    assets:exchange:binance:sol    10.000000 SOL {{ 230.00 USD, fee:0.10 USD, fee_to:expenses:fees:trading, venue:binance, note:"maker fee" }}
+    assets:cash:usd              -230.10 USD
+"#;
+
+    let result = parse_transactions(input);
+    assert!(result.ok, "expected ok, got diagnostics: {:?}", result.diagnostics);
+  }
+
+  #[test]
+  fn parses_lot_cost_annotations() {
+    let input = r#"2026-01-16T10:05:03Z * "Kraken" "Sell BTC" ; txn:01J2NB
+    assets:exchange:kraken:btc   -0.005000 BTC { 30000 USD, 2026-01-15T09:31:22.123456+11:00, "original acquisition", lot:kraken:btc:abc123 } @@ 160.00 USD
+    assets:exchange:binance:sol    10.000000 SOL {{ 230.00 USD, fee:0.10 USD, fee_to:expenses:fees:trading, method:"FIFO" }}
+    assets:cash:usd              -80.10 USD
+"#;
+
+    let result = parse_transactions(input);
+    assert!(result.ok, "expected ok, got diagnostics: {:?}", result.diagnostics);
+
+    let postings = &result.transactions[0].postings;
+    let per_unit = postings[0].lot_cost.as_ref().expect("per-unit lot cost");
+    assert_eq!(per_unit.kind, CostKind::PerUnit);
+    assert_eq!(per_unit.amount.as_ref().map(|a| a.amount.to_string()), Some("30000".to_string()));
+    assert_eq!(per_unit.date.as_deref(), Some("2026-01-15T09:31:22.123456+11:00"));
+    assert_eq!(per_unit.label.as_deref(), Some("original acquisition"));
+    assert_eq!(per_unit.lot_id.as_deref(), Some("kraken:btc:abc123"));
+
+    let total = postings[1].lot_cost.as_ref().expect("total lot cost");
+    assert_eq!(total.kind, CostKind::Total);
+    assert_eq!(
+      total.field("fee"),
+      Some(&LotValue::Amount(CommodityAmount {
+        commodity: "USD".to_string(),
+        amount: "0.10".parse().unwrap(),
+      }))
+    );
+    assert_eq!(
+      total.field("fee_to"),
+      Some(&LotValue::Text("expenses:fees:trading".to_string()))
+    );
+    assert_eq!(total.field("method"), Some(&LotValue::Text("FIFO".to_string())));
+  }
+
+  #[test]
+  fn parses_price_annotations() {
+    let input = r#"2026-01-16 * "Kraken" "Sell BTC" ; txn:01J2NB
+    assets:exchange:kraken:btc   -0.005000 BTC @@ 160.00 USD
+    assets:exchange:kraken:sol   -2 SOL @ 23.10 USD
+    assets:cash:usd               206.20 USD
+"#;
+
+    let result = parse_transactions(input);
+    assert!(result.ok, "expected ok, got diagnostics: {:?}", result.diagnostics);
+
+    let postings = &result.transactions[0].postings;
+    let total = postings[0].price.as_ref().expect("total price");
+    assert_eq!(total.kind, PriceKind::Total);
+    assert_eq!(total.amount.commodity, "USD");
+    assert_eq!(postings[0].total_price().unwrap().amount.to_string(), "160.00");
+
+    let per_unit = postings[1].price.as_ref().expect("per-unit price");
+    assert_eq!(per_unit.kind, PriceKind::PerUnit);
+    assert_eq!(postings[1].total_price().unwrap().amount.to_string(), "46.20");
+    assert!(postings[2].price.is_none());
+  }
+
+  #[test]
+  fn rejects_invalid_price_annotations() {
+    let input = r#"2026-01-16 * "Kraken" "Sell BTC" ; txn:01J2NB
+    assets:exchange:kraken:btc   -1 BTC @@
+    assets:exchange:kraken:eth   -1 ETH @ lots USD
+    assets:exchange:kraken:sol   -1 SOL @ 20 SOL
+"#;
+
+    let result = parse_transactions(input);
+    let messages: Vec<&str> = result.diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert!(messages.contains(&"price annotation '@@' missing amount"), "{messages:?}");
+    assert!(messages.contains(&"invalid price annotation: lots USD"), "{messages:?}");
+    assert!(
+      messages.contains(&"price commodity must differ from posting commodity: SOL"),
+      "{messages:?}"
+    );
+  }
+
+  #[test]
+  fn reports_malformed_lot_cost_bodies() {
+    let input = r#"2026-01-15 * "Binance" "Buy SOL" ; txn:01J2N9R9
+    assets:exchange:binance:sol    10 SOL {{ 230.00 USD, , fee:, date:soon }}
+    assets:exchange:binance:btc    1 BTC { 30000 USD
+"#;
+
+    let result = parse_transactions(input);
+    let messages: Vec<&str> = result.diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert!(messages.contains(&"empty lot field"), "{messages:?}");
+    assert!(messages.contains(&"lot field fee missing value"), "{messages:?}");
+    assert!(messages.contains(&"lot date must be a date or datetime"), "{messages:?}");
+    assert!(
+      messages.contains(&"unterminated lot cost annotation (expected '}')"),
+      "{messages:?}"
+    );
+  }
+
+  #[test]
+  fn reports_unbalanced_transaction_residuals() {
+    let input = r#"2026-01-15 * "Binance" "Buy SOL" ; txn:01J2N9R9
+    assets:exchange:binance:sol    10.000000 SOL {{ 230.00 USD }}
     assets:cash:usd              -230.10 USD
+
+2026-01-16 * "Kraken" "Sell BTC" ; txn:01J2NB
+    assets:exchange:kraken:btc   -0.005000 BTC { 30000 USD } @@ 160.00 USD
+    assets:cash:usd               160.00 USD
+    income:trading:pnl            -10.00 USD
+
+2026-01-17 * "Shop" "Rounding" ; txn:01J2NC
+    expenses:food                 3.333 AUD
+    assets:cash:aud              -3.33 AUD
+
+2026-01-18 * "Shop" "Whole dollars" ; txn:01J2ND
+    assets:cash:aud               100 AUD
+    expenses:food                -99.60 AUD
+"#;
+
+    let result = parse_transactions(input);
+    let unbalanced: Vec<(usize, &str)> = result
+      .diagnostics
+      .iter()
+      .filter(|d| d.message.starts_with("transaction does not balance"))
+      .map(|d| (d.line, d.message.as_str()))
+      .collect();
+    // The tolerance follows the most precise amount written, so neither a third decimal nor a
+    // whole-number amount loosens it.
+    assert_eq!(
+      unbalanced,
+      [
+        (1, "transaction does not balance (residual: -0.10 USD)"),
+        (10, "transaction does not balance (residual: 0.003 AUD)"),
+        (14, "transaction does not balance (residual: 0.40 AUD)"),
+      ]
+    );
+
+    let mut options = ParseOptions::default();
+    options
+      .balance_tolerances
+      .insert("USD".to_string(), "0.10".parse().unwrap());
+    options.balance_tolerances.insert("AUD".to_string(), "0.50".parse().unwrap());
+    let result = parse_transactions_with_options(input, &options);
+    assert!(result.ok, "expected ok, got diagnostics: {:?}", result.diagnostics);
+  }
+
+  #[test]
+  fn parses_header_meta_tags_in_order() {
+    let input = r#"2026-01-15 * "Binance" "Buy SOL" ; txn:01J2N9R9, src:binance:order:999, note:"maker, fee", at:2026-01-15T09:31:22+11:00
+    assets:exchange:binance:sol    10 SOL @ 23.00 USD
+    assets:cash:usd              -230.00 USD
+"#;
+
+    let result = parse_transactions(input);
+    assert!(result.ok, "expected ok, got diagnostics: {:?}", result.diagnostics);
+
+    let txn = &result.transactions[0];
+    assert_eq!(txn.txn_id(), Some("01J2N9R9"));
+    assert_eq!(txn.tags.get("src"), Some("binance:order:999"));
+    assert_eq!(txn.tags.get("note"), Some("maker, fee"));
+    let keys: Vec<&str> = txn.tags.iter().map(|(k, _)| k).collect();
+    assert_eq!(keys, vec!["txn", "src", "note", "at"]);
+    assert_eq!(
+      serde_json::to_string(&txn.tags).unwrap(),
+      r#"{"txn":"01J2N9R9","src":"binance:order:999","note":"maker, fee","at":"2026-01-15T09:31:22+11:00"}"#
+    );
+  }
+
+  #[test]
+  fn reports_malformed_meta_tags() {
+    let input = r#"2026-01-15 * "Binance" "Buy SOL" ; txn:01J2N9R9, reviewed, src:, txn:again, note:two words
+    assets:cash:usd               0.00 USD
+"#;
+
+    let result = parse_transactions(input);
+    let messages: Vec<&str> = result.diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert!(messages.contains(&"malformed meta tag (expected key:value): reviewed"), "{messages:?}");
+    assert!(messages.contains(&"meta tag src missing value"), "{messages:?}");
+    assert!(messages.contains(&"duplicate meta tag: txn"), "{messages:?}");
+    assert!(messages.contains(&"invalid value for meta tag note: two words"), "{messages:?}");
+    assert_eq!(result.transactions[0].txn_id(), Some("01J2N9R9"));
+  }
+
+  #[test]
+  fn strict_txn_ids_require_valid_unique_ids_across_files() {
+    let options = ParseOptions {
+      require_txn_ids: true,
+      txn_id_syntax: TxnIdSyntax::Ulid,
+      ..ParseOptions::default()
+    };
+    let sources = vec![
+      LedgerSource {
+        path: "a.transactions".to_string(),
+        contents: r#"2026-01-15 * "A" "One" ; txn:01J2N9R9XK7Q4W3M5T8V6Y2Z1A
+    assets:cash:usd    0.00 USD
+
+2026-01-16 * "A" "Two" ; src:manual
+    assets:cash:usd    0.00 USD
+"#
+        .to_string(),
+      },
+      LedgerSource {
+        path: "b.transactions".to_string(),
+        contents: r#"2026-02-01 * "B" "Three" ; txn:01J2N9R9XK7Q4W3M5T8V6Y2Z1A
+    assets:cash:usd    0.00 USD
+
+2026-02-02 * "B" "Four" ; txn:gen-1234
+    assets:cash:usd    0.00 USD
+"#
+        .to_string(),
+      },
+    ];
+
+    let result = parse_sources(&sources, &options);
+    let found: Vec<(Option<&str>, usize, &str)> = result
+      .diagnostics
+      .iter()
+      .map(|d| (d.file.as_deref(), d.line, d.message.as_str()))
+      .collect();
+    assert_eq!(
+      found,
+      vec![
+        (Some("a.transactions"), 4, "missing txn id (expected txn:<ID> in header meta)"),
+        (Some("b.transactions"), 4, "invalid txn id (expected ULID): gen-1234"),
+        (
+          Some("b.transactions"),
+          1,
+          "duplicate txn id 01J2N9R9XK7Q4W3M5T8V6Y2Z1A (first used at a.transactions:1)"
+        ),
+      ]
+    );
+
+    let lenient = parse_sources(&sources, &ParseOptions::default());
+    assert!(lenient.ok, "expected ok, got diagnostics: {:?}", lenient.diagnostics);
+  }
+
+  #[test]
+  fn parses_posting_and_account_declaration_meta() {
+    let input = r#"account assets:CBA:smartaccess AUD ; bank:CBA, bsb:"062-000"
+    opening 100.00 ; src:statement:2025-12
+
+2026-01-16 * "Kraken" "Sell BTC" ; txn:01J2NB
+    assets:exchange:kraken:btc   -0.005000 BTC @@ 160.00 USD ; lot:kraken:btc:abc123
+    assets:cash:usd               160.00 USD ; note:"settled, T+1"
 "#;
 
     let result = parse_transactions(input);
     assert!(result.ok, "expected ok, got diagnostics: {:?}", result.diagnostics);
+
+    let postings = &result.transactions[0].postings;
+    assert_eq!(postings[0].tags.get("lot"), Some("kraken:btc:abc123"));
+    assert_eq!(postings[0].remainder.as_deref(), Some("@@ 160.00 USD"));
+    assert!(postings[0].price.is_some());
+    assert_eq!(postings[1].tags.get("note"), Some("settled, T+1"));
+    assert_eq!(postings[1].meta.as_deref(), Some(r#"note:"settled, T+1""#));
+    assert_eq!(postings[1].remainder, None);
+
+    let decl = &result.accounts[0];
+    let tags: Vec<(&str, &str)> = decl.tags.iter().collect();
+    assert_eq!(
+      tags,
+      vec![("bank", "CBA"), ("bsb", "062-000"), ("src", "statement:2025-12")]
+    );
+  }
+
+  #[test]
+  fn rejects_unexpected_text_after_posting() {
+    let input = r#"2026-01-16 * "Shop" "Lunch" ; txn:01J2NB
+    expenses:food                 10.00 USD lunch
+    assets:cash:usd              -10.00 USD
+"#;
+
+    let result = parse_transactions(input);
+    assert!(result
+      .diagnostics
+      .iter()
+      .any(|d| d.message == "unexpected text after posting: lunch" && d.line == 2));
+  }
+
+  #[test]
+  fn evaluates_balance_assertions_in_date_order_across_files() {
+    let sources = vec![
+      LedgerSource {
+        path: "archive/ledger-202601.transactions".to_string(),
+        contents: r#"account assets:CBA:smartaccess AUD
+    opening 1000.00
+
+2026-01-20 * "Rent" "January" ; txn:r1
+    assets:CBA:smartaccess   -400.00 AUD = 600.00 AUD
+    expenses:rent             400.00 AUD
+"#
+        .to_string(),
+      },
+      LedgerSource {
+        path: "ledger.transactions".to_string(),
+        contents: r#"2026-02-03 * "Shop" "Groceries" ; txn:g1
+    assets:CBA:smartaccess    -50.00 AUD = 500.00 AUD ; note:statement
+    expenses:food              50.00 AUD
+
+2026-02-01 * "Cafe" "Coffee" ; txn:c1
+    assets:CBA:smartaccess     -5.00 AUD = 595.00 AUD
+    expenses:food               5.00 AUD
+"#
+        .to_string(),
+      },
+    ];
+
+    let result = parse_sources(&sources, &ParseOptions::default());
+    let failures: Vec<(Option<&str>, usize, &str)> = result
+      .diagnostics
+      .iter()
+      .map(|d| (d.file.as_deref(), d.line, d.message.as_str()))
+      .collect();
+    assert_eq!(
+      failures,
+      vec![(
+        Some("ledger.transactions"),
+        2,
+        "balance assertion failed for assets:CBA:smartaccess: expected 500.00 AUD, actual 545.00 AUD"
+      )]
+    );
+    let posting = &result.transactions[1].postings[0];
+    assert_eq!(posting.tags.get("note"), Some("statement"));
+    assert_eq!(posting.remainder.as_deref(), Some("= 500.00 AUD"));
+
+    let options = ParseOptions {
+      skip_balance_assertions: true,
+      ..ParseOptions::default()
+    };
+    assert!(parse_sources(&sources, &options).diagnostics.is_empty());
+  }
+
+  #[test]
+  fn checks_balance_assertions_on_elided_postings() {
+    let input = r#"2026-01-10 * "Open" ; txn:o1
+    assets:cash      100.00 AUD
+    assets:wallet     10 USD
+    equity:opening = -100.00 AUD ; note:start
+
+2026-01-11 * "Move" ; txn:m1
+    assets:cash      -20.00 AUD
+    assets:bank = 30.00 AUD
+"#;
+
+    let result = parse_transactions(input);
+    let messages: Vec<(usize, &str)> =
+      result.diagnostics.iter().map(|d| (d.line, d.message.as_str())).collect();
+    assert_eq!(
+      messages,
+      [(8, "balance assertion failed for assets:bank: expected 30.00 AUD, actual 20.00 AUD")]
+    );
+
+    // Inferred in two commodities, the assertion stays on the last part.
+    let opening: Vec<(&str, Option<&str>)> = result.transactions[0].postings[2..]
+      .iter()
+      .map(|p| (p.commodity.as_str(), p.remainder.as_deref()))
+      .collect();
+    assert_eq!(opening, [("AUD", None), ("USD", Some("= -100.00 AUD"))]);
+    assert_eq!(result.transactions[0].postings[3].tags.get("note"), Some("start"));
   }
 
   #[test]
@@ -549,14 +3092,15 @@ mod tests {
       balance.totals,
       vec![CommodityAmount {
         commodity: "AUD".to_string(),
-        amount: 100.0
+        amount: "100.00".parse().unwrap()
       }]
     );
+    assert_eq!(balance.totals[0].amount.to_string(), "100.00");
   }
 
   #[test]
   fn rejects_posting_without_amount() {
-    let input = r#"2026-01-15 * \"Binance\" \"Buy SOL\" ; txn:01J2N9R9
+    let input = r#"2026-01-15 * "Binance" "Buy SOL" ; txn:01J2N9R9
     assets:exchange:binance:sol
 "#;
 
@@ -567,4 +3111,514 @@ mod tests {
       .iter()
       .any(|d| d.message.contains("missing amount")));
   }
+
+  #[test]
+  fn infers_elided_posting_amount() {
+    let input = r#"2026-01-15 * "Binance" "Buy SOL" ; txn:01J2N9R9
+    assets:exchange:binance:sol    10.000000 SOL {{ 230.00 USD, fee:0.10 USD }}
+    assets:exchange:binance:usd ; note:"settled"
+"#;
+
+    let result = parse_transactions(input);
+    assert!(result.ok, "{:?}", result.diagnostics);
+    let cash = &result.transactions[0].postings[1];
+    assert!(cash.inferred);
+    assert_eq!(cash.amount_text, "-230.10");
+    assert_eq!(cash.commodity, "USD");
+    assert_eq!(cash.tags.get("note"), Some("settled"));
+
+    // Extra indentation before the account doesn't lose the meta comment.
+    let input = concat!(
+      "2026-01-15 * \"Café\" \"Lunch\" ; txn:01J2N9RA\n",
+      "    expenses:food    18.50 EUR\n",
+      "      assets:bank ; src:bank, note:\"carte\"\n",
+    );
+    let result = parse_transactions(input);
+    assert!(result.ok, "{:?}", result.diagnostics);
+    let bank = &result.transactions[0].postings[1];
+    assert_eq!(bank.amount_text, "-18.50");
+    assert_eq!(bank.meta.as_deref(), Some("src:bank, note:\"carte\""));
+    assert_eq!(bank.tags.get("src"), Some("bank"));
+  }
+
+  #[test]
+  fn reports_amounts_out_of_range() {
+    let input = r#"2026-01-15 * "Kraken" "Buy ETH" ; txn:01J2N9R9
+    assets:exchange:kraken:eth    1.000000000000000000 ETH { 3000.000000000000000000 USD }
+    assets:exchange:kraken:usd   -3000.00 USD
+
+2026-01-16 * "Kraken" "Buy ETH" ; txn:01J2N9RA
+    assets:exchange:kraken:eth    100000000000000000000.000000000000000001 ETH { 3000000000000000000 USD }
+    assets:exchange:kraken:usd    -1 USD
+
+2026-01-17 * "Kraken" "Deposit" ; txn:01J2N9RB
+    assets:exchange:kraken:usd    170141183460469231731.687303715884105727 USD
+    equity:opening
+
+2026-01-18 * "Kraken" "Deposit" ; txn:01J2N9RC
+    assets:exchange:kraken:usd    170141183460469231731.687303715884105727 USD
+    equity:opening
+"#;
+
+    let result = parse_transactions(input);
+    let errors: Vec<(usize, &str)> = result
+      .diagnostics
+      .iter()
+      .filter(|d| d.is_error())
+      .map(|d| (d.line, d.message.as_str()))
+      .collect();
+    assert_eq!(
+      errors,
+      [
+        (
+          5,
+          "amount out of range: 100000000000000000000.000000000000000001 ETH at its cost or \
+           price (line 6)",
+        ),
+        (14, "balance of assets:exchange:kraken:usd out of range in USD on 2026-01-18"),
+        (15, "balance of equity:opening out of range in USD on 2026-01-18"),
+      ]
+    );
+    let weight = result.transactions[0].postings[0].weight().unwrap();
+    assert_eq!(weight.amount.to_string(), "3000.000000000000000000");
+
+    let input = r#"account assets:cash USD
+    opening 170141183460469231731687303715884105727
+account assets:bank USD
+    opening 2026-01-16 170141183460469231731687303715884105727
+
+2026-01-15 * "Bank" "Deposit" ; txn:01J2N9R9
+    assets:cash     1 USD
+    assets:bank     1 USD
+    equity:opening -2 USD
+
+2026-01-17 * "Bank" "Interest" ; txn:01J2N9RA
+    assets:bank     1 USD
+    income:interest
+"#;
+    let result = parse_transactions(input);
+    let errors: Vec<(usize, &str)> = result
+      .diagnostics
+      .iter()
+      .filter(|d| d.is_error())
+      .map(|d| (d.line, d.message.as_str()))
+      .collect();
+    assert_eq!(
+      errors,
+      [
+        (7, "balance of assets:cash out of range in USD on 2026-01-15"),
+        (3, "balance of assets:bank out of range in USD on 2026-01-16"),
+      ]
+    );
+  }
+
+  #[test]
+  fn books_lot_fees_per_policy() {
+    let input = r#"2026-01-15 * "Binance" "Buy SOL" ; txn:01J2N9R9
+    assets:exchange:binance:sol    10 SOL {{ 230.00 USD, fee:0.10 USD, fee_to:expenses:fees:trading }}
+    assets:exchange:binance:usd   -230.10 USD
+
+2026-01-16 * "Kraken" "Buy BTC" ; txn:01J2N9RA
+    assets:exchange:kraken:btc     0.01 BTC { 30000 USD, fee:1.50 USD }
+    expenses:fees                  1.25 USD
+    assets:exchange:kraken:usd   -301.25 USD
+
+2026-01-17 * "Kraken" "Buy BTC" ; txn:01J2N9RB
+    assets:exchange:kraken:btc     0.01000000 BTC { 30000 USD, fee:1.50 USD }
+    assets:exchange:kraken:usd   -300.00 USD
+"#;
+
+    let unpaid =
+      "transaction does not balance (residual: 1.50 USD); no posting pays the lot fee of 1.50 USD";
+    let result = parse_transactions(input);
+    let fee = &result.transactions[0].postings[2];
+    let text = format!("{} {} {}", fee.account, fee.amount, fee.commodity);
+    assert_eq!((text.as_str(), fee.fee_for), ("expenses:fees:trading 0.10 USD", Some(2)));
+    let errors = result.diagnostics.iter().filter(|d| d.is_error());
+    let messages: Vec<(usize, &str)> = errors.map(|d| (d.line, d.message.as_str())).collect();
+    assert_eq!(
+      messages,
+      [
+        (6, "fee 1.50 USD disagrees with the 1.25 USD posted to expenses:fees"),
+        (10, unpaid),
+      ]
+    );
+
+    let options = ParseOptions {
+      fee_policy: FeePolicy::Capitalize,
+      ..ParseOptions::default()
+    };
+    let result = parse_transactions_with_options(input, &options);
+    assert_eq!(result.transactions[0].postings.len(), 2);
+    let errors = result.diagnostics.iter().filter(|d| d.is_error());
+    let messages: Vec<(usize, &str)> = errors.map(|d| (d.line, d.message.as_str())).collect();
+    assert_eq!(
+      messages,
+      [
+        (6, "fee 1.50 USD is capitalized into the lot cost but also posted to expenses:fees"),
+        (5, unpaid),
+        (10, unpaid),
+      ]
+    );
+
+    // One posting can pay the fees of several lots charged to the same account.
+    let input = r#"2026-01-15 * "Binance" "Buy SOL" ; txn:01J2N9R9
+    assets:exchange:binance:sol    10 SOL {{ 230.00 USD, fee:0.10 USD, fee_to:expenses:fees:trading }}
+    assets:exchange:binance:eth    1 ETH {{ 3000.00 USD, fee:0.10 USD, fee_to:expenses:fees:trading }}
+    expenses:fees:trading          0.20 USD
+    assets:exchange:binance:usd   -3230.20 USD
+
+2026-01-16 * "Binance" "Buy SOL" ; txn:01J2N9RA
+    assets:exchange:binance:sol    10 SOL {{ 230.00 USD, fee:0.10 USD, fee_to:expenses:fees:trading }}
+    assets:exchange:binance:eth    1 ETH {{ 3000.00 USD, fee:0.10 USD, fee_to:expenses:fees:trading }}
+    expenses:fees:trading          0.10 USD
+    assets:exchange:binance:usd   -3230.10 USD
+"#;
+    let result = parse_transactions(input);
+    assert_eq!(result.transactions[0].postings.len(), 4);
+    let errors = result.diagnostics.iter().filter(|d| d.is_error());
+    let messages: Vec<(usize, &str)> = errors.map(|d| (d.line, d.message.as_str())).collect();
+    assert_eq!(
+      messages,
+      [(
+        8,
+        "fees 0.20 USD of 2 lots disagree with the 0.10 USD posted to expenses:fees:trading"
+      )]
+    );
+  }
+
+  #[test]
+  fn splits_elided_amount_across_commodities() {
+    let input = r#"2026-01-15 * "Wise" "Top up" ; txn:01J2N9R9
+    assets:wise:aud    100.00 AUD
+    assets:wise:usd    65.00 USD
+    equity:transfers
+"#;
+
+    let result = parse_transactions(input);
+    assert!(result.ok, "{:?}", result.diagnostics);
+    let inferred: Vec<String> = result.transactions[0]
+      .postings
+      .iter()
+      .filter(|p| p.inferred)
+      .map(|p| format!("{} {} {}", p.account, p.amount, p.commodity))
+      .collect();
+    assert_eq!(inferred, ["equity:transfers -100.00 AUD", "equity:transfers -65.00 USD"]);
+  }
+
+  #[test]
+  fn rejects_second_elided_posting() {
+    let input = r#"2026-01-15 * "Coles" "Groceries" ; txn:01J2N9R9
+    expenses:groceries    42.50 AUD
+    liabilities:CBA:visa
+    assets:CBA:savings
+"#;
+
+    let result = parse_transactions(input);
+    assert!(!result.ok);
+    assert_eq!(result.diagnostics.len(), 1);
+    assert_eq!(result.diagnostics[0].line, 4);
+    assert!(result.diagnostics[0].message.contains("only one posting"));
+  }
+
+  #[test]
+  fn parses_commodity_declarations_and_formats_balances() {
+    let input = r#"commodity USD ; region:us
+    precision 2
+    symbol "$"
+    name "US Dollar"
+    kind fiat
+
+commodity SOL
+    precision 4
+    kind crypto
+
+2026-01-15 * "Binance" "Buy SOL" ; txn:01J2N9R9
+    assets:exchange:binance:sol    10.123456 SOL {{ 230.00 USD }}
+    assets:cash:usd               -230 USD
+"#;
+
+    let result = parse_transactions(input);
+    assert!(result.ok, "{:?}", result.diagnostics);
+    let usd = &result.commodities[0];
+    assert_eq!(usd.commodity, "USD");
+    assert_eq!(usd.precision, Some(2));
+    assert_eq!(usd.symbol.as_deref(), Some("$"));
+    assert_eq!(usd.name.as_deref(), Some("US Dollar"));
+    assert_eq!(usd.kind, Some(CommodityKind::Fiat));
+    assert_eq!(usd.tags.get("region"), Some("us"));
+
+    let cash = result.balances.iter().find(|b| b.account == "assets:cash:usd").unwrap();
+    assert_eq!(cash.totals[0].amount.to_string(), "-230.00");
+    assert_eq!(cash.display["USD"], "-$230.00");
+    let sol = result.balances.iter().find(|b| b.account == "assets:exchange:binance:sol").unwrap();
+    assert_eq!(sol.display["SOL"], "10.1235 SOL");
+
+    // Too large to pad to the declared precision: kept as written.
+    let input = r#"commodity USD
+    precision 2
+
+2026-01-15 * "Bank" "Deposit" ; txn:01J2N9R9
+    assets:cash:usd    17014118346046923173168730371588410572 USD
+    equity:opening
+"#;
+    let result = parse_transactions(input);
+    assert!(result.ok, "{:?}", result.diagnostics);
+    let cash = result.balances.iter().find(|b| b.account == "assets:cash:usd").unwrap();
+    assert_eq!(cash.display["USD"], "17014118346046923173168730371588410572 USD");
+  }
+
+  #[test]
+  fn rejects_malformed_commodity_declarations() {
+    let input = r#"commodity USD
+    precision two
+    kind bond
+    colour green
+
+commodity USD
+"#;
+
+    let result = parse_transactions(input);
+    let messages: Vec<&str> = result.diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(
+      messages,
+      [
+        "invalid commodity precision: two",
+        "unknown commodity kind: bond (expected fiat, crypto or stock)",
+        "unknown commodity declaration entry: colour",
+        "duplicate commodity declaration USD (first declared at line 1)",
+      ]
+    );
+  }
+
+  #[test]
+  fn strict_mode_requires_declared_commodities() {
+    let input = r#"commodity USD
+    precision 2
+
+account assets:wise:aud AUD
+account assets:exchange:binance:sol
+account assets:cash:usd
+    opening 500.00 USD
+
+2026-01-15 * "Binance" "Buy SOL" ; txn:01J2N9R9
+    assets:exchange:binance:sol    10.000000 SOL {{ 230.001 USD }}
+    assets:cash:usd               -230.001 USD
+"#;
+
+    let options = ParseOptions {
+      strict: true,
+      ..ParseOptions::default()
+    };
+    assert!(parse_transactions(input).ok);
+    let result = parse_transactions_with_options(input, &options);
+    let messages: Vec<(usize, &str)> = result
+      .diagnostics
+      .iter()
+      .map(|d| (d.line, d.message.as_str()))
+      .collect();
+    assert_eq!(
+      messages,
+      [
+        (4, "undeclared commodity: AUD"),
+        (10, "undeclared commodity: SOL"),
+        (11, "amount -230.001 USD has more decimal places than the declared precision (2)"),
+      ]
+    );
+  }
+
+  #[test]
+  fn rejects_malformed_price_directives() {
+    let result = parse_transactions(
+      r#"price 2026-01-10 BTC
+price yesterday BTC 1 AUD
+price 2026-01-10 BTC -1 AUD
+price 2026-01-10 AUD 1 AUD
+price 2026-01-10 BTC -1 AUD ; source:manual
+"#,
+    );
+    let messages: Vec<(usize, &str)> =
+      result.diagnostics.iter().map(|d| (d.column, d.message.as_str())).collect();
+    assert_eq!(
+      messages,
+      [
+        (20, "price directive missing amount"),
+        (6, "invalid price date: yesterday"),
+        (21, "price must be positive"),
+        (21, "price of AUD must be quoted in another commodity"),
+        (21, "price must be positive"),
+      ]
+    );
+    assert!(result.prices.is_empty());
+  }
+
+  #[test]
+  fn strict_option_suggests_declared_names() {
+    let input = r#"option strict
+
+commodity USD
+account assets:cash:usd
+account expenses:groceries
+
+2026-01-15 * "Coles" "Groceries" ; txn:01J2N9R9
+    expenses:grocery    42.50 UDS
+    assets:cash:uds    -42.50 USD
+    equity:opening      0 USD
+"#;
+
+    let result = parse_transactions(input);
+    assert!(result.strict);
+    let messages: Vec<&str> = result
+      .diagnostics
+      .iter()
+      .map(|d| d.message.as_str())
+      .filter(|m| m.starts_with("undeclared"))
+      .collect();
+    assert_eq!(
+      messages,
+      [
+        "undeclared account: expenses:grocery (did you mean expenses:groceries?)",
+        "undeclared account: assets:cash:uds (did you mean assets:cash:usd?)",
+        "undeclared account: equity:opening",
+        "undeclared commodity: UDS (did you mean USD?)",
+      ]
+    );
+  }
+
+  #[test]
+  fn flags_postings_outside_account_lifetime() {
+    let input = r#"account assets:CBA:smartaccess AUD
+    open 2026-01-01
+    opening 100.00
+    close 2026-01-31
+
+account expenses:groceries AUD
+
+2025-12-31 * "Coles" "Groceries" ; txn:01J2N9R9
+    expenses:groceries        10.00 AUD
+    assets:CBA:smartaccess   -10.00 AUD
+
+2026-01-31 * "Coles" "Groceries" ; txn:01J2N9RA
+    expenses:groceries        20.00 AUD
+    assets:CBA:smartaccess   -20.00 AUD
+
+2026-02-01 * "Coles" "Groceries" ; txn:01J2N9RB
+    expenses:groceries        30.00 AUD
+    assets:CBA:smartaccess   -30.00 AUD
+"#;
+
+    let result = parse_transactions(input);
+    let messages: Vec<(usize, &str)> = result
+      .diagnostics
+      .iter()
+      .map(|d| (d.line, d.message.as_str()))
+      .collect();
+    assert_eq!(
+      messages,
+      [
+        (
+          10,
+          "posting to assets:CBA:smartaccess dated 2025-12-31 before it was opened on 2026-01-01"
+        ),
+        (
+          18,
+          "posting to assets:CBA:smartaccess dated 2026-02-01 after it was closed on 2026-01-31"
+        ),
+      ]
+    );
+
+    assert!(result.balances.iter().all(|b| b.account != "assets:CBA:smartaccess"));
+    assert_eq!(result.closed_balances.len(), 1);
+    assert_eq!(result.closed_balances[0].totals[0].amount.to_string(), "40.00");
+  }
+
+  #[test]
+  fn allows_one_dated_opening_per_commodity() {
+    let input = r#"account assets:exchange:binance
+    opening 2026-01-10 1.50 SOL
+    opening 200.00 USD
+    opening 50.00 USD
+
+2026-01-05 * "Binance" "Check" ; txn:01J2N9R9
+    assets:exchange:binance    0 SOL = 0 SOL
+    assets:exchange:binance    0 USD = 200.00 USD
+
+2026-01-10 * "Binance" "Check" ; txn:01J2N9RA
+    assets:exchange:binance    0 SOL = 1.50 SOL
+"#;
+
+    let result = parse_transactions(input);
+    let messages: Vec<&str> = result.diagnostics.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(messages, ["duplicate opening declaration for USD"]);
+
+    let decl = &result.accounts[0];
+    assert_eq!(decl.openings.len(), 2);
+    assert_eq!(decl.openings[0].date.as_deref(), Some("2026-01-10"));
+    assert_eq!(decl.openings[1].date, None);
+    let totals: Vec<String> = result.balances[0]
+      .totals
+      .iter()
+      .map(|t| format!("{} {}", t.amount, t.commodity))
+      .collect();
+    assert_eq!(totals, ["1.50 SOL", "200.00 USD"]);
+  }
+
+  #[test]
+  fn classifies_accounts_and_warns_on_unconventional_signs() {
+    let input = r#"account assets:loans:car ; type:liability
+account assets:CBA:smartaccess
+    opening 100.00 AUD
+account equity:float ; type:capital
+
+2026-01-15 * "Coles" "Groceries" ; txn:01J2N9R9
+    expenses:groceries        142.50 AUD
+    assets:CBA:smartaccess   -142.50 AUD
+
+2026-01-16 * "Toyota" "Repayment" ; txn:01J2N9RA
+    assets:loans:car          300.00 AUD
+    asset:CBA:savings        -300.00 AUD
+"#;
+
+    let result = parse_transactions(input);
+    assert!(!result.ok);
+    let messages: Vec<(Severity, &str)> = result
+      .diagnostics
+      .iter()
+      .map(|d| (d.severity, d.message.as_str()))
+      .collect();
+    assert_eq!(
+      messages,
+      [
+        (
+          Severity::Error,
+          "unknown account type: capital (expected asset, liability, equity, income or expense)",
+        ),
+        (
+          Severity::Error,
+          "account asset:CBA:savings has no account type: unknown root asset (did you mean assets?)",
+        ),
+        (
+          Severity::Warning,
+          "asset account assets:CBA:smartaccess goes negative on 2026-01-15: -42.50 AUD",
+        ),
+        (
+          Severity::Warning,
+          "liability account assets:loans:car goes negative on 2026-01-16: -300.00 AUD",
+        ),
+      ]
+    );
+    assert_eq!(result.account_types["assets:loans:car"], AccountType::Liability);
+    assert_eq!(result.account_types["expenses:groceries"], AccountType::Expense);
+    assert_eq!(result.account_types["equity:float"], AccountType::Equity);
+    assert!(!result.account_types.contains_key("asset:CBA:savings"));
+
+    let options = ParseOptions {
+      account_types: AccountTypes::default().with_root("asset", AccountType::Asset),
+      ..ParseOptions::default()
+    };
+    let input = input.replace("capital", "equity");
+    let result = parse_transactions_with_options(&input, &options);
+    assert!(result.ok, "{:?}", result.diagnostics);
+    assert_eq!(result.diagnostics.len(), 3);
+  }
 }
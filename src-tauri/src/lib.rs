@@ -1,3 +1,14 @@
+pub mod amount;
+pub mod accounts;
+pub mod gains;
+pub mod holdings;
 pub mod ledger_parser;
+pub mod lots;
+pub mod periodic;
+pub mod prices;
+pub mod query;
+pub mod register;
+pub mod reports;
+pub mod valuation;
 pub mod generated_ledger;
 pub mod generated_store;
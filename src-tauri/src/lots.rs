@@ -0,0 +1,775 @@
+use crate::amount::{Amount, MAX_SCALE};
+use crate::ledger_parser::{
+  net_of_fee, CommodityAmount, CostKind, Diagnostic, FeePolicy, LotCost, LotValue, ParseResult,
+  Posting, Severity, Transaction,
+};
+use crate::prices::{known_at, trim_rate, DERIVED_RATE_SCALE};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// How a reduction picks lots when it doesn't name one with `lot:`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BookingMethod {
+  /// Only unambiguous reductions: a single matching lot, or every matching lot in full.
+  Strict,
+  /// Oldest acquisition first.
+  #[default]
+  Fifo,
+  /// Newest acquisition first.
+  Lifo,
+  /// Highest unit cost first.
+  Hifo,
+  /// Every matching lot in proportion to its remaining quantity, so the reduction carries
+  /// their average cost.
+  Average,
+}
+
+impl BookingMethod {
+  /// Parses a `method:` value, ignoring case (`"FIFO"`, `fifo`, `avg`, ...).
+  pub fn parse(name: &str) -> Option<Self> {
+    match name.trim().to_ascii_lowercase().as_str() {
+      "strict" => Some(Self::Strict),
+      "fifo" => Some(Self::Fifo),
+      "lifo" => Some(Self::Lifo),
+      "hifo" => Some(Self::Hifo),
+      "average" | "avg" => Some(Self::Average),
+      _ => None,
+    }
+  }
+}
+
+/// Units of a commodity acquired together, at one cost.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Lot {
+  /// The acquisition's `lot:` id, or `<txn id or datetime>/<posting number>` when it has none.
+  pub id: String,
+  pub account: String,
+  pub commodity: String,
+  /// The annotation's date, or the datetime of the acquiring transaction.
+  pub acquired: String,
+  pub label: Option<String>,
+  /// Quantity acquired (or transferred in).
+  pub quantity: Amount,
+  pub remaining: Amount,
+  /// The acquiring posting's cost annotation as written.
+  pub annotation: Option<LotCost>,
+  /// Cost of one unit as written; `None` for units received without a cost annotation.
+  pub unit_cost: Option<CommodityAmount>,
+  /// Cost of the remaining quantity, including a capitalized fee.
+  pub cost_basis: Option<CommodityAmount>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub file: Option<String>,
+  /// 1-based line number of the acquiring posting.
+  pub line: usize,
+  pub txn_id: Option<String>,
+}
+
+impl Lot {
+  /// Removes `quantity` units, returning the part taken with its share of the cost basis, or
+  /// `None` (leaving the lot as it was) when that share is out of range.
+  fn take(&mut self, quantity: Amount) -> Option<LotMatch> {
+    let cost_basis = match (&self.unit_cost, &self.cost_basis) {
+      (Some(unit), Some(basis)) => {
+        let taken = if quantity == self.remaining {
+          basis.amount
+        } else if unit.amount.checked_mul(&self.remaining) == Some(basis.amount) {
+          trim_rate(unit.amount.checked_mul(&quantity)?)
+        } else {
+          // The basis carries more than the unit cost (a capitalized fee): take it pro rata.
+          let scale = (unit.amount.scale() + quantity.scale()).max(basis.amount.scale());
+          let share = basis.amount.checked_mul(&quantity)?;
+          trim_rate(share.checked_div(&self.remaining, scale.min(MAX_SCALE))?)
+        };
+        Some((basis.amount.checked_sub(&taken)?, unit.commodity.clone(), taken))
+      }
+      _ => None,
+    };
+    self.remaining = self.remaining.checked_sub(&quantity)?;
+    let cost_basis = cost_basis.map(|(left, commodity, taken)| {
+      if let Some(basis) = &mut self.cost_basis {
+        basis.amount = left;
+      }
+      CommodityAmount {
+        commodity,
+        amount: taken,
+      }
+    });
+    Some(LotMatch {
+      lot_id: Some(self.id.clone()),
+      acquired: Some(self.acquired.clone()),
+      label: self.label.clone(),
+      quantity,
+      unit_cost: self.unit_cost.clone(),
+      cost_basis,
+    })
+  }
+}
+
+/// The part of one lot consumed by a reduction.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LotMatch {
+  /// `None` (like `acquired`) when the units weren't held in a known lot and the reduction's own
+  /// annotation supplied their cost.
+  pub lot_id: Option<String>,
+  pub acquired: Option<String>,
+  pub label: Option<String>,
+  pub quantity: Amount,
+  pub unit_cost: Option<CommodityAmount>,
+  /// Cost basis of `quantity`.
+  pub cost_basis: Option<CommodityAmount>,
+}
+
+/// A posting that took units out of an account's lots.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LotReduction {
+  pub date: String,
+  pub datetime: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub file: Option<String>,
+  /// 1-based line number of the reducing posting.
+  pub line: usize,
+  pub txn_id: Option<String>,
+  pub account: String,
+  pub commodity: String,
+  /// Units taken out (positive).
+  pub quantity: Amount,
+  /// Total of the posting's `@`/`@@` price, e.g. sale proceeds.
+  pub price: Option<CommodityAmount>,
+  /// The posting's `fee:` field when capitalized (see [`FeePolicy::Capitalize`]).
+  pub fee: Option<CommodityAmount>,
+  /// Account the lots moved to, for a transfer between accounts.
+  pub transferred_to: Option<String>,
+  pub matches: Vec<LotMatch>,
+}
+
+/// Lots still held after every transaction, and how each reduction was matched.
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct LotInventory {
+  /// Lots with a remaining quantity, by account and commodity, oldest first.
+  pub lots: Vec<Lot>,
+  /// Reductions in date order.
+  pub reductions: Vec<LotReduction>,
+  /// Over-sold, ambiguous or unmatched reductions, duplicate lot ids and unknown methods.
+  /// Reductions whose missing units were costed from their own annotation are warnings.
+  pub diagnostics: Vec<Diagnostic>,
+}
+
+fn error(file: Option<&String>, line: usize, message: String) -> Diagnostic {
+  Diagnostic {
+    line,
+    column: 0,
+    message,
+    file: file.cloned(),
+    severity: Severity::Error,
+  }
+}
+
+/// Whether `lot` is one of the lots a `{ ... }` annotation on a reduction selects.
+fn selects(spec: &LotCost, quantity: Amount, lot: &Lot) -> bool {
+  if let Some(id) = &spec.lot_id {
+    return lot.id == *id;
+  }
+  if spec.date.as_ref().is_some_and(|date| !lot.acquired.starts_with(date.as_str())) {
+    return false;
+  }
+  if spec.label.is_some() && spec.label != lot.label {
+    return false;
+  }
+  let Some(cost) = &spec.amount else {
+    return true;
+  };
+  let Some(unit) = &lot.unit_cost else {
+    return false;
+  };
+  // Costs are matched as written, never including a capitalized fee.
+  let written = lot.annotation.as_ref().filter(|a| a.kind == CostKind::PerUnit);
+  let per_unit = match (spec.kind, written.and_then(|a| a.amount.as_ref())) {
+    (CostKind::PerUnit, Some(written)) => return cost == written,
+    (CostKind::PerUnit, None) => Some(cost.amount),
+    (CostKind::Total, _) => cost.amount.checked_div(&quantity, DERIVED_RATE_SCALE),
+  };
+  cost.commodity == unit.commodity && per_unit == Some(unit.amount)
+}
+
+/// For each posting moving units out of a tracked account without a price, the index of a
+/// posting in the same transaction receiving the same units into another account without a cost
+/// annotation: the lots move along instead of being disposed of.
+fn transfer_targets(
+  txn: &Transaction,
+  tracked: &BTreeMap<(String, String), Vec<Lot>>,
+) -> BTreeMap<usize, usize> {
+  let mut targets = BTreeMap::new();
+  let mut used = BTreeSet::new();
+  for (index, posting) in txn.postings.iter().enumerate() {
+    let key = (posting.account.clone(), posting.commodity.clone());
+    if !posting.amount.is_negative() || posting.price.is_some() || !tracked.contains_key(&key) {
+      continue;
+    }
+    let target = txn.postings.iter().enumerate().position(|(other, p)| {
+      !used.contains(&other)
+        && p.account != posting.account
+        && p.commodity == posting.commodity
+        && p.amount == -posting.amount
+        && p.lot_cost.is_none()
+    });
+    if let Some(target) = target {
+      used.insert(target);
+      targets.insert(index, target);
+    }
+  }
+  targets
+}
+
+struct Inventory<'a> {
+  default_method: BookingMethod,
+  fee_policy: FeePolicy,
+  account_methods: BTreeMap<&'a str, BookingMethod>,
+  /// Lots by (account, commodity) in acquisition order, including used-up ones; a key is
+  /// present once the account has held lots of the commodity.
+  lots: BTreeMap<(String, String), Vec<Lot>>,
+  /// Where each explicit `lot:` id was first acquired.
+  ids: BTreeMap<String, (Option<String>, usize)>,
+  reductions: Vec<LotReduction>,
+  diagnostics: Vec<Diagnostic>,
+}
+
+impl Inventory<'_> {
+  fn acquire(&mut self, txn: &Transaction, number: usize, posting: &Posting) {
+    let cost = posting.lot_cost.as_ref();
+    let quantity = posting.amount;
+    let (unit_cost, cost_basis) = match cost.and_then(|c| c.amount.as_ref().map(|a| (c.kind, a))) {
+      Some((CostKind::PerUnit, amount)) => {
+        let Some(basis) = amount.amount.checked_mul(&quantity) else {
+          let message = format!("cost of {quantity} {} out of range", posting.commodity);
+          self.diagnostics.push(error(txn.file.as_ref(), posting.line, message));
+          return;
+        };
+        (amount.amount, trim_rate(basis))
+      }
+      Some((CostKind::Total, amount)) => {
+        let unit = amount.amount.checked_div(&quantity, DERIVED_RATE_SCALE).unwrap_or_default();
+        (unit.normalize(), amount.amount)
+      }
+      None => (Amount::ZERO, Amount::ZERO),
+    };
+    // A capitalized fee in the cost commodity is part of what the lot cost.
+    let cost_amount = cost.and_then(|c| c.amount.as_ref());
+    let cost_basis = match (posting.capitalized_fee(self.fee_policy), cost_amount) {
+      (Some(fee), Some(written)) => {
+        let gross = CommodityAmount {
+          commodity: written.commodity.clone(),
+          amount: cost_basis,
+        };
+        net_of_fee(&gross, fee, true).map_or(cost_basis, |net| net.amount)
+      }
+      _ => cost_basis,
+    };
+    let cost_commodity = cost.and_then(|c| c.amount.as_ref()).map(|a| a.commodity.clone());
+    let with_commodity = |amount| {
+      cost_commodity.clone().map(|commodity| CommodityAmount { commodity, amount })
+    };
+
+    let explicit = cost.and_then(|c| c.lot_id.clone());
+    if let Some(id) = &explicit {
+      if let Some((file, line)) = self.ids.get(id) {
+        let first = match file {
+          Some(file) => format!("{file}:{line}"),
+          None => format!("line {line}"),
+        };
+        let message = format!("duplicate lot id {id} (first acquired at {first})");
+        self.diagnostics.push(error(txn.file.as_ref(), posting.line, message));
+        return;
+      }
+      self.ids.insert(id.clone(), (txn.file.clone(), posting.line));
+    }
+    let id = explicit.unwrap_or_else(|| {
+      format!("{}/{number}", txn.txn_id().unwrap_or(txn.datetime.as_str()))
+    });
+
+    let lot = Lot {
+      id,
+      account: posting.account.clone(),
+      commodity: posting.commodity.clone(),
+      acquired: cost.and_then(|c| c.date.clone()).unwrap_or_else(|| txn.datetime.clone()),
+      label: cost.and_then(|c| c.label.clone()),
+      quantity,
+      remaining: quantity,
+      annotation: cost.cloned(),
+      unit_cost: with_commodity(unit_cost),
+      cost_basis: with_commodity(cost_basis),
+      file: txn.file.clone(),
+      line: posting.line,
+      txn_id: txn.txn_id().map(str::to_string),
+    };
+    self.insert(lot);
+  }
+
+  /// Adds `lot`, keeping the account's lots in acquisition order.
+  fn insert(&mut self, lot: Lot) {
+    let lots = self.lots.entry((lot.account.clone(), lot.commodity.clone())).or_default();
+    let at = lots.partition_point(|held| held.acquired <= lot.acquired);
+    lots.insert(at, lot);
+  }
+
+  fn method(&mut self, txn: &Transaction, posting: &Posting) -> BookingMethod {
+    let field = posting.lot_cost.as_ref().and_then(|c| c.field("method"));
+    let name = match field {
+      None => {
+        let account = self.account_methods.get(posting.account.as_str());
+        return account.copied().unwrap_or(self.default_method);
+      }
+      Some(LotValue::Text(name)) => name.clone(),
+      Some(LotValue::Date(text)) => text.clone(),
+      Some(LotValue::Amount(amount)) => format!("{} {}", amount.amount, amount.commodity),
+    };
+    BookingMethod::parse(&name).unwrap_or_else(|| {
+      let message = format!(
+        "unknown booking method: {name} (expected strict, fifo, lifo, hifo or average)"
+      );
+      self.diagnostics.push(error(txn.file.as_ref(), posting.line, message));
+      self.default_method
+    })
+  }
+
+  fn reduce(&mut self, txn: &Transaction, posting: &Posting, target: Option<&Posting>) {
+    let method = self.method(txn, posting);
+    let quantity = posting.amount.abs();
+    let (account, commodity) = (&posting.account, &posting.commodity);
+    let report = |message: String| error(txn.file.as_ref(), posting.line, message);
+    let lots = self.lots.entry((account.clone(), commodity.clone())).or_default();
+
+    let mut candidates: Vec<usize> = (0..lots.len())
+      .filter(|&i| lots[i].remaining.is_positive())
+      .filter(|&i| posting.lot_cost.as_ref().is_none_or(|spec| selects(spec, quantity, &lots[i])))
+      .collect();
+    let spec = posting.lot_cost.as_ref();
+    let annotated = spec.and_then(|c| c.amount.as_ref().map(|a| (c.kind, a)));
+    let out_of_range = || report(format!("cost of {quantity} {commodity} out of range"));
+    let available = candidates
+      .iter()
+      .try_fold(Amount::ZERO, |total, &i| total.checked_add(&lots[i].remaining));
+    let Some((available, shortfall)) =
+      available.and_then(|available| Some((available, quantity.checked_sub(&available)?)))
+    else {
+      self.diagnostics.push(out_of_range());
+      return;
+    };
+    if shortfall.is_positive() {
+      let spec_id = spec.and_then(|c| c.lot_id.as_deref()).filter(|_| candidates.is_empty());
+      let message = match spec_id {
+        Some(id) => format!("no lot {id} of {commodity} held in {account}"),
+        None => format!(
+          "cannot reduce {quantity} {commodity} from {account}: only {available} {commodity} held \
+           in matching lots"
+        ),
+      };
+      if annotated.is_some() {
+        let message = format!("{message}; using the annotated cost");
+        self.diagnostics.push(Diagnostic {
+          severity: Severity::Warning,
+          ..report(message)
+        });
+      } else {
+        self.diagnostics.push(report(message));
+        if spec_id.is_some() {
+          return;
+        }
+      }
+    }
+    if method == BookingMethod::Strict && candidates.len() > 1 && quantity < available {
+      let message = format!(
+        "ambiguous reduction of {quantity} {commodity} from {account}: {} lots match (name one \
+         with lot:, a date, a label or a cost, or use another booking method)",
+        candidates.len()
+      );
+      self.diagnostics.push(report(message));
+      return;
+    }
+
+    match method {
+      BookingMethod::Lifo => {
+        candidates.reverse();
+        candidates.sort_by(|&a, &b| lots[b].acquired.cmp(&lots[a].acquired));
+      }
+      BookingMethod::Hifo => {
+        let unit = |i: usize| lots[i].unit_cost.as_ref().map(|c| c.amount);
+        candidates.sort_by_key(|&i| std::cmp::Reverse(unit(i)));
+      }
+      _ => {}
+    }
+
+    let mut takes: Vec<(usize, Amount)> = Vec::new();
+    if method == BookingMethod::Average && quantity < available {
+      let scale = candidates.iter().map(|&i| lots[i].remaining.scale()).max().unwrap_or(0);
+      let scale = scale.max(quantity.scale());
+      let mut left = quantity;
+      for (n, &i) in candidates.iter().enumerate() {
+        let share = if n + 1 == candidates.len() {
+          left
+        } else {
+          let share = quantity.checked_mul(&lots[i].remaining);
+          share.and_then(|s| s.checked_div(&available, scale)).unwrap_or_default().min(left)
+        };
+        let Some(rest) = left.checked_sub(&share) else {
+          self.diagnostics.push(out_of_range());
+          return;
+        };
+        left = rest;
+        takes.push((i, share));
+      }
+    } else {
+      let mut left = quantity;
+      for &i in &candidates {
+        if !left.is_positive() {
+          break;
+        }
+        let share = lots[i].remaining.min(left);
+        let Some(rest) = left.checked_sub(&share) else {
+          self.diagnostics.push(out_of_range());
+          return;
+        };
+        left = rest;
+        takes.push((i, share));
+      }
+    }
+
+    let matches: Option<Vec<LotMatch>> = takes
+      .into_iter()
+      .filter(|(_, share)| share.is_positive())
+      .map(|(i, share)| lots[i].take(share))
+      .collect();
+    let Some(mut matches) = matches else {
+      self.diagnostics.push(out_of_range());
+      return;
+    };
+    if let (Some((kind, cost)), true) = (annotated, shortfall.is_positive()) {
+      let (unit, basis) = match kind {
+        CostKind::PerUnit => (cost.amount, cost.amount.checked_mul(&shortfall)),
+        CostKind::Total => {
+          let unit = cost.amount.checked_div(&quantity, DERIVED_RATE_SCALE).unwrap_or_default();
+          let basis = if available.is_zero() {
+            Some(cost.amount)
+          } else {
+            unit.checked_mul(&shortfall)
+          };
+          (unit.normalize(), basis)
+        }
+      };
+      let Some(basis) = basis.map(trim_rate) else {
+        self.diagnostics.push(out_of_range());
+        return;
+      };
+      let with_commodity = |amount| CommodityAmount {
+        commodity: cost.commodity.clone(),
+        amount,
+      };
+      matches.push(LotMatch {
+        lot_id: spec.and_then(|c| c.lot_id.clone()),
+        acquired: spec.and_then(|c| c.date.clone()),
+        label: spec.and_then(|c| c.label.clone()),
+        quantity: shortfall,
+        unit_cost: Some(with_commodity(unit)),
+        cost_basis: Some(with_commodity(basis)),
+      });
+    }
+
+    if let Some(target) = target {
+      for (n, taken) in matches.iter().enumerate() {
+        let source = self.lots[&(account.clone(), commodity.clone())]
+          .iter()
+          .find(|lot| Some(&lot.id) == taken.lot_id.as_ref());
+        let origin = |lot: &Lot| (lot.file.clone(), lot.line, lot.txn_id.clone());
+        let (file, line, txn_id) = source.map_or_else(
+          || (txn.file.clone(), target.line, txn.txn_id().map(str::to_string)),
+          origin,
+        );
+        let annotation = source.map_or_else(|| spec.cloned(), |lot| lot.annotation.clone());
+        let generated = || format!("{}/{}", txn.txn_id().unwrap_or(&txn.datetime), n + 1);
+        self.insert(Lot {
+          id: taken.lot_id.clone().unwrap_or_else(generated),
+          account: target.account.clone(),
+          commodity: commodity.clone(),
+          acquired: taken.acquired.clone().unwrap_or_else(|| txn.datetime.clone()),
+          label: taken.label.clone(),
+          quantity: taken.quantity,
+          remaining: taken.quantity,
+          annotation,
+          unit_cost: taken.unit_cost.clone(),
+          cost_basis: taken.cost_basis.clone(),
+          file,
+          line,
+          txn_id,
+        });
+      }
+    }
+
+    self.reductions.push(LotReduction {
+      date: txn.date.clone(),
+      datetime: txn.datetime.clone(),
+      file: txn.file.clone(),
+      line: posting.line,
+      txn_id: txn.txn_id().map(str::to_string),
+      account: account.clone(),
+      commodity: commodity.clone(),
+      quantity,
+      price: posting.total_price(),
+      fee: posting.capitalized_fee(self.fee_policy).map(|fee| CommodityAmount {
+        commodity: fee.commodity.clone(),
+        amount: fee.amount.abs(),
+      }),
+      transferred_to: target.map(|t| t.account.clone()),
+      matches,
+    });
+  }
+}
+
+/// Tracks lots through every transaction in date order.
+///
+/// A posting with a `{ ... }`/`{{ ... }}` annotation and a positive amount acquires a lot; one
+/// with a negative amount reduces the lots it selects (by `lot:` id, or by the date, label and
+/// cost it names). Once an account holds lots of a commodity, postings without an annotation
+/// acquire (at unknown cost) and reduce its lots too, unless they move the units to another
+/// account in the same transaction, which carries the lots over.
+///
+/// Reductions that don't name a lot pick them by the annotation's `method:` field, else the
+/// `method:` tag of the account declaration, else `default_method`.
+pub fn lot_inventory(result: &ParseResult, default_method: BookingMethod) -> LotInventory {
+  build(result, default_method, None)
+}
+
+/// [`lot_inventory`] as of `date` (a date or a full datetime): only transactions known at that
+/// point are applied.
+pub fn lot_inventory_at(
+  result: &ParseResult,
+  default_method: BookingMethod,
+  date: &str,
+) -> LotInventory {
+  build(result, default_method, Some(date))
+}
+
+fn build(result: &ParseResult, default_method: BookingMethod, date: Option<&str>) -> LotInventory {
+  let mut inventory = Inventory {
+    default_method,
+    fee_policy: result.fee_policy,
+    account_methods: BTreeMap::new(),
+    lots: BTreeMap::new(),
+    ids: BTreeMap::new(),
+    reductions: Vec::new(),
+    diagnostics: Vec::new(),
+  };
+  for decl in &result.accounts {
+    let Some(name) = decl.tags.get("method") else {
+      continue;
+    };
+    match BookingMethod::parse(name) {
+      Some(method) => {
+        inventory.account_methods.insert(&decl.account, method);
+      }
+      None => {
+        let message = format!(
+          "unknown booking method: {name} (expected strict, fifo, lifo, hifo or average)"
+        );
+        inventory.diagnostics.push(error(decl.file.as_ref(), decl.line, message));
+      }
+    }
+  }
+
+  let mut ordered: Vec<&Transaction> = result
+    .transactions
+    .iter()
+    .filter(|t| date.is_none_or(|date| known_at(&t.datetime, date)))
+    .collect();
+  ordered.sort_by(|a, b| a.datetime.cmp(&b.datetime));
+  for txn in ordered {
+    let targets = transfer_targets(txn, &inventory.lots);
+    let received: BTreeSet<usize> = targets.values().copied().collect();
+    for (index, posting) in txn.postings.iter().enumerate() {
+      let key = (posting.account.clone(), posting.commodity.clone());
+      let tracked = inventory.lots.contains_key(&key);
+      if posting.amount.is_zero() || received.contains(&index) {
+        continue;
+      }
+      if posting.amount.is_positive() && (posting.lot_cost.is_some() || tracked) {
+        inventory.acquire(txn, index + 1, posting);
+      } else if posting.amount.is_negative() && (posting.lot_cost.is_some() || tracked) {
+        let target = targets.get(&index).map(|&t| &txn.postings[t]);
+        inventory.reduce(txn, posting, target);
+      }
+    }
+  }
+
+  LotInventory {
+    lots: inventory
+      .lots
+      .into_values()
+      .flatten()
+      .filter(|lot| lot.remaining.is_positive())
+      .collect(),
+    reductions: inventory.reductions,
+    diagnostics: inventory.diagnostics,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ledger_parser::{parse_transactions, parse_transactions_with_options, ParseOptions};
+
+  const BUYS: &str = r#"2026-01-05 * "Kraken" "Buy BTC" ; txn:01J2N9R1
+    assets:exchange:kraken:btc    1 BTC { 30000 USD }
+    assets:exchange:kraken:usd   -30000 USD
+
+2026-01-10 * "Kraken" "Buy BTC" ; txn:01J2N9R2
+    assets:exchange:kraken:btc    1 BTC { 50000 USD, lot:kraken:btc:dip }
+    assets:exchange:kraken:usd   -50000 USD
+
+2026-01-15 * "Kraken" "Buy BTC" ; txn:01J2N9R3
+    assets:exchange:kraken:btc    2 BTC {{ 80000 USD }}
+    assets:exchange:kraken:usd   -80000 USD
+"#;
+
+  fn sell(spec: &str) -> String {
+    format!(
+      r#"{BUYS}
+2026-02-01 * "Kraken" "Sell BTC" ; txn:01J2N9R4
+    assets:exchange:kraken:btc   -1.5 BTC {spec} @ 60000 USD
+    assets:exchange:kraken:usd    90000 USD
+"#
+    )
+  }
+
+  fn taken(inventory: &LotInventory) -> Vec<(String, String, String)> {
+    let reduction = inventory.reductions.last().unwrap();
+    reduction
+      .matches
+      .iter()
+      .map(|m| {
+        let basis = m.cost_basis.as_ref().unwrap();
+        let basis = format!("{} {}", basis.amount, basis.commodity);
+        (m.lot_id.clone().unwrap(), m.quantity.to_string(), basis)
+      })
+      .collect()
+  }
+
+  fn owned(lots: &[(&str, &str, &str)]) -> Vec<(String, String, String)> {
+    lots.iter().map(|(a, b, c)| (a.to_string(), b.to_string(), c.to_string())).collect()
+  }
+
+  #[test]
+  fn matches_reductions_by_booking_method_and_lot_id() {
+    let result = parse_transactions(&sell("{}"));
+    assert!(result.ok, "{:?}", result.diagnostics);
+
+    let fifo = lot_inventory(&result, BookingMethod::Fifo);
+    assert!(fifo.diagnostics.is_empty(), "{:?}", fifo.diagnostics);
+    assert_eq!(
+      taken(&fifo),
+      owned(&[("01J2N9R1/1", "1", "30000 USD"), ("kraken:btc:dip", "0.5", "25000.0 USD")])
+    );
+    let remaining: Vec<String> = fifo.lots.iter().map(|l| l.remaining.to_string()).collect();
+    assert_eq!(remaining, ["0.5", "2"]);
+    assert_eq!(fifo.lots[0].cost_basis.as_ref().unwrap().amount.to_string(), "25000.0");
+    assert_eq!(fifo.reductions[0].price.as_ref().unwrap().amount.to_string(), "90000.0");
+
+    let lifo = lot_inventory(&result, BookingMethod::Lifo);
+    assert_eq!(taken(&lifo), owned(&[("01J2N9R3/1", "1.5", "60000.0 USD")]));
+    assert_eq!(lifo.lots[2].cost_basis.as_ref().unwrap().amount.to_string(), "20000.0");
+
+    let hifo = lot_inventory(&result, BookingMethod::Hifo);
+    assert_eq!(
+      taken(&hifo),
+      owned(&[("kraken:btc:dip", "1", "50000 USD"), ("01J2N9R3/1", "0.5", "20000.0 USD")])
+    );
+
+    let average = lot_inventory(&result, BookingMethod::Average);
+    let basis: Amount = average.reductions[0]
+      .matches
+      .iter()
+      .map(|m| m.cost_basis.as_ref().unwrap().amount)
+      .sum();
+    assert_eq!(basis.to_string(), "60000.0");
+
+    let result = parse_transactions(&sell(r#"{ lot:kraken:btc:dip, method:"FIFO" }"#));
+    let inventory = lot_inventory(&result, BookingMethod::Strict);
+    assert_eq!(inventory.diagnostics.len(), 1);
+    assert_eq!(
+      inventory.diagnostics[0].message,
+      "cannot reduce 1.5 BTC from assets:exchange:kraken:btc: only 1 BTC held in matching lots"
+    );
+    assert_eq!(taken(&inventory), owned(&[("kraken:btc:dip", "1", "50000 USD")]));
+  }
+
+  #[test]
+  fn reports_ambiguous_and_unmatched_reductions_and_carries_lots_across_transfers() {
+    let result = parse_transactions(&sell("{}"));
+    let strict = lot_inventory(&result, BookingMethod::Strict);
+    assert!(strict.diagnostics[0].message.starts_with("ambiguous reduction of 1.5 BTC"));
+    assert!(strict.reductions.is_empty());
+    assert_eq!(strict.lots.len(), 3);
+
+    let result = parse_transactions(&sell("{ 2026-01-15 }"));
+    let strict = lot_inventory(&result, BookingMethod::Strict);
+    assert!(strict.diagnostics.is_empty(), "{:?}", strict.diagnostics);
+    assert_eq!(taken(&strict), owned(&[("01J2N9R3/1", "1.5", "60000.0 USD")]));
+
+    let result = parse_transactions(&sell("{ lot:missing }"));
+    let inventory = lot_inventory(&result, BookingMethod::Fifo);
+    assert_eq!(
+      inventory.diagnostics[0].message,
+      "no lot missing of BTC held in assets:exchange:kraken:btc"
+    );
+
+    let result = parse_transactions(&format!(
+      r#"{BUYS}
+2026-02-01 * "Wallet" "Withdraw BTC" ; txn:01J2N9R4
+    assets:exchange:kraken:btc   -1.5 BTC
+    assets:wallet:btc             1.5 BTC
+"#
+    ));
+    assert!(result.ok, "{:?}", result.diagnostics);
+    let inventory = lot_inventory(&result, BookingMethod::Fifo);
+    assert!(inventory.diagnostics.is_empty(), "{:?}", inventory.diagnostics);
+    let reduction = &inventory.reductions[0];
+    assert_eq!(reduction.transferred_to.as_deref(), Some("assets:wallet:btc"));
+    let wallet: Vec<(&str, String, &str)> = inventory
+      .lots
+      .iter()
+      .filter(|l| l.account == "assets:wallet:btc")
+      .map(|l| (l.id.as_str(), l.remaining.to_string(), l.acquired.as_str()))
+      .collect();
+    assert_eq!(
+      wallet,
+      [
+        ("01J2N9R1/1", "1".to_string(), "2026-01-05"),
+        ("kraken:btc:dip", "0.5".to_string(), "2026-01-10"),
+      ]
+    );
+  }
+
+  #[test]
+  fn selects_lots_by_their_written_cost_when_fees_are_capitalized() {
+    let input = r#"2026-01-10 * "Binance" "Buy SOL" ; txn:01J2N9R1
+    assets:exchange:binance:sol    10 SOL { 23.00 USD, fee:1.00 USD }
+    assets:exchange:binance:usd   -231.00 USD
+
+2026-02-10 * "Binance" "Sell SOL" ; txn:01J2N9R2
+    assets:exchange:binance:sol    -4 SOL { 23.00 USD } @ 30.00 USD
+    assets:exchange:binance:usd    120.00 USD
+    equity:trading                 -28.00 USD
+"#;
+    let options = ParseOptions {
+      fee_policy: FeePolicy::Capitalize,
+      ..ParseOptions::default()
+    };
+    let result = parse_transactions_with_options(input, &options);
+    assert!(result.ok, "{:?}", result.diagnostics);
+
+    let inventory = lot_inventory(&result, BookingMethod::Strict);
+    assert!(inventory.diagnostics.is_empty(), "{:?}", inventory.diagnostics);
+    assert_eq!(taken(&inventory), owned(&[("01J2N9R1/1", "4", "92.40 USD")]));
+    let lot = &inventory.lots[0];
+    assert_eq!(lot.unit_cost.as_ref().unwrap().amount.to_string(), "23.00");
+    assert_eq!(lot.cost_basis.as_ref().unwrap().amount.to_string(), "138.60");
+  }
+}
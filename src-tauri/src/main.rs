@@ -2,11 +2,23 @@
 
 use tauri::Manager;
 use squirrel_covid::generated_ledger::rotate_ledger_if_needed;
-use squirrel_covid::generated_store::{add_account_declaration, add_manual_transaction, import_source_files, load_active_ledger, ImportStats, ManualTransactionInput};
+use squirrel_covid::generated_store::{add_account_declaration, add_manual_transaction, import_source_files, import_source_files_with_options, load_ledger_history, ImportOptions, ImportStats, ManualTransactionInput, OpeningBalanceInput};
+use squirrel_covid::accounts::{account_tree, AccountNode, AccountType};
+use squirrel_covid::gains::{realized_gains, RealizedGains};
+use squirrel_covid::holdings::{holdings, HoldingsReport};
+use squirrel_covid::lots::{lot_inventory, BookingMethod, LotInventory};
+use squirrel_covid::periodic::{periodic_report, Interval, PeriodicReport};
+use squirrel_covid::prices::{PriceDb, Rate};
+use squirrel_covid::query::{parse_query, run_query, QueryResult};
+use squirrel_covid::register::{register, RegisterQuery, RegisterRow};
+use squirrel_covid::reports::{balance_sheet, income_statement, BalanceSheet, IncomeStatement};
+use squirrel_covid::valuation::{value_balances, Valuation};
 use squirrel_covid::ledger_parser::{
-  parse_transactions, AccountBalance, Diagnostic, ParseResult, Transaction,
+  parse_transactions, AccountBalance, AccountDeclaration, CommodityDeclaration, Diagnostic,
+  ParseResult, PriceDirective, Transaction,
 };
 use serde::Serialize;
+use std::collections::BTreeMap;
 use std::env;
 use std::path::PathBuf;
 
@@ -16,6 +28,11 @@ struct ParseResponse {
   diagnostics: Vec<Diagnostic>,
   transactions: Vec<Transaction>,
   balances: Vec<AccountBalance>,
+  closed_balances: Vec<AccountBalance>,
+  accounts: Vec<AccountDeclaration>,
+  commodities: Vec<CommodityDeclaration>,
+  prices: Vec<PriceDirective>,
+  account_types: BTreeMap<String, AccountType>,
 }
 
 impl From<ParseResult> for ParseResponse {
@@ -25,6 +42,11 @@ impl From<ParseResult> for ParseResponse {
       diagnostics: result.diagnostics,
       transactions: result.transactions,
       balances: result.balances,
+      closed_balances: result.closed_balances,
+      accounts: result.accounts,
+      commodities: result.commodities,
+      prices: result.prices,
+      account_types: result.account_types,
     }
   }
 }
@@ -58,7 +80,7 @@ fn rotate_generated_ledger(app: tauri::AppHandle, now_yyyymm: String) -> Result<
 fn load_generated_ledger(app: tauri::AppHandle, now_yyyymm: String) -> Result<ParseResponse, String> {
   let generated_dir = resolve_generated_dir(&app)?;
   rotate_ledger_if_needed(&generated_dir, &now_yyyymm).map_err(|e| format!("rotate failed: {e}"))?;
-  let result = load_active_ledger(&generated_dir)?;
+  let result = load_ledger_history(&generated_dir)?;
   Ok(result.into())
 }
 
@@ -67,10 +89,16 @@ fn import_generated_sources(
   app: tauri::AppHandle,
   now_yyyymm: String,
   paths: Vec<String>,
+  strict: Option<bool>,
+  write_inferred_amounts: Option<bool>,
 ) -> Result<ImportResponse, String> {
   let generated_dir = resolve_generated_dir(&app)?;
-  let stats = import_source_files(&generated_dir, &now_yyyymm, &paths)?;
-  let result = load_active_ledger(&generated_dir)?;
+  let options = ImportOptions {
+    write_inferred_amounts: write_inferred_amounts.unwrap_or(false),
+    strict: strict.unwrap_or(false),
+  };
+  let stats = import_source_files_with_options(&generated_dir, &now_yyyymm, &paths, &options)?;
+  let result = load_ledger_history(&generated_dir)?;
   Ok(ImportResponse {
     stats,
     parse: result.into(),
@@ -85,7 +113,7 @@ fn add_manual_to_generated_ledger(
 ) -> Result<ParseResponse, String> {
   let generated_dir = resolve_generated_dir(&app)?;
   add_manual_transaction(&generated_dir, &now_yyyymm, &input)?;
-  let result = load_active_ledger(&generated_dir)?;
+  let result = load_ledger_history(&generated_dir)?;
   Ok(result.into())
 }
 
@@ -102,13 +130,189 @@ fn add_account_to_generated_ledger(
   account_name: String,
   currency: Option<String>,
   opening_balance: Option<String>,
+  openings: Option<Vec<OpeningBalanceInput>>,
 ) -> Result<ParseResponse, String> {
   let generated_dir = resolve_generated_dir(&app)?;
-  add_account_declaration(&generated_dir, &account_name, currency.as_deref(), opening_balance.as_deref())?;
-  let result = load_active_ledger(&generated_dir)?;
+  // `opening_balance` is the single-commodity shorthand used by the add-account form.
+  let mut openings = openings.unwrap_or_default();
+  if let Some(amount) = opening_balance {
+    openings.insert(
+      0,
+      OpeningBalanceInput {
+        amount,
+        commodity: None,
+        date: None,
+      },
+    );
+  }
+  add_account_declaration(&generated_dir, &account_name, currency.as_deref(), &openings)?;
+  let result = load_ledger_history(&generated_dir)?;
   Ok(result.into())
 }
 
+/// Price of one unit of `base` in `quote` at `date`, from every loaded price directive and
+/// posting annotation.
+#[tauri::command]
+fn lookup_price(
+  app: tauri::AppHandle,
+  base: String,
+  quote: String,
+  date: String,
+) -> Result<Option<Rate>, String> {
+  let generated_dir = resolve_generated_dir(&app)?;
+  let result = load_ledger_history(&generated_dir)?;
+  PriceDb::from_parse(&result).rate(&base, &quote, &date)
+}
+
+/// Every account's balance at `date`, valued in `currency`.
+#[tauri::command]
+fn value_generated_balances(
+  app: tauri::AppHandle,
+  currency: String,
+  date: String,
+) -> Result<Valuation, String> {
+  let generated_dir = resolve_generated_dir(&app)?;
+  let result = load_ledger_history(&generated_dir)?;
+  value_balances(&result, &currency, &date)
+}
+
+/// Open accounts as a tree with rolled-up balances, cut off below `max_depth` if given.
+#[tauri::command]
+fn generated_account_tree(
+  app: tauri::AppHandle,
+  max_depth: Option<usize>,
+) -> Result<Vec<AccountNode>, String> {
+  let generated_dir = resolve_generated_dir(&app)?;
+  let result = load_ledger_history(&generated_dir)?;
+  account_tree(&result, max_depth)
+}
+
+/// Postings of one account (or account subtree) with running balances.
+#[tauri::command]
+fn generated_register(
+  app: tauri::AppHandle,
+  query: RegisterQuery,
+) -> Result<Vec<RegisterRow>, String> {
+  let generated_dir = resolve_generated_dir(&app)?;
+  let result = load_ledger_history(&generated_dir)?;
+  register(&result, &query)
+}
+
+/// Income and expenses between `from` and `to` (inclusive), grouped to `depth` segments.
+#[tauri::command]
+fn generated_income_statement(
+  app: tauri::AppHandle,
+  from: Option<String>,
+  to: Option<String>,
+  depth: Option<usize>,
+) -> Result<IncomeStatement, String> {
+  let generated_dir = resolve_generated_dir(&app)?;
+  let result = load_ledger_history(&generated_dir)?;
+  income_statement(&result, from.as_deref(), to.as_deref(), depth)
+}
+
+/// Assets, liabilities and equity (with retained earnings) as of `date`.
+#[tauri::command]
+fn generated_balance_sheet(
+  app: tauri::AppHandle,
+  date: String,
+  depth: Option<usize>,
+) -> Result<BalanceSheet, String> {
+  let generated_dir = resolve_generated_dir(&app)?;
+  let result = load_ledger_history(&generated_dir)?;
+  balance_sheet(&result, &date, depth)
+}
+
+/// Per-period changes and ending balances across the active ledger and its archives.
+#[tauri::command]
+fn generated_periodic_report(
+  app: tauri::AppHandle,
+  interval: Interval,
+  from: Option<String>,
+  to: Option<String>,
+  depth: Option<usize>,
+) -> Result<PeriodicReport, String> {
+  let generated_dir = resolve_generated_dir(&app)?;
+  let result = load_ledger_history(&generated_dir)?;
+  periodic_report(&result, interval, from.as_deref(), to.as_deref(), depth)
+}
+
+/// [`generated_periodic_report`] as CSV text.
+#[tauri::command]
+fn generated_periodic_csv(
+  app: tauri::AppHandle,
+  interval: Interval,
+  from: Option<String>,
+  to: Option<String>,
+  depth: Option<usize>,
+) -> Result<String, String> {
+  let report = generated_periodic_report(app, interval, from, to, depth)?;
+  Ok(report.to_csv())
+}
+
+/// Transactions and postings of the loaded history matching `query` (see
+/// [`squirrel_covid::query::Query`]), with totals.
+#[tauri::command]
+fn query_generated_ledger(app: tauri::AppHandle, query: String) -> Result<QueryResult, String> {
+  let query = parse_query(&query)?;
+  let generated_dir = resolve_generated_dir(&app)?;
+  let result = load_ledger_history(&generated_dir)?;
+  run_query(&result, &query)
+}
+
+/// Remaining lots and matched reductions across the active ledger and its archives. `method`
+/// books reductions whose annotation and account don't name a method (default FIFO).
+#[tauri::command]
+fn generated_lots(
+  app: tauri::AppHandle,
+  method: Option<BookingMethod>,
+) -> Result<LotInventory, String> {
+  let generated_dir = resolve_generated_dir(&app)?;
+  let result = load_ledger_history(&generated_dir)?;
+  Ok(lot_inventory(&result, method.unwrap_or_default()))
+}
+
+/// Realized gains of sales between `from` and `to` across the active ledger and its archives,
+/// with lots booked by `method` (default FIFO).
+#[tauri::command]
+fn generated_realized_gains(
+  app: tauri::AppHandle,
+  method: Option<BookingMethod>,
+  from: Option<String>,
+  to: Option<String>,
+) -> Result<RealizedGains, String> {
+  let generated_dir = resolve_generated_dir(&app)?;
+  let result = load_ledger_history(&generated_dir)?;
+  let method = method.unwrap_or_default();
+  Ok(realized_gains(&result, method, from.as_deref(), to.as_deref()))
+}
+
+/// [`generated_realized_gains`] as CSV text, one line per disposal.
+#[tauri::command]
+fn generated_realized_gains_csv(
+  app: tauri::AppHandle,
+  method: Option<BookingMethod>,
+  from: Option<String>,
+  to: Option<String>,
+) -> Result<String, String> {
+  let report = generated_realized_gains(app, method, from, to)?;
+  Ok(report.to_csv())
+}
+
+/// Lots held at `date` across the active ledger and its archives, with cost basis, market value
+/// and unrealized gain in `currency`.
+#[tauri::command]
+fn generated_holdings(
+  app: tauri::AppHandle,
+  currency: String,
+  date: String,
+  method: Option<BookingMethod>,
+) -> Result<HoldingsReport, String> {
+  let generated_dir = resolve_generated_dir(&app)?;
+  let result = load_ledger_history(&generated_dir)?;
+  holdings(&result, method.unwrap_or_default(), &currency, &date)
+}
+
 fn main() {
   tauri::Builder::default()
     .plugin(tauri_plugin_dialog::init())
@@ -135,7 +339,20 @@ fn main() {
       load_generated_ledger,
       import_generated_sources,
       add_manual_to_generated_ledger,
-      add_account_to_generated_ledger
+      add_account_to_generated_ledger,
+      lookup_price,
+      value_generated_balances,
+      generated_account_tree,
+      generated_register,
+      generated_income_statement,
+      generated_balance_sheet,
+      generated_periodic_report,
+      generated_periodic_csv,
+      query_generated_ledger,
+      generated_lots,
+      generated_realized_gains,
+      generated_realized_gains_csv,
+      generated_holdings
     ])
     .run(tauri::generate_context!())
     .expect("error while running tauri application");
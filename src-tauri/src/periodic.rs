@@ -0,0 +1,335 @@
+use crate::amount::Amount;
+use crate::ledger_parser::ParseResult;
+use crate::reports::group_account;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Length of the buckets of a [`PeriodicReport`]. Weeks start on Monday.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Interval {
+  Week,
+  Month,
+  Quarter,
+  Year,
+}
+
+impl Interval {
+  pub fn parse(name: &str) -> Option<Self> {
+    match name {
+      "week" | "weekly" => Some(Self::Week),
+      "month" | "monthly" => Some(Self::Month),
+      "quarter" | "quarterly" => Some(Self::Quarter),
+      "year" | "yearly" => Some(Self::Year),
+      _ => None,
+    }
+  }
+
+  /// First day of the period containing `day`.
+  fn start_of(self, day: i64) -> i64 {
+    let (year, month, _) = civil_from_days(day);
+    match self {
+      // Day 0 (1970-01-01) was a Thursday.
+      Self::Week => day - (day + 3).rem_euclid(7),
+      Self::Month => days_from_civil(year, month, 1),
+      Self::Quarter => days_from_civil(year, (month - 1) / 3 * 3 + 1, 1),
+      Self::Year => days_from_civil(year, 1, 1),
+    }
+  }
+
+  /// First day of the period after the one starting on `start`.
+  fn next(self, start: i64) -> i64 {
+    let (year, month, _) = civil_from_days(start);
+    let add_months = |months: u32| {
+      let index = month - 1 + months;
+      days_from_civil(year + i64::from(index / 12), index % 12 + 1, 1)
+    };
+    match self {
+      Self::Week => start + 7,
+      Self::Month => add_months(1),
+      Self::Quarter => add_months(3),
+      Self::Year => days_from_civil(year + 1, 1, 1),
+    }
+  }
+
+  /// `2026-W03` (ISO week), `2026-01`, `2026-Q1` or `2026`.
+  fn label(self, start: i64) -> String {
+    let (year, month, _) = civil_from_days(start);
+    match self {
+      Self::Week => {
+        // The ISO week is the one holding the week's Thursday.
+        let thursday = start + 3;
+        let (iso_year, _, _) = civil_from_days(thursday);
+        let week = (thursday - days_from_civil(iso_year, 1, 1)) / 7 + 1;
+        format!("{iso_year}-W{week:02}")
+      }
+      Self::Month => format!("{year}-{month:02}"),
+      Self::Quarter => format!("{year}-Q{}", (month - 1) / 3 + 1),
+      Self::Year => format!("{year}"),
+    }
+  }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let year_of_era = year - era * 400;
+  let month = i64::from(month);
+  let month_index = if month > 2 { month - 3 } else { month + 9 };
+  let day_of_year = (153 * month_index + 2) / 5 + i64::from(day) - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+  let z = days + 719_468;
+  let era = z.div_euclid(146_097);
+  let day_of_era = z - era * 146_097;
+  let year_of_era =
+    (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let mp = (5 * day_of_year + 2) / 153;
+  let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+  let year = year_of_era + era * 400 + i64::from(month <= 2);
+  (year, month, day)
+}
+
+pub(crate) fn parse_date(date: &str) -> Option<i64> {
+  let mut parts = date.get(..10)?.splitn(3, '-');
+  let year = parts.next()?.parse().ok()?;
+  let month = parts.next()?.parse().ok().filter(|m| (1..=12).contains(m))?;
+  let day = parts.next()?.parse().ok().filter(|d| (1..=31).contains(d))?;
+  Some(days_from_civil(year, month, day))
+}
+
+fn format_date(days: i64) -> String {
+  let (year, month, day) = civil_from_days(days);
+  format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Period {
+  pub label: String,
+  /// First day of the period.
+  pub start: String,
+  /// Last day of the period (inclusive).
+  pub end: String,
+}
+
+/// One account and commodity, with a value per period.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PeriodicRow {
+  pub account: String,
+  pub commodity: String,
+  /// Net change during each period.
+  pub changes: Vec<Amount>,
+  /// Balance at the end of each period, including everything before the first period.
+  pub balances: Vec<Amount>,
+}
+
+/// Per-period balance changes and ending balances; `changes` and `balances` of every row line up
+/// with `periods`.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PeriodicReport {
+  pub interval: Interval,
+  pub periods: Vec<Period>,
+  pub rows: Vec<PeriodicRow>,
+}
+
+pub(crate) fn csv_field(value: &str) -> String {
+  if value.contains([',', '"', '\n']) {
+    format!("\"{}\"", value.replace('"', "\"\""))
+  } else {
+    value.to_string()
+  }
+}
+
+impl PeriodicReport {
+  /// One line per account, commodity and measure (`change` or `balance`), one column per
+  /// period.
+  pub fn to_csv(&self) -> String {
+    let mut out = String::from("account,commodity,measure");
+    for period in &self.periods {
+      out.push(',');
+      out.push_str(&csv_field(&period.label));
+    }
+    out.push('\n');
+    for row in &self.rows {
+      // Written to the row's largest scale, so an empty period reads `0.00` rather than `0`.
+      let scale = row.changes.iter().chain(&row.balances).map(Amount::scale).max().unwrap_or(0);
+      for (measure, values) in [("change", &row.changes), ("balance", &row.balances)] {
+        let (account, commodity) = (csv_field(&row.account), csv_field(&row.commodity));
+        out.push_str(&format!("{account},{commodity},{measure}"));
+        for value in values {
+          out.push_str(&format!(",{}", value.checked_rescale(scale).unwrap_or(*value)));
+        }
+        out.push('\n');
+      }
+    }
+    out
+  }
+}
+
+/// Buckets every posting and opening by `interval` between `from` and `to` (inclusive dates;
+/// default to the first and last dated entry), widened to whole periods. Accounts are grouped
+/// by their path cut to `depth` segments. Fails on an invalid bound or a total out of range.
+pub fn periodic_report(
+  result: &ParseResult,
+  interval: Interval,
+  from: Option<&str>,
+  to: Option<&str>,
+  depth: Option<usize>,
+) -> Result<PeriodicReport, String> {
+  // (account, commodity, date, amount) of every movement; `None` dates are undated openings.
+  let mut entries: Vec<(&str, &str, Option<i64>, Amount)> = Vec::new();
+  for decl in &result.accounts {
+    for opening in &decl.openings {
+      let date = opening.date.as_deref().and_then(parse_date);
+      entries.push((&decl.account, &opening.commodity, date, opening.amount));
+    }
+  }
+  for txn in &result.transactions {
+    let Some(date) = parse_date(&txn.date) else {
+      continue;
+    };
+    for posting in &txn.postings {
+      entries.push((&posting.account, &posting.commodity, Some(date), posting.amount));
+    }
+  }
+
+  let bound = |date: Option<&str>, kind: &str| match date {
+    Some(text) => parse_date(text)
+      .map(Some)
+      .ok_or_else(|| format!("invalid {kind} date: {text} (expected YYYY-MM-DD)")),
+    None => Ok(None),
+  };
+  let dated = || entries.iter().filter_map(|(_, _, date, _)| *date);
+  let first = bound(from, "from")?.or_else(|| dated().min());
+  let last = bound(to, "to")?.or_else(|| dated().max());
+
+  let mut starts: Vec<i64> = Vec::new();
+  if let (Some(first), Some(last)) = (first, last) {
+    let mut start = interval.start_of(first);
+    while start <= last {
+      starts.push(start);
+      start = interval.next(start);
+    }
+  }
+  let periods: Vec<Period> = starts
+    .iter()
+    .map(|&start| Period {
+      label: interval.label(start),
+      start: format_date(start),
+      end: format_date(interval.next(start) - 1),
+    })
+    .collect();
+
+  let end = starts.last().map(|&start| interval.next(start));
+  let mut rows: BTreeMap<(&str, &str), (Amount, Vec<Amount>)> = BTreeMap::new();
+  for (account, commodity, date, amount) in entries {
+    if date.zip(end).is_some_and(|(date, end)| date >= end) {
+      continue;
+    }
+    let group = group_account(account, depth);
+    let (opening, changes) = rows
+      .entry((group, commodity))
+      .or_insert_with(|| (Amount::ZERO, vec![Amount::ZERO; starts.len()]));
+    let total = match date.map(|date| starts.partition_point(|&start| start <= date)) {
+      Some(index) if index > 0 => &mut changes[index - 1],
+      _ => opening,
+    };
+    *total = total
+      .checked_add(&amount)
+      .ok_or_else(|| format!("total of {group} out of range in {commodity}"))?;
+  }
+
+  let rows = rows
+    .into_iter()
+    .map(|((account, commodity), (opening, changes))| {
+      let mut balance = opening;
+      let mut balances = Vec::with_capacity(changes.len());
+      for change in &changes {
+        balance = balance
+          .checked_add(change)
+          .ok_or_else(|| format!("balance of {account} out of range in {commodity}"))?;
+        balances.push(balance);
+      }
+      Ok(PeriodicRow {
+        account: account.to_string(),
+        commodity: commodity.to_string(),
+        changes,
+        balances,
+      })
+    })
+    .collect::<Result<_, String>>()?;
+
+  Ok(PeriodicReport {
+    interval,
+    periods,
+    rows,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ledger_parser::parse_transactions;
+
+  #[test]
+  fn labels_calendar_periods() {
+    let day = parse_date("2026-01-01").unwrap();
+    assert_eq!(format_date(day), "2026-01-01");
+    assert_eq!(format_date(Interval::Week.start_of(day)), "2025-12-29");
+    assert_eq!(Interval::Week.label(Interval::Week.start_of(day)), "2026-W01");
+    assert_eq!(Interval::Quarter.label(parse_date("2026-11-30").unwrap()), "2026-Q4");
+    assert_eq!(format_date(Interval::Month.next(parse_date("2025-12-01").unwrap())), "2026-01-01");
+    assert_eq!(parse_date("2026-13-01"), None);
+  }
+
+  #[test]
+  fn buckets_changes_and_ending_balances() {
+    let result = parse_transactions(
+      r#"account assets:CBA:smartaccess AUD
+    opening 1000.00
+
+2025-12-20 * "Employer" "Salary" ; txn:01J2N9R8
+    assets:CBA:smartaccess    3000.00 AUD
+    income:salary            -3000.00 AUD
+
+2026-01-16 * "Coles" "Groceries" ; txn:01J2N9RA
+    expenses:food:groceries    142.50 AUD
+    assets:CBA:smartaccess    -142.50 AUD
+
+2026-03-02 * "Cafe" "Lunch" ; txn:01J2N9RB
+    expenses:food:dining        20.00 AUD
+    assets:CBA:smartaccess     -20.00 AUD
+"#,
+    );
+    assert!(result.ok, "{:?}", result.diagnostics);
+
+    let report =
+      periodic_report(&result, Interval::Month, Some("2026-01-10"), Some("2026-03-31"), Some(2))
+        .unwrap();
+    let labels: Vec<&str> = report.periods.iter().map(|p| p.label.as_str()).collect();
+    assert_eq!(labels, ["2026-01", "2026-02", "2026-03"]);
+    assert_eq!(report.periods[1].end, "2026-02-28");
+
+    let row = |account: &str| report.rows.iter().find(|r| r.account == account).unwrap();
+    let text = |values: &[Amount]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+    assert_eq!(text(&row("assets:CBA").changes), ["-142.50", "0", "-20.00"]);
+    assert_eq!(text(&row("assets:CBA").balances), ["3857.50", "3857.50", "3837.50"]);
+    assert_eq!(text(&row("expenses:food").balances), ["142.50", "142.50", "162.50"]);
+
+    let csv = report.to_csv();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("account,commodity,measure,2026-01,2026-02,2026-03"));
+    assert_eq!(lines.next(), Some("assets:CBA,AUD,change,-142.50,0.00,-20.00"));
+    assert_eq!(lines.next(), Some("assets:CBA,AUD,balance,3857.50,3857.50,3837.50"));
+
+    let quarters = periodic_report(&result, Interval::Quarter, None, None, Some(1)).unwrap();
+    assert_eq!(quarters.periods.len(), 2);
+    assert_eq!(quarters.periods[0].label, "2025-Q4");
+  }
+}
@@ -0,0 +1,333 @@
+use crate::amount::Amount;
+use crate::ledger_parser::{ParseResult, PriceDirective, Transaction};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+/// Fractional digits kept for rates that are derived rather than written (inverted, chained or
+/// divided out of an `@@` total).
+pub const DERIVED_RATE_SCALE: u32 = 12;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceSource {
+  /// The per-unit cost of a `{ ... }`/`{{ ... }}` lot annotation.
+  Cost,
+  /// A `price` directive.
+  Directive,
+  /// An `@`/`@@` annotation on a posting.
+  Annotation,
+}
+
+/// One observed price of a unit of `base` in `quote`.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PricePoint {
+  pub datetime: String,
+  pub rate: Amount,
+  pub source: PriceSource,
+}
+
+/// Result of a price lookup.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Rate {
+  pub base: String,
+  pub quote: String,
+  /// Value of one unit of `base` in `quote`.
+  pub rate: Amount,
+  /// Datetime of the oldest price the rate was derived from.
+  pub as_of: String,
+  /// Commodities the rate was chained through, from `base` to `quote` inclusive.
+  pub path: Vec<String>,
+}
+
+/// Historical prices keyed by `(base, quote)`, each list sorted by datetime.
+#[derive(Debug, Clone, Default)]
+pub struct PriceDb {
+  pairs: BTreeMap<(String, String), Vec<PricePoint>>,
+}
+
+/// Whether a price observed at `datetime` is known at `date` (a date or a full datetime).
+/// A bare date includes every price observed during that day.
+pub(crate) fn known_at(datetime: &str, date: &str) -> bool {
+  if date.len() == 10 {
+    datetime.get(..10).unwrap_or(datetime) <= date
+  } else {
+    datetime <= date
+  }
+}
+
+pub(crate) fn trim_rate(rate: Amount) -> Amount {
+  if rate.scale() > DERIVED_RATE_SCALE {
+    rate.round_dp(DERIVED_RATE_SCALE).normalize()
+  } else {
+    rate
+  }
+}
+
+impl PriceDb {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Builds the database from the `price` directives and posting cost/price annotations.
+  pub fn from_parse(result: &ParseResult) -> Self {
+    Self::from_parts(&result.prices, &result.transactions)
+  }
+
+  /// At the same datetime a directive wins over an `@` annotation, which wins over a lot cost.
+  pub fn from_parts(prices: &[PriceDirective], transactions: &[Transaction]) -> Self {
+    let mut db = Self::new();
+    for source in [PriceSource::Cost, PriceSource::Annotation] {
+      for txn in transactions {
+        for posting in &txn.postings {
+          let total = match source {
+            PriceSource::Cost => posting
+              .lot_cost
+              .as_ref()
+              .filter(|c| c.amount.is_some())
+              .and_then(|_| posting.weight()),
+            _ => posting.total_price(),
+          };
+          let Some(total) = total else {
+            continue;
+          };
+          let units = posting.amount.abs();
+          let Some(rate) = total.amount.abs().checked_div(&units, DERIVED_RATE_SCALE) else {
+            continue;
+          };
+          db.insert(&posting.commodity, &total.commodity, &txn.datetime, rate.normalize(), source);
+        }
+      }
+    }
+    for price in prices {
+      db.insert(
+        &price.commodity,
+        &price.price.commodity,
+        &price.datetime,
+        price.price.amount,
+        PriceSource::Directive,
+      );
+    }
+    db
+  }
+
+  /// Records a price, keeping insertion order among prices with the same datetime (the last one
+  /// recorded wins on lookup).
+  pub fn insert(
+    &mut self,
+    base: &str,
+    quote: &str,
+    datetime: &str,
+    rate: Amount,
+    source: PriceSource,
+  ) {
+    if base == quote || !rate.is_positive() {
+      return;
+    }
+    let points = self.pairs.entry((base.to_string(), quote.to_string())).or_default();
+    let at = points.partition_point(|p| p.datetime.as_str() <= datetime);
+    points.insert(
+      at,
+      PricePoint {
+        datetime: datetime.to_string(),
+        rate,
+        source,
+      },
+    );
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.pairs.is_empty()
+  }
+
+  /// Every commodity appearing on either side of a recorded price.
+  pub fn commodities(&self) -> BTreeSet<&str> {
+    self
+      .pairs
+      .keys()
+      .flat_map(|(base, quote)| [base.as_str(), quote.as_str()])
+      .collect()
+  }
+
+  /// Recorded prices of `base` in `quote`, oldest first (no inversion).
+  pub fn history(&self, base: &str, quote: &str) -> &[PricePoint] {
+    self
+      .pairs
+      .get(&(base.to_string(), quote.to_string()))
+      .map(Vec::as_slice)
+      .unwrap_or_default()
+  }
+
+  /// Latest recorded price of `base` in `quote` known at `date` (forward fill).
+  fn latest(&self, base: &str, quote: &str, date: &str) -> Option<&PricePoint> {
+    self
+      .history(base, quote)
+      .iter()
+      .rev()
+      .find(|p| known_at(&p.datetime, date))
+  }
+
+  /// One hop: the more recent of the direct price and the inverted reverse price.
+  fn hop(&self, from: &str, to: &str, date: &str) -> Option<(Amount, &str)> {
+    let direct = self.latest(from, to, date);
+    let inverse = self.latest(to, from, date);
+    match (direct, inverse) {
+      (Some(d), Some(i)) if i.datetime > d.datetime => invert(i),
+      (Some(d), _) => Some((d.rate, d.datetime.as_str())),
+      (None, Some(i)) => invert(i),
+      (None, None) => None,
+    }
+  }
+
+  /// Value of one unit of `base` in `quote` at `date`, using the latest price known at that
+  /// date. Falls back to the inverse of the reverse pair, then to the shortest chain through
+  /// other commodities (e.g. `SOL -> USD -> AUD`). Fails when the chained rate is out of range.
+  pub fn rate(&self, base: &str, quote: &str, date: &str) -> Result<Option<Rate>, String> {
+    if base == quote {
+      return Ok(Some(Rate {
+        base: base.to_string(),
+        quote: quote.to_string(),
+        rate: Amount::from_int(1),
+        as_of: date.to_string(),
+        path: vec![base.to_string()],
+      }));
+    }
+
+    let mut neighbours: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for (b, q) in self.pairs.keys() {
+      neighbours.entry(b).or_default().insert(q);
+      neighbours.entry(q).or_default().insert(b);
+    }
+
+    // Breadth-first search so the chain with the fewest hops wins.
+    let mut previous: BTreeMap<&str, &str> = BTreeMap::new();
+    let mut queue: VecDeque<&str> = VecDeque::from([base]);
+    while let Some(from) = queue.pop_front() {
+      if from == quote {
+        break;
+      }
+      for &to in neighbours.get(from).into_iter().flatten() {
+        if to == base || previous.contains_key(to) || self.hop(from, to, date).is_none() {
+          continue;
+        }
+        previous.insert(to, from);
+        queue.push_back(to);
+      }
+    }
+
+    if !previous.contains_key(quote) {
+      return Ok(None);
+    }
+    let mut path = vec![quote];
+    while let Some(&from) = previous.get(path[path.len() - 1]) {
+      path.push(from);
+    }
+    path.reverse();
+
+    let mut rate = Amount::from_int(1);
+    let mut as_of: Option<&str> = None;
+    for step in path.windows(2) {
+      let Some((hop_rate, datetime)) = self.hop(step[0], step[1], date) else {
+        return Ok(None);
+      };
+      let chained = rate.checked_mul(&hop_rate).ok_or_else(|| {
+        format!("rate of {base} in {quote} out of range via {}", path.join(" -> "))
+      })?;
+      rate = trim_rate(chained);
+      as_of = Some(as_of.map_or(datetime, |oldest| oldest.min(datetime)));
+    }
+
+    Ok(Some(Rate {
+      base: base.to_string(),
+      quote: quote.to_string(),
+      rate,
+      as_of: as_of.unwrap_or(date).to_string(),
+      path: path.into_iter().map(str::to_string).collect(),
+    }))
+  }
+
+  /// Converts `amount` of `commodity` into `quote` at `date`; `None` without a rate. Fails when
+  /// the rate or the result is out of range.
+  pub fn convert(
+    &self,
+    amount: Amount,
+    commodity: &str,
+    quote: &str,
+    date: &str,
+  ) -> Result<Option<Amount>, String> {
+    let Some(rate) = self.rate(commodity, quote, date)? else {
+      return Ok(None);
+    };
+    let converted = amount
+      .checked_mul(&rate.rate)
+      .ok_or_else(|| format!("{amount} {commodity} out of range in {quote}"))?;
+    Ok(Some(trim_rate(converted)))
+  }
+}
+
+fn invert(point: &PricePoint) -> Option<(Amount, &str)> {
+  let rate = Amount::from_int(1).checked_div(&point.rate, DERIVED_RATE_SCALE)?;
+  Some((rate.normalize(), point.datetime.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ledger_parser::parse_transactions;
+
+  fn db(input: &str) -> PriceDb {
+    let result = parse_transactions(input);
+    assert!(result.ok, "{:?}", result.diagnostics);
+    PriceDb::from_parse(&result)
+  }
+
+  #[test]
+  fn forward_fills_directive_prices() {
+    let prices = db(r#"price 2026-01-10 BTC 150000.00 AUD
+price 2026-01-20 BTC 160000.00 AUD
+"#);
+
+    assert_eq!(prices.rate("BTC", "AUD", "2026-01-09"), Ok(None));
+    let rate = prices.rate("BTC", "AUD", "2026-01-15").unwrap().unwrap();
+    assert_eq!(rate.rate.to_string(), "150000.00");
+    assert_eq!(rate.as_of, "2026-01-10");
+    let later = prices.rate("BTC", "AUD", "2026-01-20").unwrap().unwrap();
+    assert_eq!(later.rate.to_string(), "160000.00");
+  }
+
+  #[test]
+  fn inverts_and_triangulates_rates() {
+    let prices = db(r#"price 2026-01-10 USD 1.60 AUD
+price 2026-01-12 SOL 200.00 USD
+
+2026-01-15 * "Binance" "Sell SOL" ; txn:01J2N9R9
+    assets:exchange:binance:sol    -2 SOL @@ 500.00 USD
+    assets:exchange:binance:usd    500.00 USD
+"#);
+
+    let inverted = prices.rate("AUD", "USD", "2026-01-31").unwrap().unwrap();
+    assert_eq!(inverted.rate.to_string(), "0.625");
+
+    let before = prices.rate("SOL", "AUD", "2026-01-13").unwrap().unwrap();
+    assert_eq!(before.rate.to_string(), "320.0000");
+    assert_eq!(before.path, ["SOL", "USD", "AUD"]);
+    assert_eq!(before.as_of, "2026-01-10");
+
+    // The `@@` annotation on the 15th supersedes the directive from the 12th.
+    let after = prices.rate("SOL", "AUD", "2026-01-15").unwrap().unwrap();
+    assert_eq!(after.rate.to_string(), "400.00");
+    let value = prices.convert("3".parse().unwrap(), "SOL", "AUD", "2026-01-15");
+    assert_eq!(value.unwrap().unwrap().to_string(), "1200.00");
+  }
+
+  #[test]
+  fn reports_chained_rates_out_of_range() {
+    let prices = db(r#"price 2026-01-10 AAA 100000000000000000000 BBB
+price 2026-01-10 BBB 100000000000000000000 CCC
+"#);
+
+    assert_eq!(
+      prices.rate("AAA", "CCC", "2026-01-31"),
+      Err("rate of AAA in CCC out of range via AAA -> BBB -> CCC".to_string())
+    );
+  }
+}
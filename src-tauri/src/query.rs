@@ -0,0 +1,414 @@
+use crate::amount::Amount;
+use crate::ledger_parser::{CommodityAmount, ParseResult, Posting, Transaction};
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+/// A parsed query, evaluated against one posting of a transaction at a time.
+///
+/// Terms are whitespace separated and combine with `and` (the default), `or`, `not` and
+/// parentheses:
+///
+/// ```text
+/// acct:REGEX          account matches the regex (case-insensitive); a bare word (including an
+///                     account path such as `assets:cba`) means the same
+/// date:2026-01        date starts with the prefix; `date:2026-01-10..2026-02` is an inclusive
+///                     range of prefixes, either end may be left open
+/// payee:TEXT          payee contains TEXT (case-insensitive)
+/// narration:TEXT      narration contains TEXT; `desc:TEXT` matches payee or narration
+/// status:*            `*`, `!` or `none`
+/// cur:USD             posting commodity
+/// amt:>=100           signed posting amount compared with `<`, `<=`, `>`, `>=` or `=`
+/// meta:KEY[:VALUE]    posting or header meta has KEY (with exactly VALUE)
+/// ```
+///
+/// Values containing spaces or parentheses can be quoted: `payee:"Coles Online"`.
+#[derive(Debug, Clone)]
+pub enum Query {
+  All,
+  And(Box<Query>, Box<Query>),
+  Or(Box<Query>, Box<Query>),
+  Not(Box<Query>),
+  Term(Term),
+}
+
+#[derive(Debug, Clone)]
+pub enum Term {
+  Account(Regex),
+  Date { from: Option<String>, to: Option<String> },
+  Payee(String),
+  Narration(String),
+  Description(String),
+  Status(Option<char>),
+  Commodity(String),
+  Amount(Vec<Ordering>, Amount),
+  Meta { key: String, value: Option<String> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  Open,
+  Close,
+  Word(String),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+  let mut tokens = Vec::new();
+  let mut chars = text.chars().peekable();
+  while let Some(&c) = chars.peek() {
+    match c {
+      c if c.is_whitespace() => {
+        chars.next();
+      }
+      '(' | ')' => {
+        chars.next();
+        tokens.push(if c == '(' { Token::Open } else { Token::Close });
+      }
+      _ => {
+        let mut word = String::new();
+        while let Some(&c) = chars.peek() {
+          if c.is_whitespace() || c == '(' || c == ')' {
+            break;
+          }
+          chars.next();
+          if c != '"' {
+            word.push(c);
+            continue;
+          }
+          loop {
+            match chars.next() {
+              Some('"') => break,
+              Some(c) => word.push(c),
+              None => return Err(format!("unterminated quote in query: {text}")),
+            }
+          }
+        }
+        tokens.push(Token::Word(word));
+      }
+    }
+  }
+  Ok(tokens)
+}
+
+fn is_date_prefix(text: &str) -> bool {
+  let bytes = text.as_bytes();
+  matches!(bytes.len(), 4 | 7 | 10)
+    && bytes.iter().enumerate().all(|(i, b)| match i {
+      4 | 7 => *b == b'-',
+      _ => b.is_ascii_digit(),
+    })
+}
+
+const FIELDS: [&str; 14] = [
+  "acct", "account", "date", "payee", "narration", "note", "desc", "status", "cur", "commodity",
+  "amt", "amount", "meta", "tag",
+];
+
+fn parse_term(word: &str) -> Result<Term, String> {
+  let (field, value) = match word.split_once(':') {
+    Some((field, value)) if FIELDS.contains(&field) => (field, value),
+    _ => ("acct", word),
+  };
+  let lower = value.to_lowercase();
+  let term = match field {
+    "date" => {
+      let (from, to) = value.split_once("..").unwrap_or((value, value));
+      let bound = |text: &str| match text {
+        "" => Ok(None),
+        text if is_date_prefix(text) => Ok(Some(text.to_string())),
+        _ => Err(format!(
+          "invalid date in query: {text} (expected YYYY, YYYY-MM or YYYY-MM-DD)"
+        )),
+      };
+      Term::Date {
+        from: bound(from)?,
+        to: bound(to)?,
+      }
+    }
+    "payee" => Term::Payee(lower),
+    "narration" | "note" => Term::Narration(lower),
+    "desc" => Term::Description(lower),
+    "status" => match value {
+      "*" | "!" => Term::Status(value.chars().next()),
+      "none" | "" => Term::Status(None),
+      _ => return Err(format!("invalid status in query: {value} (expected *, ! or none)")),
+    },
+    "cur" | "commodity" => Term::Commodity(value.to_string()),
+    "amt" | "amount" => {
+      let (orderings, number) = [
+        (">=", vec![Ordering::Greater, Ordering::Equal]),
+        ("<=", vec![Ordering::Less, Ordering::Equal]),
+        (">", vec![Ordering::Greater]),
+        ("<", vec![Ordering::Less]),
+        ("=", vec![Ordering::Equal]),
+      ]
+      .into_iter()
+      .find_map(|(op, orderings)| value.strip_prefix(op).map(|rest| (orderings, rest)))
+      .unwrap_or((vec![Ordering::Equal], value));
+      let amount = number
+        .parse()
+        .map_err(|_| format!("invalid amount in query: {value}"))?;
+      Term::Amount(orderings, amount)
+    }
+    "meta" | "tag" => {
+      let (key, value) = match value.split_once(':') {
+        Some((key, value)) => (key, Some(value.to_string())),
+        None => (value, None),
+      };
+      if key.is_empty() {
+        return Err("meta query needs a key, e.g. meta:src".to_string());
+      }
+      Term::Meta {
+        key: key.to_string(),
+        value,
+      }
+    }
+    // acct, account
+    _ => {
+      let regex = RegexBuilder::new(value)
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| format!("invalid account regex {value}: {e}"))?;
+      Term::Account(regex)
+    }
+  };
+  Ok(term)
+}
+
+struct Parser {
+  tokens: Vec<Token>,
+  pos: usize,
+}
+
+impl Parser {
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.pos)
+  }
+
+  fn keyword(&self, keyword: &str) -> bool {
+    matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+  }
+
+  fn or(&mut self) -> Result<Query, String> {
+    let mut query = self.and()?;
+    while self.keyword("or") {
+      self.pos += 1;
+      query = Query::Or(Box::new(query), Box::new(self.and()?));
+    }
+    Ok(query)
+  }
+
+  fn and(&mut self) -> Result<Query, String> {
+    let mut query = self.unary()?;
+    loop {
+      if self.keyword("and") {
+        self.pos += 1;
+      } else if self.keyword("or") || matches!(self.peek(), None | Some(Token::Close)) {
+        return Ok(query);
+      }
+      query = Query::And(Box::new(query), Box::new(self.unary()?));
+    }
+  }
+
+  fn unary(&mut self) -> Result<Query, String> {
+    let token = self.tokens.get(self.pos).cloned();
+    self.pos += 1;
+    match token {
+      Some(Token::Word(w)) if w.eq_ignore_ascii_case("not") => {
+        Ok(Query::Not(Box::new(self.unary()?)))
+      }
+      Some(Token::Word(w)) if w.eq_ignore_ascii_case("and") || w.eq_ignore_ascii_case("or") => {
+        Err(format!("expected a query term before {w}"))
+      }
+      Some(Token::Word(w)) => Ok(Query::Term(parse_term(&w)?)),
+      Some(Token::Open) => {
+        let query = self.or()?;
+        match self.tokens.get(self.pos) {
+          Some(Token::Close) => {
+            self.pos += 1;
+            Ok(query)
+          }
+          _ => Err("unbalanced parentheses in query".to_string()),
+        }
+      }
+      Some(Token::Close) => Err("unbalanced parentheses in query".to_string()),
+      None => Err("query ended early: expected a term".to_string()),
+    }
+  }
+}
+
+/// Parses a query; an empty query matches everything.
+pub fn parse_query(text: &str) -> Result<Query, String> {
+  let tokens = tokenize(text)?;
+  if tokens.is_empty() {
+    return Ok(Query::All);
+  }
+  let mut parser = Parser { tokens, pos: 0 };
+  let query = parser.or()?;
+  if parser.pos < parser.tokens.len() {
+    return Err("unbalanced parentheses in query".to_string());
+  }
+  Ok(query)
+}
+
+impl Term {
+  fn matches(&self, txn: &Transaction, posting: &Posting) -> bool {
+    let contains = |field: &Option<String>, needle: &str| {
+      field.as_deref().is_some_and(|f| f.to_lowercase().contains(needle))
+    };
+    match self {
+      Term::Account(regex) => regex.is_match(&posting.account),
+      Term::Date { from, to } => {
+        from.as_deref().is_none_or(|from| txn.date.as_str() >= from)
+          && to
+            .as_deref()
+            .is_none_or(|to| txn.date.get(..to.len()).unwrap_or(&txn.date) <= to)
+      }
+      Term::Payee(needle) => contains(&txn.payee, needle),
+      Term::Narration(needle) => contains(&txn.narration, needle),
+      Term::Description(needle) => contains(&txn.payee, needle) || contains(&txn.narration, needle),
+      Term::Status(status) => txn.status == *status,
+      Term::Commodity(commodity) => posting.commodity == *commodity,
+      Term::Amount(orderings, amount) => orderings.contains(&posting.amount.cmp(amount)),
+      Term::Meta { key, value } => [posting.tags.get(key), txn.tags.get(key)]
+        .into_iter()
+        .flatten()
+        .any(|found| value.as_deref().is_none_or(|v| v == found)),
+    }
+  }
+}
+
+impl Query {
+  pub fn matches(&self, txn: &Transaction, posting: &Posting) -> bool {
+    match self {
+      Query::All => true,
+      Query::And(a, b) => a.matches(txn, posting) && b.matches(txn, posting),
+      Query::Or(a, b) => a.matches(txn, posting) || b.matches(txn, posting),
+      Query::Not(q) => !q.matches(txn, posting),
+      Query::Term(term) => term.matches(txn, posting),
+    }
+  }
+}
+
+/// A transaction with at least one matching posting.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct QueryMatch {
+  pub transaction: Transaction,
+  /// Indices into `transaction.postings` of the postings that matched.
+  pub postings: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct QueryResult {
+  /// Matching transactions in date order.
+  pub matches: Vec<QueryMatch>,
+  /// Sum of the matching postings per commodity.
+  pub totals: Vec<CommodityAmount>,
+  /// Display text of `totals` per commodity, using the declared symbol and precision.
+  pub display: BTreeMap<String, String>,
+}
+
+/// Evaluates `query` against every posting in `result`. Fails when a total is out of range.
+pub fn run_query(result: &ParseResult, query: &Query) -> Result<QueryResult, String> {
+  let mut ordered: Vec<&Transaction> = result.transactions.iter().collect();
+  ordered.sort_by(|a, b| a.datetime.cmp(&b.datetime));
+
+  let mut totals: BTreeMap<String, Amount> = BTreeMap::new();
+  let mut matches = Vec::new();
+  for txn in ordered {
+    let postings: Vec<usize> = txn
+      .postings
+      .iter()
+      .enumerate()
+      .filter(|(_, posting)| query.matches(txn, posting))
+      .map(|(index, _)| index)
+      .collect();
+    if postings.is_empty() {
+      continue;
+    }
+    for &index in &postings {
+      let posting = &txn.postings[index];
+      let total = totals.entry(posting.commodity.clone()).or_default();
+      *total = total
+        .checked_add(&posting.amount)
+        .ok_or_else(|| format!("query total out of range in {}", posting.commodity))?;
+    }
+    matches.push(QueryMatch {
+      transaction: txn.clone(),
+      postings,
+    });
+  }
+
+  Ok(QueryResult {
+    matches,
+    display: totals
+      .iter()
+      .map(|(commodity, amount)| (commodity.clone(), result.format_amount(commodity, *amount)))
+      .collect(),
+    totals: totals
+      .into_iter()
+      .map(|(commodity, amount)| CommodityAmount { commodity, amount })
+      .collect(),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ledger_parser::parse_transactions;
+
+  const LEDGER: &str = r#"2026-01-15 * "Binance" "Buy SOL" ; txn:01J2N9R9, src:binance:order:999
+    assets:exchange:binance:sol    2 SOL { 200.00 USD }
+    assets:exchange:binance:usd    -400.00 USD
+
+2026-01-16 ! "Coles Online" "Groceries" ; txn:01J2N9RA
+    expenses:groceries        42.50 AUD ; note:weekly
+    assets:CBA:smartaccess   -42.50 AUD
+
+2026-02-02 * "Cafe" "Lunch with Sam" ; txn:01J2N9RB
+    expenses:dining           20.00 AUD
+    assets:CBA:smartaccess   -20.00 AUD
+"#;
+
+  fn matched(text: &str) -> Vec<String> {
+    let result = parse_transactions(LEDGER);
+    let query = parse_query(text).unwrap();
+    run_query(&result, &query)
+      .unwrap()
+      .matches
+      .iter()
+      .flat_map(|m| m.postings.iter().map(|&i| m.transaction.postings[i].account.clone()))
+      .collect()
+  }
+
+  #[test]
+  fn filters_postings_with_combinators() {
+    assert_eq!(matched("expenses").len(), 2);
+    assert_eq!(matched("assets:cba").len(), 2);
+    assert_eq!(matched("acct:^assets:cba date:2026-01"), ["assets:CBA:smartaccess"]);
+    assert_eq!(matched("date:2026-01-16.. cur:AUD amt:<0"), ["assets:CBA:smartaccess"; 2]);
+    assert_eq!(matched(r#"payee:"coles online" and not meta:note"#), ["assets:CBA:smartaccess"]);
+    assert_eq!(matched("meta:src:binance:order:999 amt:>=2"), ["assets:exchange:binance:sol"]);
+    assert_eq!(
+      matched("(status:! or desc:sam) expenses"),
+      ["expenses:groceries", "expenses:dining"]
+    );
+    assert_eq!(matched("").len(), 6);
+
+    let result = parse_transactions(LEDGER);
+    let totals = run_query(&result, &parse_query("acct:smartaccess").unwrap()).unwrap().totals;
+    assert_eq!(totals[0].amount.to_string(), "-62.50");
+  }
+
+  #[test]
+  fn rejects_malformed_queries() {
+    let error = |text: &str| parse_query(text).unwrap_err();
+    assert_eq!(error("(expenses"), "unbalanced parentheses in query");
+    assert_eq!(error("expenses)"), "unbalanced parentheses in query");
+    assert_eq!(error("amt:>lots"), "invalid amount in query: >lots");
+    assert_eq!(error("expenses or"), "query ended early: expected a term");
+    assert!(error("date:Jan").starts_with("invalid date in query: Jan"));
+    assert!(error(r#"acct:"(""#).starts_with("invalid account regex ("));
+  }
+}
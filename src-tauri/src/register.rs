@@ -0,0 +1,202 @@
+use crate::amount::Amount;
+use crate::ledger_parser::{CommodityAmount, ParseResult};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Which postings a register lists.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct RegisterQuery {
+  pub account: String,
+  /// Also match postings to sub-accounts of `account` (`assets:exchange` matches
+  /// `assets:exchange:binance`).
+  pub include_subaccounts: bool,
+  /// First date listed (`YYYY-MM-DD`, inclusive). Earlier postings still count towards the
+  /// running balance.
+  pub from: Option<String>,
+  /// Last date listed (`YYYY-MM-DD`, inclusive).
+  pub to: Option<String>,
+}
+
+impl RegisterQuery {
+  pub fn matches(&self, account: &str) -> bool {
+    account == self.account
+      || (self.include_subaccounts
+        && account
+          .strip_prefix(self.account.as_str())
+          .is_some_and(|rest| rest.starts_with(':')))
+  }
+}
+
+/// One matching posting and the running balance after it.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct RegisterRow {
+  pub date: String,
+  pub datetime: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub file: Option<String>,
+  /// 1-based line number of the posting.
+  pub line: usize,
+  pub txn_id: Option<String>,
+  pub payee: Option<String>,
+  pub narration: Option<String>,
+  /// Raw text of the transaction's header meta comment.
+  pub meta: Option<String>,
+  /// Account of the posting; differs from the queried account for sub-account postings.
+  pub account: String,
+  /// Accounts of the transaction's postings that the query doesn't match, in posting order.
+  pub counter_accounts: Vec<String>,
+  /// Signed posting amount: positive flows into the account, negative out of it.
+  pub amount: Amount,
+  pub commodity: String,
+  /// Running per-commodity balance of the matched accounts after this posting, including
+  /// openings and postings dated before `from`.
+  pub balance: Vec<CommodityAmount>,
+}
+
+/// Adds `amount` to the running balance in `commodity`.
+fn add(
+  running: &mut BTreeMap<String, Amount>,
+  commodity: &str,
+  amount: Amount,
+) -> Result<(), String> {
+  let balance = running.entry(commodity.to_string()).or_default();
+  *balance = balance
+    .checked_add(&amount)
+    .ok_or_else(|| format!("running balance out of range in {commodity}"))?;
+  Ok(())
+}
+
+/// Lists the postings matching `query` in date order with running balances. Fails when a
+/// running balance is out of range.
+pub fn register(result: &ParseResult, query: &RegisterQuery) -> Result<Vec<RegisterRow>, String> {
+  let mut running: BTreeMap<String, Amount> = BTreeMap::new();
+  let mut dated = Vec::new();
+  for decl in result.accounts.iter().filter(|d| query.matches(&d.account)) {
+    for opening in &decl.openings {
+      match opening.date.as_deref() {
+        Some(date) => dated.push((date, opening)),
+        None => add(&mut running, &opening.commodity, opening.amount)?,
+      }
+    }
+  }
+  dated.sort_by_key(|(date, _)| *date);
+  let mut dated = dated.into_iter().peekable();
+
+  let mut ordered: Vec<_> = result.transactions.iter().collect();
+  ordered.sort_by(|a, b| a.datetime.cmp(&b.datetime));
+
+  let mut rows = Vec::new();
+  for txn in ordered {
+    if query.to.as_deref().is_some_and(|to| txn.date.as_str() > to) {
+      break;
+    }
+    // A dated opening applies from the start of its day.
+    while let Some((_, opening)) = dated.next_if(|(date, _)| *date <= txn.date.as_str()) {
+      add(&mut running, &opening.commodity, opening.amount)?;
+    }
+
+    let listed = query.from.as_deref().is_none_or(|from| txn.date.as_str() >= from);
+    let mut counter_accounts: Vec<String> = Vec::new();
+    for posting in txn.postings.iter().filter(|p| !query.matches(&p.account)) {
+      if !counter_accounts.contains(&posting.account) {
+        counter_accounts.push(posting.account.clone());
+      }
+    }
+
+    for posting in txn.postings.iter().filter(|p| query.matches(&p.account)) {
+      add(&mut running, &posting.commodity, posting.amount)?;
+      if !listed {
+        continue;
+      }
+      rows.push(RegisterRow {
+        date: txn.date.clone(),
+        datetime: txn.datetime.clone(),
+        file: txn.file.clone(),
+        line: posting.line,
+        txn_id: txn.txn_id().map(str::to_string),
+        payee: txn.payee.clone(),
+        narration: txn.narration.clone(),
+        meta: txn.meta.clone(),
+        account: posting.account.clone(),
+        counter_accounts: counter_accounts.clone(),
+        amount: posting.amount,
+        commodity: posting.commodity.clone(),
+        balance: running
+          .iter()
+          .map(|(commodity, amount)| CommodityAmount {
+            commodity: commodity.clone(),
+            amount: *amount,
+          })
+          .collect(),
+      });
+    }
+  }
+  Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ledger_parser::parse_transactions;
+
+  #[test]
+  fn lists_postings_with_running_balances() {
+    let result = parse_transactions(
+      r#"account assets:exchange:binance
+    opening 500.00 USD
+    opening 2026-01-20 1 SOL
+
+2026-01-16 * "Binance" "Buy SOL" ; txn:01J2N9RA
+    assets:exchange:binance:sol    2 SOL { 200.00 USD }
+    assets:exchange:binance       -400.00 USD
+
+2026-01-15 * "Binance" "Deposit" ; txn:01J2N9R9
+    assets:exchange:binance        100.00 USD
+    assets:CBA:smartaccess        -100.00 USD
+
+2026-01-20 * "Binance" "Fee" ; txn:01J2N9RB
+    expenses:fees                  1.00 USD
+    assets:exchange:binance       -1.00 USD
+"#,
+    );
+    assert!(result.ok, "{:?}", result.diagnostics);
+
+    let query = RegisterQuery {
+      account: "assets:exchange:binance".to_string(),
+      ..RegisterQuery::default()
+    };
+    let rows = register(&result, &query).unwrap();
+    let summary: Vec<(&str, String, String)> = rows
+      .iter()
+      .map(|r| {
+        let balance: Vec<String> =
+          r.balance.iter().map(|b| format!("{} {}", b.amount, b.commodity)).collect();
+        (r.date.as_str(), r.amount.to_string(), balance.join(", "))
+      })
+      .collect();
+    assert_eq!(
+      summary,
+      [
+        ("2026-01-15", "100.00".to_string(), "600.00 USD".to_string()),
+        ("2026-01-16", "-400.00".to_string(), "200.00 USD".to_string()),
+        ("2026-01-20", "-1.00".to_string(), "1 SOL, 199.00 USD".to_string()),
+      ]
+    );
+    assert_eq!(rows[0].counter_accounts, ["assets:CBA:smartaccess"]);
+    assert_eq!(rows[0].txn_id.as_deref(), Some("01J2N9R9"));
+
+    let query = RegisterQuery {
+      account: "assets:exchange".to_string(),
+      include_subaccounts: true,
+      from: Some("2026-01-16".to_string()),
+      to: Some("2026-01-16".to_string()),
+    };
+    let rows = register(&result, &query).unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].account, "assets:exchange:binance:sol");
+    assert!(rows[0].counter_accounts.is_empty());
+    let balance: Vec<String> = rows[1].balance.iter().map(|b| b.amount.to_string()).collect();
+    assert_eq!(balance, ["2", "200.00"]);
+  }
+}
@@ -0,0 +1,284 @@
+use crate::accounts::AccountType;
+use crate::amount::Amount;
+use crate::ledger_parser::{CommodityAmount, ParseResult};
+use crate::valuation::balances_at;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+type Totals = BTreeMap<String, Amount>;
+
+/// One row of a report: an account (or the group of accounts under it at the report depth).
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ReportLine {
+  pub account: String,
+  /// Per-commodity amounts with the conventional sign of the section's account type.
+  pub amounts: Vec<CommodityAmount>,
+  /// Display text per commodity, using the declared symbol and precision.
+  pub display: BTreeMap<String, String>,
+}
+
+/// The accounts of one account type and their per-commodity total.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ReportSection {
+  pub account_type: AccountType,
+  pub lines: Vec<ReportLine>,
+  pub total: ReportLine,
+}
+
+/// Income and expenses over a date range.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct IncomeStatement {
+  pub from: Option<String>,
+  pub to: Option<String>,
+  pub income: ReportSection,
+  pub expenses: ReportSection,
+  /// Income minus expenses; negative for a loss.
+  pub net_income: ReportLine,
+}
+
+/// Assets, liabilities and equity as of a date.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct BalanceSheet {
+  pub date: String,
+  pub assets: ReportSection,
+  pub liabilities: ReportSection,
+  pub equity: ReportSection,
+  /// Net income of every income and expense posting up to `date`, not yet closed to an equity
+  /// account.
+  pub retained_earnings: ReportLine,
+  /// Liabilities plus equity plus retained earnings.
+  pub liabilities_and_equity: ReportLine,
+}
+
+/// `account` cut to its first `depth` segments.
+pub(crate) fn group_account(account: &str, depth: Option<usize>) -> &str {
+  match depth.and_then(|depth| account.match_indices(':').nth(depth.max(1) - 1)) {
+    Some((pos, _)) => &account[..pos],
+    None => account,
+  }
+}
+
+fn line(result: &ParseResult, account: &str, totals: &Totals) -> ReportLine {
+  let nonzero = totals.iter().filter(|(_, amount)| !amount.is_zero());
+  let amounts: Vec<CommodityAmount> = nonzero
+    .map(|(commodity, amount)| CommodityAmount {
+      commodity: commodity.clone(),
+      amount: result.commodity(commodity).map_or(*amount, |d| d.round(*amount)),
+    })
+    .collect();
+  let display = amounts
+    .iter()
+    .map(|a| (a.commodity.clone(), result.format_amount(&a.commodity, a.amount)))
+    .collect();
+  ReportLine {
+    account: account.to_string(),
+    amounts,
+    display,
+  }
+}
+
+/// Adds (or with `negate`, subtracts) `amount` to the total in `commodity`.
+fn add_amount(
+  into: &mut Totals,
+  commodity: &str,
+  amount: &Amount,
+  negate: bool,
+) -> Result<(), String> {
+  let total = into.entry(commodity.to_string()).or_default();
+  let sum = if negate { total.checked_sub(amount) } else { total.checked_add(amount) };
+  *total = sum.ok_or_else(|| format!("report total out of range in {commodity}"))?;
+  Ok(())
+}
+
+fn add(into: &mut Totals, from: &Totals, negate: bool) -> Result<(), String> {
+  for (commodity, amount) in from {
+    add_amount(into, commodity, amount, negate)?;
+  }
+  Ok(())
+}
+
+fn section(
+  result: &ParseResult,
+  account_type: AccountType,
+  balances: &BTreeMap<String, Totals>,
+  depth: Option<usize>,
+) -> Result<ReportSection, String> {
+  let mut grouped: BTreeMap<&str, Totals> = BTreeMap::new();
+  let mut total = Totals::new();
+  let typed = balances
+    .iter()
+    .filter(|(account, _)| result.account_types.get(account.as_str()) == Some(&account_type));
+  for (account, by_commodity) in typed {
+    let group = grouped.entry(group_account(account, depth)).or_default();
+    add(group, by_commodity, !account_type.is_debit_normal())?;
+    add(&mut total, by_commodity, !account_type.is_debit_normal())?;
+  }
+  Ok(ReportSection {
+    account_type,
+    lines: grouped
+      .iter()
+      .map(|(account, totals)| line(result, account, totals))
+      .filter(|l| !l.amounts.is_empty())
+      .collect(),
+    total: line(result, account_type.as_str(), &total),
+  })
+}
+
+/// Net income (income minus expenses, conventional sign) of `balances`.
+fn net_income(
+  result: &ParseResult,
+  balances: &BTreeMap<String, Totals>,
+) -> Result<Totals, String> {
+  let mut net = Totals::new();
+  for (account, by_commodity) in balances {
+    let account_type = result.account_types.get(account.as_str());
+    if matches!(account_type, Some(AccountType::Income | AccountType::Expense)) {
+      add(&mut net, by_commodity, true)?;
+    }
+  }
+  Ok(net)
+}
+
+/// Income and expenses posted between `from` and `to` (inclusive dates, either open-ended),
+/// grouped by account path cut to `depth` segments. Fails when a total is out of range.
+pub fn income_statement(
+  result: &ParseResult,
+  from: Option<&str>,
+  to: Option<&str>,
+  depth: Option<usize>,
+) -> Result<IncomeStatement, String> {
+  let mut flows: BTreeMap<String, Totals> = BTreeMap::new();
+  let in_range = |date: &str| from.is_none_or(|f| date >= f) && to.is_none_or(|t| date <= t);
+  for txn in result.transactions.iter().filter(|t| in_range(&t.date)) {
+    for posting in &txn.postings {
+      let totals = flows.entry(posting.account.clone()).or_default();
+      add_amount(totals, &posting.commodity, &posting.amount, false)?;
+    }
+  }
+
+  Ok(IncomeStatement {
+    from: from.map(str::to_string),
+    to: to.map(str::to_string),
+    income: section(result, AccountType::Income, &flows, depth)?,
+    expenses: section(result, AccountType::Expense, &flows, depth)?,
+    net_income: line(result, "net income", &net_income(result, &flows)?),
+  })
+}
+
+/// Balances of asset, liability and equity accounts at `date` (openings included), grouped by
+/// account path cut to `depth` segments. Fails when a balance or total is out of range.
+pub fn balance_sheet(
+  result: &ParseResult,
+  date: &str,
+  depth: Option<usize>,
+) -> Result<BalanceSheet, String> {
+  let balances = balances_at(result, date)?;
+  let assets = section(result, AccountType::Asset, &balances, depth)?;
+  let liabilities = section(result, AccountType::Liability, &balances, depth)?;
+  let equity = section(result, AccountType::Equity, &balances, depth)?;
+  let retained = net_income(result, &balances)?;
+
+  let mut combined = retained.clone();
+  for total in [&liabilities.total, &equity.total] {
+    for amount in &total.amounts {
+      add_amount(&mut combined, &amount.commodity, &amount.amount, false)?;
+    }
+  }
+
+  Ok(BalanceSheet {
+    date: date.to_string(),
+    assets,
+    liabilities,
+    equity,
+    retained_earnings: line(result, "retained earnings", &retained),
+    liabilities_and_equity: line(result, "liabilities and equity", &combined),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ledger_parser::parse_transactions;
+
+  const LEDGER: &str = r#"commodity AUD
+    symbol "$"
+    precision 2
+
+account assets:CBA:smartaccess AUD
+    opening 1000.00
+account equity:opening AUD
+    opening -1000.00
+
+2026-01-10 * "Employer" "Salary" ; txn:01J2N9R9
+    assets:CBA:smartaccess    3000.00 AUD
+    income:salary            -3000.00 AUD
+
+2026-01-16 * "Coles" "Groceries" ; txn:01J2N9RA
+    expenses:food:groceries    142.50 AUD
+    liabilities:amex          -142.50 AUD
+
+2026-02-02 * "Cafe" "Lunch" ; txn:01J2N9RB
+    expenses:food:dining        20.00 AUD
+    expenses:transport          10.00 AUD
+    assets:CBA:smartaccess     -30.00 AUD
+"#;
+
+  fn amounts(line: &ReportLine) -> Vec<String> {
+    line.amounts.iter().map(|a| format!("{} {}", a.amount, a.commodity)).collect()
+  }
+
+  #[test]
+  fn builds_income_statement_grouped_by_depth() {
+    let result = parse_transactions(LEDGER);
+    assert!(result.ok, "{:?}", result.diagnostics);
+
+    let january = income_statement(&result, Some("2026-01-01"), Some("2026-01-31"), None).unwrap();
+    assert_eq!(amounts(&january.income.total), ["3000.00 AUD"]);
+    assert_eq!(january.expenses.lines.len(), 1);
+    assert_eq!(january.net_income.display["AUD"], "$2857.50");
+
+    let all = income_statement(&result, None, None, Some(2)).unwrap();
+    let lines: Vec<(&str, Vec<String>)> = all
+      .expenses
+      .lines
+      .iter()
+      .map(|l| (l.account.as_str(), amounts(l)))
+      .collect();
+    assert_eq!(
+      lines,
+      [
+        ("expenses:food", vec!["162.50 AUD".to_string()]),
+        ("expenses:transport", vec!["10.00 AUD".to_string()]),
+      ]
+    );
+    assert_eq!(amounts(&all.net_income), ["2827.50 AUD"]);
+  }
+
+  #[test]
+  fn builds_balance_sheet_with_retained_earnings() {
+    let result = parse_transactions(LEDGER);
+    let sheet = balance_sheet(&result, "2026-01-31", Some(1)).unwrap();
+
+    assert_eq!(amounts(&sheet.assets.total), ["4000.00 AUD"]);
+    assert_eq!(sheet.assets.lines[0].account, "assets");
+    assert_eq!(amounts(&sheet.liabilities.total), ["142.50 AUD"]);
+    assert_eq!(amounts(&sheet.equity.total), ["1000.00 AUD"]);
+    assert_eq!(amounts(&sheet.retained_earnings), ["2857.50 AUD"]);
+    assert_eq!(amounts(&sheet.liabilities_and_equity), ["4000.00 AUD"]);
+  }
+
+  #[test]
+  fn reports_totals_out_of_range() {
+    let result = parse_transactions(
+      r#"account assets:cash AUD
+    opening 100000000000000000000000000000000000000
+account assets:bank AUD
+    opening 100000000000000000000000000000000000000
+"#,
+    );
+    assert!(result.ok, "{:?}", result.diagnostics);
+
+    let error = balance_sheet(&result, "2026-01-31", None).unwrap_err();
+    assert_eq!(error, "report total out of range in AUD");
+  }
+}
@@ -0,0 +1,190 @@
+use crate::accounts::AccountType;
+use crate::amount::Amount;
+use crate::ledger_parser::ParseResult;
+use crate::prices::{known_at, trim_rate, PriceDb};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// One commodity held by an account, with its value in the report currency.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ValuedAmount {
+  pub commodity: String,
+  pub amount: Amount,
+  /// Value of one unit in the report currency; `None` when no price was found.
+  pub rate: Option<Amount>,
+  pub value: Option<Amount>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ValuedBalance {
+  pub account: String,
+  pub holdings: Vec<ValuedAmount>,
+  /// Sum of the holdings that could be valued.
+  pub value: Amount,
+  /// Every holding had a price.
+  pub complete: bool,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Valuation {
+  pub currency: String,
+  pub date: String,
+  pub balances: Vec<ValuedBalance>,
+  /// Net worth: the summed value of the asset and liability accounts. Income, expense and
+  /// equity accounts are left out, since in a balanced ledger they only offset it.
+  pub total: Amount,
+  /// Commodities with a non-zero balance but no price into `currency` at `date`.
+  pub missing_prices: Vec<String>,
+}
+
+/// Per-account, per-commodity balances as of `date`: openings plus every transaction dated on
+/// or before it. Fails when a balance is out of range.
+pub fn balances_at(
+  result: &ParseResult,
+  date: &str,
+) -> Result<BTreeMap<String, BTreeMap<String, Amount>>, String> {
+  let mut balances: BTreeMap<String, BTreeMap<String, Amount>> = result
+    .accounts
+    .iter()
+    .map(|decl| (decl.account.clone(), BTreeMap::new()))
+    .collect();
+  let mut add = |account: &str, commodity: &str, amount: &Amount| {
+    let balance = balances
+      .entry(account.to_string())
+      .or_default()
+      .entry(commodity.to_string())
+      .or_default();
+    *balance = balance
+      .checked_add(amount)
+      .ok_or_else(|| format!("balance of {account} out of range in {commodity} on {date}"))?;
+    Ok::<_, String>(())
+  };
+  for decl in &result.accounts {
+    let openings = decl.openings.iter().filter(|o| o.date.as_deref().is_none_or(|d| d <= date));
+    for opening in openings {
+      add(&decl.account, &opening.commodity, &opening.amount)?;
+    }
+  }
+  for txn in result.transactions.iter().filter(|t| known_at(&t.datetime, date)) {
+    for posting in &txn.postings {
+      add(&posting.account, &posting.commodity, &posting.amount)?;
+    }
+  }
+  Ok(balances)
+}
+
+/// Values every account's balance at `date` in `currency`, using `price` directives and the
+/// cost/price annotations of the parsed ledger. Fails when a balance or value is out of range.
+pub fn value_balances(
+  result: &ParseResult,
+  currency: &str,
+  date: &str,
+) -> Result<Valuation, String> {
+  let prices = PriceDb::from_parse(result);
+  let decl = result.commodities.iter().find(|c| c.commodity == currency);
+  let round = |value: Amount| decl.map_or_else(|| trim_rate(value), |d| d.round(value));
+
+  let mut missing: BTreeSet<String> = BTreeSet::new();
+  let out_of_range = |account: &str| format!("value of {account} out of range in {currency}");
+  let mut total = Amount::ZERO;
+  let mut balances: Vec<ValuedBalance> = Vec::new();
+  for (account, by_commodity) in balances_at(result, date)? {
+    let mut holdings: Vec<ValuedAmount> = Vec::new();
+    let mut value = Amount::ZERO;
+    for (commodity, amount) in by_commodity {
+      let rate = if amount.is_zero() {
+        None
+      } else {
+        prices.rate(&commodity, currency, date)?.map(|r| r.rate)
+      };
+      let holding_value = match rate {
+        Some(rate) => {
+          let converted = amount.checked_mul(&rate).ok_or_else(|| out_of_range(&account))?;
+          Some(round(converted))
+        }
+        None if amount.is_zero() => Some(Amount::ZERO),
+        None => {
+          missing.insert(commodity.clone());
+          None
+        }
+      };
+      if let Some(holding_value) = holding_value {
+        value = value.checked_add(&holding_value).ok_or_else(|| out_of_range(&account))?;
+      }
+      holdings.push(ValuedAmount {
+        commodity,
+        amount,
+        rate,
+        value: holding_value,
+      });
+    }
+    let account_type = result.account_types.get(&account);
+    if matches!(account_type, Some(AccountType::Asset | AccountType::Liability)) {
+      total = total.checked_add(&value).ok_or_else(|| out_of_range("net worth"))?;
+    }
+    balances.push(ValuedBalance {
+      account,
+      complete: holdings.iter().all(|h| h.value.is_some()),
+      holdings,
+      value,
+    });
+  }
+
+  Ok(Valuation {
+    currency: currency.to_string(),
+    date: date.to_string(),
+    balances,
+    total,
+    missing_prices: missing.into_iter().collect(),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ledger_parser::parse_transactions;
+
+  #[test]
+  fn values_balances_in_report_currency() {
+    let result = parse_transactions(
+      r#"commodity AUD
+    precision 2
+
+price 2026-01-10 USD 1.60 AUD
+
+account assets:wise:aud AUD
+    opening 100.00
+
+2026-01-15 * "Binance" "Buy SOL" ; txn:01J2N9R9
+    assets:exchange:binance:sol    2 SOL { 200.00 USD }
+    assets:exchange:binance:usd    -400.00 USD
+
+2026-01-16 * "Airdrop" "JUP" ; txn:01J2N9RA
+    assets:exchange:binance:jup    50 JUP
+    income:airdrops               -50 JUP
+
+2026-01-20 * "Coles" "Groceries" ; txn:01J2N9RB
+    expenses:food:groceries        20.00 AUD
+    liabilities:amex              -20.00 AUD
+
+2026-02-01 * "Binance" "Buy SOL" ; txn:01J2N9RC
+    assets:exchange:binance:sol    1 SOL { 250.00 USD }
+    assets:exchange:binance:usd    -250.00 USD
+"#,
+    );
+    assert!(result.ok, "{:?}", result.diagnostics);
+
+    let valuation = value_balances(&result, "AUD", "2026-01-31").unwrap();
+    let value = |account: &str| {
+      let balance = valuation.balances.iter().find(|b| b.account == account).unwrap();
+      (balance.value.to_string(), balance.complete)
+    };
+    assert_eq!(value("assets:wise:aud"), ("100.00".to_string(), true));
+    assert_eq!(value("assets:exchange:binance:sol"), ("640.00".to_string(), true));
+    assert_eq!(value("assets:exchange:binance:usd"), ("-640.00".to_string(), true));
+    assert_eq!(value("assets:exchange:binance:jup"), ("0".to_string(), false));
+    assert_eq!(valuation.missing_prices, ["JUP"]);
+    assert_eq!(value("expenses:food:groceries"), ("20.00".to_string(), true));
+    assert_eq!(valuation.total.to_string(), "80.00");
+  }
+}
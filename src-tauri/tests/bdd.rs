@@ -1,4 +1,5 @@
 use cucumber::{given, then, when, World as _};
+use squirrel_covid::amount::Amount;
 use squirrel_covid::generated_ledger::rotate_ledger_if_needed;
 use squirrel_covid::generated_store::{add_manual_transaction, import_source_files, load_active_ledger, ManualPostingInput, ManualTransactionInput};
 use squirrel_covid::ledger_parser::{parse_transactions, ParseResult};
@@ -137,7 +138,7 @@ async fn the_balance_for_account_should_be(
     .result
     .as_ref()
     .expect("parse result should be set by the When step");
-  let expected_amount: f64 = amount_text
+  let expected_amount: Amount = amount_text
     .parse()
     .unwrap_or_else(|e| panic!("invalid expected amount {amount_text:?}: {e}"));
   let balance = result
@@ -151,8 +152,9 @@ async fn the_balance_for_account_should_be(
     .find(|t| t.commodity == commodity)
     .unwrap_or_else(|| panic!("missing commodity {commodity:?} for account {account:?}"));
 
-  assert!(
-    (actual.amount - expected_amount).abs() < 1e-9,
+  assert_eq!(
+    actual.amount.to_string(),
+    expected_amount.to_string(),
     "expected {expected_amount} {commodity} for {account}, got {actual:?}",
   );
 }
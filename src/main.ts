@@ -6,14 +6,29 @@ type Diagnostic = {
   line: number;
   column: number;
   message: string;
+  severity?: "error" | "warning";
 };
 
+type AccountType = "asset" | "liability" | "equity" | "income" | "expense";
+
+type PriceAnnotation = {
+  kind: "per_unit" | "total";
+  amount: CommodityAmount;
+};
+
+// Amounts are exact decimal strings (e.g. "-0.005000") as serialized by the backend.
 type Posting = {
   account: string;
-  amount: number;
+  amount: string;
   amount_text?: string;
   commodity: string;
+  price?: PriceAnnotation | null;
+  balance_assertion?: CommodityAmount | null;
+  meta?: string | null;
+  tags?: Record<string, string>;
   remainder?: string | null;
+  inferred?: boolean;
+  fee_for?: number | null;
 };
 
 type Transaction = {
@@ -23,17 +38,59 @@ type Transaction = {
   payee?: string | null;
   narration?: string | null;
   meta?: string | null;
+  tags?: Record<string, string>;
   postings: Posting[];
 };
 
 type CommodityAmount = {
   commodity: string;
-  amount: number;
+  amount: string;
+};
+
+type RegisterRow = {
+  date: string;
+  datetime: string;
+  txn_id?: string | null;
+  payee?: string | null;
+  narration?: string | null;
+  meta?: string | null;
+  account: string;
+  counter_accounts: string[];
+  amount: string;
+  commodity: string;
+  balance: CommodityAmount[];
 };
 
 type AccountBalance = {
   account: string;
   totals: CommodityAmount[];
+  display?: Record<string, string>;
+};
+
+type CommodityDeclaration = {
+  commodity: string;
+  precision?: number | null;
+  symbol?: string | null;
+  name?: string | null;
+  kind?: "fiat" | "crypto" | "stock" | null;
+  tags?: Record<string, string>;
+};
+
+type AccountDeclaration = {
+  account: string;
+  default_commodity?: string | null;
+  open_date?: string | null;
+  close_date?: string | null;
+  openings?: { date?: string | null; commodity: string; amount: string }[];
+  tags?: Record<string, string>;
+};
+
+type PriceDirective = {
+  date: string;
+  datetime: string;
+  commodity: string;
+  price: CommodityAmount;
+  tags?: Record<string, string>;
 };
 
 type ParseResponse = {
@@ -41,12 +98,18 @@ type ParseResponse = {
   diagnostics: Diagnostic[];
   transactions: Transaction[];
   balances: AccountBalance[];
+  closed_balances?: AccountBalance[];
+  accounts?: AccountDeclaration[];
+  commodities?: CommodityDeclaration[];
+  prices?: PriceDirective[];
+  account_types?: Record<string, AccountType>;
 };
 
 type ImportStats = {
   imported: number;
   skipped_duplicates: number;
   archived: number;
+  directives: number;
 };
 
 type ImportResponse = {
@@ -83,13 +146,19 @@ function escapeText(text: string): string {
     .replaceAll("'", "&#039;");
 }
 
-function formatAmount(amount: number, commodity: string): string {
-  const decimals = commodity === "USD" ? 2 : 6;
-  return amount.toFixed(decimals);
+const declaredCommodities = new Map<string, CommodityDeclaration>();
+
+function formatAmount(amount: string | number, commodity: string): string {
+  const decimals = declaredCommodities.get(commodity)?.precision ?? (commodity === "USD" ? 2 : 6);
+  return Number(amount).toFixed(decimals);
 }
 
 function pickDisplayTotal(totals: CommodityAmount[]): CommodityAmount | undefined {
-  return totals.find((t) => t.commodity === "USD") ?? totals[0];
+  return (
+    totals.find((t) => declaredCommodities.get(t.commodity)?.kind === "fiat") ??
+    totals.find((t) => t.commodity === "USD") ??
+    totals[0]
+  );
 }
 
 function nowYYYYMM(): string {
@@ -106,6 +175,10 @@ function nowYYYYMMDD(): string {
 
 function applyParse(state: AppState, response: ParseResponse): void {
   state.parse = response;
+  declaredCommodities.clear();
+  for (const decl of response.commodities ?? []) {
+    declaredCommodities.set(decl.commodity, decl);
+  }
 
   const nextBalances = response.balances ?? [];
   const groupNames = Array.from(new Set(nextBalances.map((b) => b.account.split(":")[0] ?? "other")));
@@ -117,6 +190,21 @@ function applyParse(state: AppState, response: ParseResponse): void {
     state.selectedAccount =
       nextBalances.find((b) => b.account === "assets:cash:usd")?.account ?? nextBalances[0]?.account;
   }
+  state.register = undefined;
+  void loadRegister(state);
+}
+
+async function loadRegister(state: AppState): Promise<void> {
+  const account = state.selectedAccount;
+  if (!account) return;
+
+  try {
+    const rows = await invoke<RegisterRow[]>("generated_register", { query: { account } });
+    if (state.selectedAccount === account) state.register = rows;
+  } catch (err) {
+    state.status = `Error: ${String(err)}`;
+  }
+  render(state);
 }
 
 async function loadGeneratedLedger(state: AppState): Promise<void> {
@@ -149,7 +237,7 @@ async function importSources(state: AppState, paths: string[]): Promise<void> {
     });
     applyParse(state, response.parse);
     const parsedCount = response.parse.transactions.length;
-    state.status = `Parsed (${parsedCount} transactions) — imported ${response.stats.imported}, archived ${response.stats.archived}, skipped ${response.stats.skipped_duplicates}`;
+    state.status = `Parsed (${parsedCount} transactions) — imported ${response.stats.imported}, archived ${response.stats.archived}, skipped ${response.stats.skipped_duplicates}, directives ${response.stats.directives}`;
   } catch (err) {
     state.status = `Error: ${String(err)}`;
   } finally {
@@ -267,25 +355,18 @@ function render(state: AppState): void {
   const selectedTotal = selectedBalance ? pickDisplayTotal(selectedBalance.totals) : undefined;
 
   const allTransactions = state.parse?.transactions ?? [];
-  const visibleTransactions = selectedAccount
-    ? allTransactions.filter((t) => t.postings.some((p) => p.account === selectedAccount))
-    : allTransactions;
+  const registerRows = state.register ?? [];
 
   const searchQuery = state.search.trim().toLowerCase();
-  const filteredTransactions =
+  const filteredRows =
     searchQuery.length === 0
-      ? visibleTransactions
-      : visibleTransactions.filter((t) => {
-          const haystack = `${t.payee ?? ""} ${t.narration ?? ""} ${t.meta ?? ""}`.toLowerCase();
+      ? registerRows
+      : registerRows.filter((r) => {
+          const haystack = `${r.payee ?? ""} ${r.narration ?? ""} ${r.meta ?? ""}`.toLowerCase();
           return haystack.includes(searchQuery);
         });
 
-  const uncategorisedCount = selectedAccount
-    ? filteredTransactions.filter((t) => {
-        const other = t.postings.find((p) => p.account !== selectedAccount);
-        return !other;
-      }).length
-    : 0;
+  const uncategorisedCount = filteredRows.filter((r) => r.counter_accounts.length === 0).length;
 
   const diagnostics = state.parse?.diagnostics ?? [];
   const statusText =
@@ -453,46 +534,36 @@ function render(state: AppState): void {
                   <th>Category</th>
                   <th class="num">Payment</th>
                   <th class="num">Deposit</th>
+                  <th class="num">Balance</th>
                 </tr>
               </thead>
               <tbody>
                 ${
-                  filteredTransactions.length === 0
-                    ? `<tr><td colspan="6" class="empty">No transactions.</td></tr>`
-                    : filteredTransactions
-                        .map((t) => {
-                          const postingAmount = selectedAccount
-                            ? t.postings
-                                .filter((p) => p.account === selectedAccount)
-                                .reduce((sum, p) => sum + p.amount, 0)
-                            : 0;
-
-                          const commodity =
-                            selectedAccount &&
-                            t.postings.find((p) => p.account === selectedAccount)?.commodity
-                              ? t.postings.find((p) => p.account === selectedAccount)!.commodity
-                              : "USD";
-
-                          const other = selectedAccount
-                            ? t.postings.find((p) => p.account !== selectedAccount)
-                            : undefined;
-
+                  filteredRows.length === 0
+                    ? `<tr><td colspan="7" class="empty">No transactions.</td></tr>`
+                    : filteredRows
+                        .map((r) => {
+                          const postingAmount = Number(r.amount);
                           const payment =
-                            postingAmount < 0 ? formatAmount(Math.abs(postingAmount), commodity) : "";
-                          const deposit = postingAmount > 0 ? formatAmount(postingAmount, commodity) : "";
+                            postingAmount < 0 ? formatAmount(Math.abs(postingAmount), r.commodity) : "";
+                          const deposit = postingAmount > 0 ? formatAmount(postingAmount, r.commodity) : "";
+                          const balance = r.balance.find((b) => b.commodity === r.commodity);
 
                           return `
                             <tr data-testid="txn-row">
-                              <td>${escapeText(t.date)}</td>
-                              <td data-testid="txn-payee">${escapeText(t.payee ?? "")}</td>
+                              <td>${escapeText(r.date)}</td>
+                              <td data-testid="txn-payee">${escapeText(r.payee ?? "")}</td>
                               <td>
                                 <div class="notes__main" data-testid="txn-notes">${escapeText(
-                                  t.narration ?? "",
+                                  r.narration ?? "",
                                 )}</div>
                               </td>
-                              <td class="category">${escapeText(other?.account ?? "—")}</td>
+                              <td class="category">${escapeText(r.counter_accounts[0] ?? "—")}</td>
                               <td class="num">${escapeText(payment)}</td>
                               <td class="num" data-testid="txn-deposit">${escapeText(deposit)}</td>
+                              <td class="num">${escapeText(
+                                balance ? formatAmount(balance.amount, r.commodity) : "",
+                              )}</td>
                             </tr>
                           `;
                         })
@@ -511,7 +582,9 @@ function render(state: AppState): void {
                     ${diagnostics
                       .map(
                         (d) =>
-                          `<li><code>line ${d.line}, col ${d.column}</code> — ${escapeText(d.message)}</li>`,
+                          `<li><code>line ${d.line}, col ${d.column}</code> — ${
+                            d.severity === "warning" ? "warning: " : ""
+                          }${escapeText(d.message)}</li>`,
                       )
                       .join("")}
                   </ul>
@@ -655,7 +728,9 @@ function render(state: AppState): void {
     el.addEventListener("click", () => {
       const account = el.getAttribute("data-account") ?? "";
       state.selectedAccount = account;
+      state.register = undefined;
       render(state);
+      void loadRegister(state);
     });
   });
 
@@ -753,6 +828,7 @@ type AppState = {
   busy: boolean;
   parse?: ParseResponse;
   selectedAccount?: string;
+  register?: RegisterRow[];
   search: string;
   status?: string;
   expandedGroups: Record<string, boolean>;